@@ -0,0 +1,229 @@
+// Unpacking of zip, gzip and tar deliveries, bounded against deep nesting and decompression bombs
+use std::io::{Cursor, Read};
+use flate2::read::MultiGzDecoder;
+use crate::error::AppError;
+
+// Archives inside archives are followed this many levels deep
+pub const MAX_DEPTH: usize = 8;
+// Everything one delivery may unpack to, however its archives are nested
+pub const MAX_UNPACKED_BYTES: u64 = 1024 * 1024 * 1024;
+pub const MAX_ENTRIES: usize = 100_000;
+
+pub const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+const ZIP_EMPTY_MAGIC: &[u8] = b"PK\x05\x06";
+const GZIP_MAGIC: &[u8] = &[0x1F, 0x8B];
+const TAR_MAGIC_OFFSET: usize = 257;
+const TAR_MAGIC: &[u8] = b"ustar";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Zip,
+    Gzip,
+    Tar,
+}
+
+impl ArchiveFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ArchiveFormat::Zip => "zip",
+            ArchiveFormat::Gzip => "gzip",
+            ArchiveFormat::Tar => "tar",
+        }
+    }
+}
+
+pub struct ArchiveEntry {
+    // Path inside the archive, as the archive names it
+    pub path: String,
+    pub content: Vec<u8>,
+}
+
+// What is left of the limits for one delivery; shared by every archive unpacked from it
+pub struct UnpackBudget {
+    bytes_left: u64,
+    entries_left: usize,
+}
+
+impl Default for UnpackBudget {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl UnpackBudget {
+    pub fn new() -> Self {
+        Self {
+            bytes_left: MAX_UNPACKED_BYTES,
+            entries_left: MAX_ENTRIES,
+        }
+    }
+
+    // Sizes in archive headers are not trusted; reading stops one byte past the limit
+    fn read_entry(&mut self, path: &str, reader: impl Read) -> Result<Vec<u8>, AppError> {
+        if self.entries_left == 0 {
+            return Err(AppError::Archive(format!("more than {} files in one delivery", MAX_ENTRIES)));
+        }
+        self.entries_left -= 1;
+
+        let mut content = Vec::new();
+        reader.take(self.bytes_left + 1)
+            .read_to_end(&mut content)
+            .map_err(|e| AppError::Archive(format!("{}: {}", path, e)))?;
+
+        if content.len() as u64 > self.bytes_left {
+            return Err(AppError::Archive(format!(
+                "{}: delivery unpacks to more than {} MiB", path, MAX_UNPACKED_BYTES / (1024 * 1024)
+            )));
+        }
+        self.bytes_left -= content.len() as u64;
+
+        Ok(content)
+    }
+}
+
+// Archive format from the leading bytes; zip-based workbooks are told apart by the caller
+pub fn detect_archive(content: &[u8]) -> Option<ArchiveFormat> {
+    if content.starts_with(ZIP_MAGIC) || content.starts_with(ZIP_EMPTY_MAGIC) {
+        Some(ArchiveFormat::Zip)
+    } else if content.starts_with(GZIP_MAGIC) {
+        Some(ArchiveFormat::Gzip)
+    } else if content.get(TAR_MAGIC_OFFSET..TAR_MAGIC_OFFSET + TAR_MAGIC.len()) == Some(TAR_MAGIC) {
+        Some(ArchiveFormat::Tar)
+    } else {
+        None
+    }
+}
+
+// Regular files of the archive in stored order; directories and links are skipped.
+// A gzip stream is one entry named after the archive without its ".gz".
+pub fn unpack(name: &str, content: &[u8], format: ArchiveFormat, budget: &mut UnpackBudget) -> Result<Vec<ArchiveEntry>, AppError> {
+    let archive_error = |e: &dyn std::fmt::Display| AppError::Archive(format!("{}: {}", name, e));
+    let mut entries = Vec::new();
+
+    match format {
+        ArchiveFormat::Gzip => {
+            let path = gunzipped_name(name);
+            let content = budget.read_entry(&path, MultiGzDecoder::new(content))?;
+            entries.push(ArchiveEntry { path, content });
+        }
+        ArchiveFormat::Zip => {
+            let mut archive = zip::ZipArchive::new(Cursor::new(content)).map_err(|e| archive_error(&e))?;
+            for i in 0..archive.len() {
+                let entry = archive.by_index(i).map_err(|e| archive_error(&e))?;
+                if !entry.is_file() {
+                    continue;
+                }
+                let path = entry.name().to_string();
+                let content = budget.read_entry(&path, entry)?;
+                entries.push(ArchiveEntry { path, content });
+            }
+        }
+        ArchiveFormat::Tar => {
+            let mut archive = tar::Archive::new(content);
+            for entry in archive.entries().map_err(|e| archive_error(&e))? {
+                let entry = entry.map_err(|e| archive_error(&e))?;
+                if !entry.header().entry_type().is_file() {
+                    continue;
+                }
+                let path = entry.path().map_err(|e| archive_error(&e))?.to_string_lossy().to_string();
+                let content = budget.read_entry(&path, entry)?;
+                entries.push(ArchiveEntry { path, content });
+            }
+        }
+    }
+
+    Ok(entries)
+}
+
+// "calls.csv.gz" holds "calls.csv" and "returns.tgz" holds "returns.tar"
+fn gunzipped_name(name: &str) -> String {
+    let lower = name.to_lowercase();
+    if lower.ends_with(".tgz") {
+        format!("{}.tar", &name[..name.len() - 4])
+    } else if lower.ends_with(".gz") {
+        name[..name.len() - 3].to_string()
+    } else {
+        name.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+
+    fn zip(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options = zip::write::SimpleFileOptions::default();
+        writer.add_directory("returns/", options).unwrap();
+        for (name, content) in files {
+            writer.start_file(*name, options).unwrap();
+            writer.write_all(content).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    fn gzip(content: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(content).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn tar(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for (name, content) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, name, *content).unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    fn paths(entries: &[ArchiveEntry]) -> Vec<&str> {
+        entries.iter().map(|entry| entry.path.as_str()).collect()
+    }
+
+    #[test]
+    fn detects_archives_from_their_leading_bytes() {
+        assert_eq!(detect_archive(&zip(&[])), Some(ArchiveFormat::Zip));
+        assert_eq!(detect_archive(&gzip(b"x")), Some(ArchiveFormat::Gzip));
+        assert_eq!(detect_archive(&tar(&[("a.txt", b"x")])), Some(ArchiveFormat::Tar));
+        assert_eq!(detect_archive(b"Calling Number,Called Number"), None);
+    }
+
+    #[test]
+    fn unpacks_the_regular_files() {
+        let mut budget = UnpackBudget::new();
+        let entries = unpack("delivery.zip", &zip(&[("returns/calls.csv", b"a,b"), ("notes.txt", b"hi")]), ArchiveFormat::Zip, &mut budget).unwrap();
+        assert_eq!(paths(&entries), ["returns/calls.csv", "notes.txt"]);
+        assert_eq!(entries[0].content, b"a,b");
+
+        let entries = unpack("logs.tgz", &gzip(&tar(&[("logs/a.txt", b"one")])), ArchiveFormat::Gzip, &mut budget).unwrap();
+        assert_eq!(paths(&entries), ["logs.tar"]);
+        let entries = unpack("logs.tar", &entries[0].content, ArchiveFormat::Tar, &mut budget).unwrap();
+        assert_eq!(paths(&entries), ["logs/a.txt"]);
+        assert_eq!(entries[0].content, b"one");
+
+        assert_eq!(gunzipped_name("Calls.CSV.GZ"), "Calls.CSV");
+    }
+
+    #[test]
+    fn stops_at_the_delivery_limits() {
+        let mut budget = UnpackBudget { bytes_left: 4, entries_left: 10 };
+        assert!(unpack("big.gz", &gzip(b"12345"), ArchiveFormat::Gzip, &mut budget).is_err());
+
+        let mut budget = UnpackBudget { bytes_left: 100, entries_left: 1 };
+        let archive = zip(&[("a.txt", b"a"), ("b.txt", b"b")]);
+        assert!(unpack("two.zip", &archive, ArchiveFormat::Zip, &mut budget).is_err());
+    }
+
+    #[test]
+    fn corrupt_archives_are_errors() {
+        let mut budget = UnpackBudget::new();
+        assert!(unpack("bad.zip", b"PK\x03\x04garbage", ArchiveFormat::Zip, &mut budget).is_err());
+    }
+}
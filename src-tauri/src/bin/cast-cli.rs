@@ -0,0 +1,532 @@
+// Headless front end for batch ingestion and cross-referencing of carrier returns
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use clap::{Parser, Subcommand};
+use cast_database_analyzer::{
+    cast_json,
+    database::{self, Database},
+    encoding::TextEncoding,
+    error::AppError,
+    export,
+    extractors::Extractors,
+    file_processor::{ContentStorage, FileProcessor},
+    job::IngestJob,
+    models::{EventFilter, Source, TowerSite},
+    phone,
+};
+
+#[derive(Parser)]
+#[command(name = "cast-cli", version, about = "CAST Database Analyzer - command-line ingestion and analysis")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Process files or directories of returns into a case database
+    Ingest {
+        /// Case database to create, or to add to if it already exists
+        #[arg(long)]
+        db: PathBuf,
+
+        /// Source assignment as SOURCE=PATH, where SOURCE is a source id or name and PATH
+        /// a file or directory (repeatable); unknown names create a new source
+        #[arg(long = "source", value_name = "SOURCE=PATH", required = true, value_parser = parse_source)]
+        sources: Vec<(String, PathBuf)>,
+
+        /// Region for phone numbers written without a country code (ISO 3166 alpha-2)
+        #[arg(long, default_value = phone::DEFAULT_REGION)]
+        region: String,
+
+        /// Character encoding of text files (UTF-8, UTF-16LE, UTF-16BE, windows-1252,
+        /// ISO-8859-1); detected per file when omitted
+        #[arg(long)]
+        encoding: Option<TextEncoding>,
+
+        /// Keep only the path of streamed CSV and text files in the case, not their text
+        #[arg(long)]
+        no_content: bool,
+    },
+
+    /// Convert a legacy CAST SQLite database or a CAST_JSON case into a case database
+    ImportLegacy {
+        /// Legacy database (sources, phone_numbers, email_addresses, ip_addresses tables) or CAST_JSON file
+        #[arg(long)]
+        legacy: PathBuf,
+
+        /// Case database to create
+        #[arg(long)]
+        db: PathBuf,
+
+        /// Region for legacy phone numbers stored without a country code
+        #[arg(long, default_value = phone::DEFAULT_REGION)]
+        region: String,
+    },
+
+    /// Bring a case database written by an older version up to the current schema
+    Upgrade {
+        /// Case database to upgrade in place
+        #[arg(long)]
+        db: PathBuf,
+    },
+
+    /// Write a case as a CAST_JSON v2 document for the desktop app's Load Database
+    ExportJson {
+        /// Case database
+        #[arg(long)]
+        db: PathBuf,
+
+        /// JSON file to write
+        #[arg(long)]
+        output: PathBuf,
+    },
+
+    /// List values found in more than one source, with the names address books give them
+    Crossref {
+        /// Case database to analyze
+        #[arg(long)]
+        db: PathBuf,
+
+        /// Write the results to this CSV file instead of printing them
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+
+    /// List identifiers seen at several tower dumps within their time windows
+    Towers {
+        /// Case database to analyze
+        #[arg(long)]
+        db: PathBuf,
+
+        /// Tower dump as SOURCE or SOURCE@START/END, where SOURCE is a source id or name and
+        /// START/END are RFC 3339 or YYYY-MM-DD (UTC); either bound may be left empty (repeatable)
+        #[arg(long = "site", value_name = "SOURCE[@START/END]", required = true, value_parser = parse_site)]
+        sites: Vec<(String, Option<String>, Option<String>)>,
+
+        /// Report identifiers seen at this many sites or more (default: all of them)
+        #[arg(long)]
+        min_sites: Option<usize>,
+
+        /// Limit to one item type (phone, imsi, imei, ...)
+        #[arg(long = "type")]
+        item_type: Option<String>,
+
+        /// Write every matching record to this CSV file instead of printing a summary
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+
+    /// List the sources in a case database
+    Sources {
+        /// Case database to read
+        #[arg(long)]
+        db: PathBuf,
+    },
+
+    /// List calls, messages and data sessions read from carrier records, chat exports and email
+    Events {
+        /// Case database to search
+        #[arg(long)]
+        db: PathBuf,
+
+        /// Phone number, IMEI or IMSI on either end of the event
+        #[arg(long)]
+        party: Option<String>,
+
+        /// Earliest start time, RFC 3339 or YYYY-MM-DD (UTC)
+        #[arg(long)]
+        from: Option<String>,
+
+        /// Latest start time, RFC 3339 or YYYY-MM-DD (UTC)
+        #[arg(long)]
+        to: Option<String>,
+
+        /// Limit to one source id
+        #[arg(long)]
+        source: Option<i32>,
+
+        /// Region for a party written without a country code
+        #[arg(long, default_value = phone::DEFAULT_REGION)]
+        region: String,
+    },
+
+    /// Show every file, line and row where a value was found
+    Occurrences {
+        /// Case database to search
+        #[arg(long)]
+        db: PathBuf,
+
+        /// Limit to one item type (phone, email, ip, imsi, imei, iccid)
+        #[arg(long = "type")]
+        item_type: Option<String>,
+
+        /// Value as stored, e.g. +15551234567
+        value: String,
+    },
+}
+
+fn parse_source(arg: &str) -> Result<(String, PathBuf), String> {
+    let (source, path) = arg
+        .split_once('=')
+        .ok_or_else(|| format!("expected SOURCE=PATH, got '{}'", arg))?;
+    let source = source.trim();
+    if source.is_empty() {
+        return Err(format!("missing source in '{}'", arg));
+    }
+    Ok((source.to_string(), PathBuf::from(path)))
+}
+
+fn parse_site(arg: &str) -> Result<(String, Option<String>, Option<String>), String> {
+    let (source, window) = match arg.split_once('@') {
+        Some((source, window)) => (source, Some(window)),
+        None => (arg, None),
+    };
+    if source.trim().is_empty() {
+        return Err(format!("missing source in '{}'", arg));
+    }
+
+    let bound = |s: &str| Some(s.trim().to_string()).filter(|s| !s.is_empty());
+    let (start, end) = match window {
+        Some(window) => {
+            let (start, end) = window
+                .split_once('/')
+                .ok_or_else(|| format!("expected START/END after '@', got '{}'", window))?;
+            (bound(start), bound(end))
+        }
+        None => (None, None),
+    };
+
+    Ok((source.trim().to_string(), start, end))
+}
+
+// A numeric SOURCE is an id; anything else is a name, created on first use
+async fn resolve_source(db: &Database, source: &str) -> Result<Source, AppError> {
+    if let Ok(id) = source.parse::<i32>() {
+        db.ensure_source(id).await?;
+        return db.get_source(id).await;
+    }
+
+    match db.find_source_by_name(source).await? {
+        Some(existing) => Ok(existing),
+        None => db.create_source(source, None, None).await,
+    }
+}
+
+// Expands a path into the files beneath it, in a stable order
+fn collect_files(path: &Path, files: &mut Vec<PathBuf>) -> Result<(), AppError> {
+    if path.is_dir() {
+        let mut entries = std::fs::read_dir(path)?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+        entries.sort();
+
+        for entry in entries {
+            collect_files(&entry, files)?;
+        }
+    } else if path.is_file() {
+        files.push(path.to_path_buf());
+    } else {
+        return Err(AppError::FileNotFound);
+    }
+
+    Ok(())
+}
+
+async fn ingest(
+    db_path: &Path,
+    sources: &[(String, PathBuf)],
+    region: &str,
+    encoding: Option<TextEncoding>,
+    storage: ContentStorage,
+) -> Result<(), AppError> {
+    let mut processor = FileProcessor::with_region(region)?.with_encoding(encoding);
+    let interactive = std::io::stderr().is_terminal();
+    if interactive {
+        // Long files show a running count, overwritten in place
+        processor = processor.with_job(IngestJob::new(0).on_progress(|progress| {
+            eprint!(
+                "\r\x1b[K{}: {} MiB, {} lines, {} items",
+                progress.file_name,
+                progress.stats.bytes_read / (1024 * 1024),
+                progress.stats.lines,
+                progress.stats.items
+            );
+        }));
+    }
+    let db_path_str = db_path.to_string_lossy();
+    let existing = db_path.exists();
+
+    // Existing cases are written in place; new ones are built in a scratch file and saved at the end
+    let db = if existing {
+        Database::from_file(&db_path_str).await?
+    } else {
+        Database::new_scratch().await?
+    };
+
+    let mut total_files = 0;
+    let mut total_items = 0;
+
+    for (source, root) in sources {
+        let source = resolve_source(&db, source).await?;
+        let mut files = Vec::new();
+        collect_files(root, &mut files)
+            .map_err(|e| AppError::General(format!("{}: {}", root.display(), e)))?;
+
+        for path in files {
+            let result = processor.process_path(&path, source.id, storage, &db).await;
+            if interactive {
+                eprint!("\r\x1b[K");
+            }
+            match result {
+                Ok(stats) => {
+                    eprintln!("[{}] {}: {} items", source.name, path.display(), stats.items);
+                    total_files += 1;
+                    total_items += stats.items;
+                }
+                Err(e) => {
+                    // Keep going so one bad return does not abort the whole batch
+                    eprintln!("[{}] {}: skipped ({})", source.name, path.display(), e);
+                }
+            }
+        }
+    }
+
+    if !existing {
+        db.save_to_file(&db_path_str).await?;
+    }
+
+    eprintln!("Processed {} files, {} items -> {}", total_files, total_items, db_path.display());
+    Ok(())
+}
+
+async fn import_legacy(legacy_path: &Path, db_path: &Path, region: &str) -> Result<(), AppError> {
+    if !legacy_path.exists() {
+        return Err(AppError::FileNotFound);
+    }
+    if db_path.exists() {
+        return Err(AppError::General(format!("{} already exists", db_path.display())));
+    }
+
+    let db = Database::open(&legacy_path.to_string_lossy(), region).await?;
+    db.save_to_file(&db_path.to_string_lossy()).await?;
+
+    let sources = db.list_sources().await?;
+    eprintln!("Imported {} sources from {} -> {}", sources.len(), legacy_path.display(), db_path.display());
+    Ok(())
+}
+
+// The reporting commands open cases read-only and refuse older ones; this is where they are migrated
+async fn upgrade(db_path: &Path) -> Result<(), AppError> {
+    if !db_path.exists() {
+        return Err(AppError::FileNotFound);
+    }
+
+    Database::from_file(&db_path.to_string_lossy()).await?;
+
+    eprintln!("{} is at schema version {}", db_path.display(), database::SCHEMA_VERSION);
+    Ok(())
+}
+
+async fn export_json(db_path: &Path, output: &Path) -> Result<(), AppError> {
+    if !db_path.exists() {
+        return Err(AppError::FileNotFound);
+    }
+
+    let db = Database::from_file_read_only(&db_path.to_string_lossy()).await?;
+    let case = db.export_cast_json().await?;
+    cast_json::write(&output.to_string_lossy(), &case)?;
+
+    eprintln!("Wrote {} tiles to {}", case.tiles.len(), output.display());
+    Ok(())
+}
+
+async fn crossref(db_path: &Path, output: Option<&Path>) -> Result<(), AppError> {
+    if !db_path.exists() {
+        return Err(AppError::FileNotFound);
+    }
+
+    let db = Database::from_file_read_only(&db_path.to_string_lossy()).await?;
+    let items = db.get_cross_reference_items().await?;
+
+    match output {
+        Some(path) => {
+            export::write_cross_reference_csv(&path.to_string_lossy(), &items)?;
+            eprintln!("Wrote {} cross-referenced values to {}", items.len(), path.display());
+        }
+        None => {
+            for item in &items {
+                let names = item.sources.iter().map(|s| s.name.as_str()).collect::<Vec<_>>();
+                println!("{}\t{}\t{}\t{}\t{}", item.value, item.item_type.as_str(), item.count, names.join("; "), item.aliases.join("; "));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn towers(
+    db_path: &Path,
+    sites: &[(String, Option<String>, Option<String>)],
+    min_sites: Option<usize>,
+    item_type: Option<&str>,
+    output: Option<&Path>,
+) -> Result<(), AppError> {
+    if !db_path.exists() {
+        return Err(AppError::FileNotFound);
+    }
+
+    let db = Database::from_file_read_only(&db_path.to_string_lossy()).await?;
+
+    let mut tower_sites = Vec::new();
+    for (source, start, end) in sites {
+        let source_id = match source.parse::<i32>() {
+            Ok(id) => id,
+            Err(_) => db
+                .find_source_by_name(source)
+                .await?
+                .ok_or_else(|| AppError::General(format!("No source named '{}'", source)))?
+                .id,
+        };
+        tower_sites.push(TowerSite {
+            source_id,
+            start: start.clone(),
+            end: end.clone(),
+            label: None,
+        });
+    }
+
+    let min_sites = min_sites.unwrap_or(tower_sites.len());
+    let items = db.get_tower_intersection(&tower_sites, min_sites, item_type).await?;
+
+    match output {
+        Some(path) => {
+            export::write_tower_intersection_csv(&path.to_string_lossy(), &items)?;
+            eprintln!("Wrote {} identifiers seen at {} or more sites to {}", items.len(), min_sites, path.display());
+        }
+        None => {
+            for item in &items {
+                let labels = item.sites.iter().map(|s| s.label.as_str()).collect::<Vec<_>>();
+                println!("{}\t{}\t{}\t{}", item.value, item.item_type.as_str(), item.site_count, labels.join("; "));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn sources(db_path: &Path) -> Result<(), AppError> {
+    if !db_path.exists() {
+        return Err(AppError::FileNotFound);
+    }
+
+    let db = Database::from_file_read_only(&db_path.to_string_lossy()).await?;
+
+    for source in db.list_sources().await? {
+        println!(
+            "{}\t{}\t{}\t{}",
+            source.id,
+            source.name,
+            source.provider.unwrap_or_default(),
+            source.warrant_number.unwrap_or_default()
+        );
+    }
+
+    Ok(())
+}
+
+async fn events(db_path: &Path, filter: EventFilter, region: &str) -> Result<(), AppError> {
+    if !db_path.exists() {
+        return Err(AppError::FileNotFound);
+    }
+
+    let extractors = Extractors::with_region(region)?;
+    let filter = EventFilter {
+        party: filter.party.map(|party| extractors.phone_in(&party).map(|p| p.e164).unwrap_or(party)),
+        ..filter
+    };
+
+    let db = Database::from_file_read_only(&db_path.to_string_lossy()).await?;
+
+    for event in db.get_events(&filter).await? {
+        println!(
+            "{}\t{}\t{}\t{} -> {}\t{}s\t{}\t[source {}] {}:{}",
+            event.start_time.as_deref().unwrap_or("-"),
+            event.event_type.as_str(),
+            event.direction.map(|d| d.as_str()).unwrap_or("-"),
+            event.from.as_deref().unwrap_or("-"),
+            event.to.clone().or_else(|| (!event.recipients.is_empty()).then(|| event.recipients.join(","))).as_deref().unwrap_or("-"),
+            event.duration_seconds.unwrap_or(0),
+            event.cell_site.as_deref().unwrap_or("-"),
+            event.source_id,
+            event.file_name.as_deref().unwrap_or(""),
+            event.line_number.unwrap_or(0)
+        );
+    }
+
+    Ok(())
+}
+
+async fn occurrences(db_path: &Path, value: &str, item_type: Option<&str>) -> Result<(), AppError> {
+    if !db_path.exists() {
+        return Err(AppError::FileNotFound);
+    }
+
+    let db = Database::from_file_read_only(&db_path.to_string_lossy()).await?;
+
+    for occurrence in db.get_occurrences(value, item_type).await? {
+        let role = occurrence.role.map(|role| format!(" ({})", role)).unwrap_or_default();
+        let sheet = match (occurrence.sheet_name, occurrence.page_number) {
+            (Some(sheet), _) => format!("[{}]", sheet),
+            (None, Some(page)) => format!("[page {}]", page),
+            (None, None) => String::new(),
+        };
+        println!(
+            "[source {}] {}{}:{}:{}{}\t{}",
+            occurrence.source_id,
+            occurrence.file_name,
+            sheet,
+            occurrence.line_number,
+            occurrence.column_number,
+            role,
+            occurrence.row_data
+        );
+    }
+
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() {
+    let cli = Cli::parse();
+
+    let result = match &cli.command {
+        Command::Ingest { db, sources, region, encoding, no_content } => {
+            let storage = if *no_content { ContentStorage::Path } else { ContentStorage::Inline };
+            ingest(db, sources, region, *encoding, storage).await
+        }
+        Command::ImportLegacy { legacy, db, region } => import_legacy(legacy, db, region).await,
+        Command::Upgrade { db } => upgrade(db).await,
+        Command::ExportJson { db, output } => export_json(db, output).await,
+        Command::Crossref { db, output } => crossref(db, output.as_deref()).await,
+        Command::Towers { db, sites, min_sites, item_type, output } => {
+            towers(db, sites, *min_sites, item_type.as_deref(), output.as_deref()).await
+        }
+        Command::Sources { db } => sources(db).await,
+        Command::Events { db, party, from, to, source, region } => {
+            let filter = EventFilter {
+                party: party.clone(),
+                start: from.clone(),
+                end: to.clone(),
+                source_id: *source,
+            };
+            events(db, filter, region).await
+        }
+        Command::Occurrences { db, item_type, value } => occurrences(db, value, item_type.as_deref()).await,
+    };
+
+    if let Err(e) = result {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+}
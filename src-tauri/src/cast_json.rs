@@ -0,0 +1,287 @@
+// CAST_JSON v2: the JSON case document written by the webview's Save Database
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{Map, Value};
+use crate::error::AppError;
+use crate::models::ItemType;
+
+pub const FORMAT: &str = "CAST_JSON";
+pub const VERSION: &str = "2.0";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CastJson {
+    pub version: String,
+    pub format: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<String>,
+    // Written by the legacy SQLite converter; kept as it was
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub conversion: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tile_counter: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<CaseMetadata>,
+    pub tiles: BTreeMap<TileId, Tile>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CaseMetadata {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub application_version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total_tiles: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total_phones: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total_emails: Option<usize>,
+    #[serde(default, rename = "totalIPs", skip_serializing_if = "Option::is_none")]
+    pub total_ips: Option<usize>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+// A tile is one source; "tile-3" is source 3
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TileId(pub i32);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Tile {
+    pub name: String,
+    #[serde(default)]
+    pub files: Vec<String>,
+    #[serde(default)]
+    pub phones: Vec<String>,
+    #[serde(default)]
+    pub emails: Vec<String>,
+    #[serde(default)]
+    pub ips: Vec<String>,
+    #[serde(default)]
+    pub phone_rows: TileRows,
+    #[serde(default)]
+    pub email_rows: TileRows,
+    #[serde(default)]
+    pub ip_rows: TileRows,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<TileMetadata>,
+}
+
+// Value -> every row it was found on
+pub type TileRows = BTreeMap<String, Vec<TileRow>>;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TileMetadata {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_date: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_count: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extraction_methods: Option<Vec<String>>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+// One place a value was found. Anything else the writer attached (extractionMethod,
+// legacy_metadata, ...) is carried in `extra`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TileRow {
+    pub file_name: String,
+    pub line_number: i64,
+    // The legacy converter writes null for rows it had no record for; read as empty
+    #[serde(default, deserialize_with = "null_as_empty")]
+    pub row_data: String,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+// A tile's values of one type with the rows they were found on, and the JSON fields both came from
+pub struct ValueList<'a> {
+    pub item_type: ItemType,
+    pub list_field: &'static str,
+    pub rows_field: &'static str,
+    pub values: &'a [String],
+    pub rows: &'a TileRows,
+}
+
+impl Tile {
+    pub fn value_lists(&self) -> [ValueList<'_>; 3] {
+        [
+            ValueList { item_type: ItemType::Phone, list_field: "phones", rows_field: "phoneRows", values: &self.phones, rows: &self.phone_rows },
+            ValueList { item_type: ItemType::Email, list_field: "emails", rows_field: "emailRows", values: &self.emails, rows: &self.email_rows },
+            ValueList { item_type: ItemType::Ip, list_field: "ips", rows_field: "ipRows", values: &self.ips, rows: &self.ip_rows },
+        ]
+    }
+}
+
+impl fmt::Display for TileId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "tile-{}", self.0)
+    }
+}
+
+impl Serialize for TileId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for TileId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let id = String::deserialize(deserializer)?;
+        id.strip_prefix("tile-")
+            .and_then(|n| n.parse::<i32>().ok())
+            .filter(|n| *n > 0)
+            .map(TileId)
+            .ok_or_else(|| serde::de::Error::custom(format!("tile id \"{}\" is not of the form \"tile-<n>\"", id)))
+    }
+}
+
+fn null_as_empty<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    Ok(Option::<String>::deserialize(deserializer)?.unwrap_or_default())
+}
+
+fn invalid(field: impl Into<String>, message: impl Into<String>) -> AppError {
+    AppError::InvalidCaseFile { field: field.into(), message: message.into() }
+}
+
+// Parses and validates a CAST_JSON document; errors name the offending field
+pub fn parse(text: &str) -> Result<CastJson, AppError> {
+    let deserializer = &mut serde_json::Deserializer::from_str(text);
+    let case: CastJson = serde_path_to_error::deserialize(deserializer).map_err(|e| {
+        let field = e.path().to_string();
+        invalid(if field == "." { "document".to_string() } else { field }, e.into_inner().to_string())
+    })?;
+    case.validate()?;
+    Ok(case)
+}
+
+pub fn read(path: &str) -> Result<CastJson, AppError> {
+    parse(&std::fs::read_to_string(path)?)
+}
+
+pub fn write(path: &str, case: &CastJson) -> Result<(), AppError> {
+    std::fs::write(path, serde_json::to_string_pretty(case)?)?;
+    Ok(())
+}
+
+// A saved case starts with "{"; SQLite cases start with their file header
+pub fn looks_like_cast_json(path: &str) -> Result<bool, AppError> {
+    use std::io::Read;
+
+    let mut head = [0u8; 64];
+    let read = std::fs::File::open(path)?.read(&mut head)?;
+    Ok(head[..read].iter().find(|b| !b.is_ascii_whitespace()) == Some(&b'{'))
+}
+
+impl CastJson {
+    pub fn validate(&self) -> Result<(), AppError> {
+        if self.format != FORMAT {
+            return Err(invalid("format", format!("expected \"{}\", found \"{}\"", FORMAT, self.format)));
+        }
+        if self.version != VERSION {
+            return Err(invalid("version", format!("unsupported version \"{}\", expected \"{}\"", self.version, VERSION)));
+        }
+
+        if let (Some(counter), Some(TileId(last))) = (self.tile_counter, self.tiles.keys().next_back()) {
+            if counter < *last as u32 {
+                return Err(invalid("tileCounter", format!("is {} but tile-{} exists", counter, last)));
+            }
+        }
+
+        for (id, tile) in &self.tiles {
+            if tile.name.trim().is_empty() {
+                return Err(invalid(format!("tiles.{}.name", id), "must not be empty"));
+            }
+
+            for list in tile.value_lists() {
+                let mut seen = HashSet::new();
+                for (i, value) in list.values.iter().enumerate() {
+                    if !seen.insert(value.as_str()) {
+                        return Err(invalid(format!("tiles.{}.{}[{}]", id, list.list_field, i), format!("duplicate value \"{}\"", value)));
+                    }
+                }
+                if let Some(value) = list.rows.keys().find(|value| !seen.contains(value.as_str())) {
+                    return Err(invalid(format!("tiles.{}.{}.{}", id, list.rows_field, value), format!("value is not listed in {}", list.list_field)));
+                }
+            }
+        }
+
+        if let Some(metadata) = &self.metadata {
+            let totals = [
+                ("totalTiles", metadata.total_tiles, self.tiles.len()),
+                ("totalPhones", metadata.total_phones, self.tiles.values().map(|t| t.phones.len()).sum()),
+                ("totalEmails", metadata.total_emails, self.tiles.values().map(|t| t.emails.len()).sum()),
+                ("totalIPs", metadata.total_ips, self.tiles.values().map(|t| t.ips.len()).sum()),
+            ];
+            for (field, stated, actual) in totals {
+                if let Some(stated) = stated.filter(|stated| *stated != actual) {
+                    return Err(invalid(format!("metadata.{}", field), format!("is {} but the tiles hold {}", stated, actual)));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CASE: &str = r#"{
+  "version": "2.0",
+  "format": "CAST_JSON",
+  "tileCounter": 2,
+  "metadata": {"totalTiles": 1, "totalPhones": 1, "exportedBy": "analyst"},
+  "tiles": {
+    "tile-2": {
+      "name": "AT&T return",
+      "files": ["calls.csv"],
+      "phones": ["+12025550143"],
+      "phoneRows": {
+        "+12025550143": [{"fileName": "calls.csv", "lineNumber": 4, "rowData": null, "extractionMethod": "regex"}]
+      }
+    }
+  }
+}"#;
+
+    // The field an invalid document is reported against
+    fn invalid_field(text: &str) -> String {
+        match parse(text) {
+            Err(AppError::InvalidCaseFile { field, .. }) => field,
+            other => panic!("expected an invalid case file, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn reads_and_writes_a_case() {
+        let case = parse(CASE).unwrap();
+        let tile = &case.tiles[&TileId(2)];
+        let row = &tile.phone_rows["+12025550143"][0];
+        assert_eq!((row.line_number, row.row_data.as_str()), (4, ""));
+        assert_eq!(row.extra["extractionMethod"], "regex");
+        assert_eq!(case.metadata.as_ref().unwrap().extra["exportedBy"], "analyst");
+
+        let written = serde_json::to_string(&case).unwrap();
+        assert!(written.contains("\"tile-2\""));
+        assert_eq!(parse(&written).unwrap(), case);
+    }
+
+    #[test]
+    fn errors_name_the_offending_field() {
+        assert_eq!(invalid_field(&CASE.replace("\"2.0\"", "\"1.0\"")), "version");
+        assert_eq!(invalid_field(&CASE.replace("tile-2", "tile-x")), "tiles.tile-x");
+        assert_eq!(invalid_field(&CASE.replace("\"lineNumber\": 4", "\"lineNumber\": \"4\"")), "tiles.tile-2.phoneRows.+12025550143[0].lineNumber");
+        assert_eq!(invalid_field(&CASE.replace("\"tileCounter\": 2", "\"tileCounter\": 1")), "tileCounter");
+        assert_eq!(invalid_field(&CASE.replace("\"AT&T return\"", "\" \"")), "tiles.tile-2.name");
+        assert_eq!(invalid_field(&CASE.replace("[\"+12025550143\"]", "[\"+12025550143\", \"+12025550143\"]")), "tiles.tile-2.phones[1]");
+        assert_eq!(invalid_field(&CASE.replace("[\"+12025550143\"]", "[]")), "tiles.tile-2.phoneRows.+12025550143");
+        assert_eq!(invalid_field(&CASE.replace("\"totalPhones\": 1", "\"totalPhones\": 3")), "metadata.totalPhones");
+        assert_eq!(invalid_field("[]"), "document");
+    }
+}
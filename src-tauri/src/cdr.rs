@@ -0,0 +1,598 @@
+// Carrier CDR layouts: detection from the header row and mapping of rows to typed events
+use chrono::{DateTime, FixedOffset, NaiveDateTime, TimeZone};
+use crate::device_id;
+use crate::extractors::{Extractors, Hit};
+use crate::models::{Carrier, CdrEvent, Direction, EventType, ItemType};
+
+// Carrier exports often open with a case banner before the real header row
+const HEADER_SEARCH_ROWS: usize = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Date,
+    Time,
+    DateTime,
+    Originating,
+    Terminating,
+    Dialed,
+    Subscriber,
+    // The far end when the carrier reports subscriber + direction instead of both parties
+    OtherParty,
+    Direction,
+    Duration,
+    Minutes,
+    CellSite,
+    Sector,
+    Imei,
+    Imsi,
+    Kind,
+    Apn,
+}
+
+struct Layout {
+    carrier: Carrier,
+    // Every one of these headers must be present for the layout to match
+    signature: &'static [&'static str],
+    // Header names per field, most specific first; matched after normalization
+    columns: &'static [(Field, &'static [&'static str])],
+    default_type: EventType,
+}
+
+static LAYOUTS: &[Layout] = &[
+    // AT&T tower dump ("Number Associated w/Tower" is the handset seen on the site)
+    Layout {
+        carrier: Carrier::Att,
+        signature: &["connection date", "number associated w tower"],
+        columns: &[
+            (Field::Date, &["connection date"]),
+            (Field::Time, &["connection time"]),
+            (Field::Originating, &["originating number"]),
+            (Field::Terminating, &["terminating number"]),
+            (Field::Dialed, &["dialed number"]),
+            (Field::Subscriber, &["number associated w tower"]),
+            (Field::Imsi, &["imsi associated w tower"]),
+            (Field::Imei, &["imei associated w tower"]),
+            (Field::Minutes, &["minutes"]),
+            (Field::CellSite, &["tower lac cid or ecgi"]),
+            (Field::Kind, &["call type", "type"]),
+            (Field::Apn, &["apn"]),
+        ],
+        default_type: EventType::Call,
+    },
+    // AT&T area search: registrations located to an ECGI, no far party
+    Layout {
+        carrier: Carrier::Att,
+        signature: &["connection date", "msisdn", "ecgi"],
+        columns: &[
+            (Field::Date, &["connection date"]),
+            (Field::Time, &["connection time"]),
+            (Field::Subscriber, &["msisdn"]),
+            (Field::Imsi, &["imsi"]),
+            (Field::Imei, &["imei"]),
+            (Field::CellSite, &["ecgi"]),
+        ],
+        default_type: EventType::Data,
+    },
+    // AT&T subscriber usage
+    Layout {
+        carrier: Carrier::Att,
+        signature: &["conn date", "originating number", "terminating number"],
+        columns: &[
+            (Field::Date, &["conn date"]),
+            (Field::Time, &["conn time"]),
+            (Field::Originating, &["originating number"]),
+            (Field::Terminating, &["terminating number"]),
+            (Field::Dialed, &["number dialed", "dialed digits"]),
+            (Field::Duration, &["elapsed time"]),
+            (Field::Imei, &["imei"]),
+            (Field::Imsi, &["imsi"]),
+            (Field::Kind, &["ct", "feature", "call type"]),
+            (Field::CellSite, &["cell location", "1st tower", "first tower"]),
+        ],
+        default_type: EventType::Call,
+    },
+    // T-Mobile call detail and tower records
+    Layout {
+        carrier: Carrier::TMobile,
+        signature: &["calling number", "called number"],
+        columns: &[
+            (Field::DateTime, &["start date time", "start time", "date time", "event time"]),
+            (Field::Date, &["date", "start date", "call date"]),
+            (Field::Time, &["time", "call time"]),
+            (Field::Originating, &["calling number", "calling party"]),
+            (Field::Terminating, &["called number", "called party"]),
+            (Field::Dialed, &["dialed number", "dialed digits"]),
+            (Field::Direction, &["direction", "call direction"]),
+            (Field::Duration, &["duration", "call duration"]),
+            (Field::Imei, &["imei"]),
+            (Field::Imsi, &["imsi"]),
+            (Field::CellSite, &["1st cell id", "first cell id", "first cell", "cell id"]),
+            (Field::Kind, &["call type", "service type", "event type", "record type"]),
+            (Field::Apn, &["apn"]),
+        ],
+        default_type: EventType::Call,
+    },
+    // Verizon voice, reported from the subscriber's side
+    Layout {
+        carrier: Carrier::Verizon,
+        signature: &["mobile directory number", "call direction"],
+        columns: &[
+            (Field::DateTime, &["seizure dt tm", "connect dt tm", "date time", "call date time"]),
+            (Field::Subscriber, &["mobile directory number"]),
+            (Field::OtherParty, &["dialed digit number", "other party number"]),
+            (Field::Originating, &["calling party number"]),
+            (Field::Terminating, &["called party number"]),
+            (Field::Direction, &["call direction"]),
+            (Field::Duration, &["seizure duration", "call duration", "duration"]),
+            (Field::CellSite, &["first serving cell site", "cell site"]),
+            (Field::Sector, &["first serving cell face", "sector"]),
+            (Field::Imei, &["esn imei", "imei", "meid"]),
+            (Field::Imsi, &["imsi"]),
+            (Field::Kind, &["service type", "call type"]),
+        ],
+        default_type: EventType::Call,
+    },
+    // Verizon messaging
+    Layout {
+        carrier: Carrier::Verizon,
+        signature: &["originating mdn", "terminating mdn"],
+        columns: &[
+            (Field::DateTime, &["date time", "message date time"]),
+            (Field::Originating, &["originating mdn"]),
+            (Field::Terminating, &["terminating mdn"]),
+            (Field::Direction, &["direction"]),
+            (Field::Kind, &["message type", "type"]),
+            (Field::CellSite, &["cell site"]),
+        ],
+        default_type: EventType::Sms,
+    },
+];
+
+// A detected layout bound to the column positions of one file
+pub struct CdrParser {
+    layout: &'static Layout,
+    columns: Vec<(Field, usize)>,
+    // Zone the timestamps are in, taken from the time header, e.g. "Connection Time (UTC)";
+    // None when the header names none
+    offset: Option<FixedOffset>,
+}
+
+// Tracks the header row of a carrier export while its rows are read in order
+pub struct CdrReader<'a> {
+    extractors: &'a Extractors,
+    parser: Option<CdrParser>,
+    rows_seen: usize,
+}
+
+impl<'a> CdrReader<'a> {
+    pub fn new(extractors: &'a Extractors) -> Self {
+        Self {
+            extractors,
+            parser: None,
+            rows_seen: 0,
+        }
+    }
+
+    // Event for a data row; header and preamble rows return None
+    pub fn read_row(&mut self, fields: &[&str]) -> Option<CdrEvent> {
+        if let Some(parser) = &self.parser {
+            return parser.parse(fields, self.extractors);
+        }
+
+        if self.rows_seen < HEADER_SEARCH_ROWS {
+            self.parser = CdrParser::detect(fields);
+        }
+        self.rows_seen += 1;
+        None
+    }
+    // Whether a carrier layout has been recognized in the rows read so far
+    pub fn recognized(&self) -> bool {
+        self.parser.is_some()
+    }
+}
+
+// Device identifiers the CDR columns name but a free-text scan of `text` cannot tell apart
+// from other digit runs, e.g. the bare 14-digit IMEIs in AT&T tower dumps
+pub fn column_hits(event: &CdrEvent, text: &str, found: &[Hit]) -> Vec<Hit> {
+    let mut hits = Vec::new();
+    let identifiers = [
+        (ItemType::Imei, event.imei.as_deref().and_then(|imei| device_id::parse_imei(&imei[..14]))),
+        (ItemType::Imsi, event.imsi.as_deref().and_then(device_id::parse_imsi)),
+    ];
+
+    for (item_type, id) in identifiers {
+        let Some(id) = id else { continue };
+        if found.iter().any(|hit| hit.item_type == item_type && hit.value == id.value) {
+            continue;
+        }
+        // An IMEI may be written without its check digit, so locate it by the 14-digit body
+        let needle = if item_type == ItemType::Imei { &id.value[..14] } else { id.value.as_str() };
+        if let Some(offset) = text.find(needle) {
+            hits.push(Hit {
+                item_type,
+                value: id.value,
+                attributes: id.attributes,
+                offset,
+            });
+        }
+    }
+
+    hits
+}
+
+// Lowercase words only, so "Number Associated w/Tower" reads "number associated w tower"
+fn normalize_header(header: &str) -> String {
+    header
+        .to_lowercase()
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// Headers often carry a unit or zone suffix, e.g. "connection time utc" for "connection time"
+fn header_matches(header: &str, name: &str) -> bool {
+    header == name || header.strip_prefix(name).is_some_and(|rest| rest.starts_with(' '))
+}
+
+impl CdrParser {
+    pub fn detect(headers: &[&str]) -> Option<CdrParser> {
+        let headers: Vec<String> = headers.iter().map(|h| normalize_header(h)).collect();
+
+        let layout = LAYOUTS.iter().find(|layout| {
+            layout.signature.iter().all(|name| headers.iter().any(|h| header_matches(h, name)))
+        })?;
+
+        let mut columns: Vec<(Field, usize)> = Vec::new();
+        for (field, names) in layout.columns {
+            // A column already taken by a more specific field is not reused, e.g. "date time" vs "date"
+            let index = names.iter().find_map(|name| {
+                (0..headers.len()).find(|i| header_matches(&headers[*i], name) && !columns.iter().any(|(_, c)| c == i))
+            });
+            if let Some(index) = index {
+                columns.push((*field, index));
+            }
+        }
+
+        let offset = columns
+            .iter()
+            .filter(|(field, _)| matches!(field, Field::Time | Field::DateTime))
+            .find_map(|(_, i)| zone_offset(headers[*i].split(' ').next_back()?));
+
+        Some(CdrParser { layout, columns, offset })
+    }
+
+    fn parse(&self, fields: &[&str], extractors: &Extractors) -> Option<CdrEvent> {
+        let field = |wanted: Field| {
+            self.columns
+                .iter()
+                .find(|(f, _)| *f == wanted)
+                .and_then(|(_, i)| fields.get(*i))
+                .map(|value| value.trim())
+                .filter(|value| !value.is_empty())
+        };
+        let phone = |wanted: Field| field(wanted).and_then(|v| extractors.phone_in(v)).map(|p| p.e164);
+
+        let mut originating = phone(Field::Originating);
+        let mut terminating = phone(Field::Terminating);
+        let subscriber = phone(Field::Subscriber);
+        let other_party = phone(Field::OtherParty);
+        let mut direction = field(Field::Direction).and_then(parse_direction);
+
+        let kind = field(Field::Kind);
+        if direction.is_none() {
+            direction = kind.and_then(parse_direction);
+        }
+
+        // Subscriber-side layouts give the far end and a direction; resolve both parties
+        if let (Some(subscriber), Some(other)) = (&subscriber, &other_party) {
+            match direction {
+                Some(Direction::Outgoing) => {
+                    originating.get_or_insert_with(|| subscriber.clone());
+                    terminating.get_or_insert_with(|| other.clone());
+                }
+                Some(Direction::Incoming) => {
+                    originating.get_or_insert_with(|| other.clone());
+                    terminating.get_or_insert_with(|| subscriber.clone());
+                }
+                None => {}
+            }
+        }
+        if direction.is_none() {
+            if let Some(subscriber) = &subscriber {
+                if originating.as_ref() == Some(subscriber) {
+                    direction = Some(Direction::Outgoing);
+                } else if terminating.as_ref() == Some(subscriber) {
+                    direction = Some(Direction::Incoming);
+                }
+            }
+        }
+
+        let start_time = match field(Field::DateTime) {
+            Some(datetime) => parse_timestamp(datetime, self.offset),
+            None => field(Field::Date).and_then(|date| {
+                let text = match field(Field::Time) {
+                    Some(time) => format!("{} {}", date, time),
+                    None => format!("{} 00:00:00", date),
+                };
+                parse_timestamp(&text, self.offset)
+            }),
+        };
+
+        let duration_seconds = field(Field::Duration)
+            .and_then(parse_duration)
+            .or_else(|| field(Field::Minutes).and_then(|m| m.parse::<f64>().ok()).map(|m| (m * 60.0).round() as i64));
+
+
+        let imei = field(Field::Imei).and_then(|v| {
+            let digits: String = v.chars().filter(char::is_ascii_digit).collect();
+            device_id::parse_imei(&digits).or_else(|| device_id::parse_imeisv(&digits)).map(|id| id.value)
+        });
+        let imsi = field(Field::Imsi).and_then(device_id::parse_imsi).map(|id| id.value);
+
+        // Footers and section breaks carry no party or time
+        if originating.is_none() && terminating.is_none() && subscriber.is_none() && start_time.is_none() {
+            return None;
+        }
+
+        let event_type = kind
+            .and_then(parse_event_type)
+            .or_else(|| field(Field::Apn).map(|_| EventType::Data))
+            .unwrap_or(self.layout.default_type);
+
+        Some(CdrEvent {
+            carrier: Some(self.layout.carrier),
+            event_type,
+            direction,
+            originating,
+            terminating,
+            dialed: phone(Field::Dialed),
+            subscriber,
+            start_time,
+            duration_seconds,
+            cell_site: field(Field::CellSite).map(str::to_string),
+            sector: field(Field::Sector).map(str::to_string),
+            imei,
+            imsi,
+            recipients: Vec::new(),
+            attachments: Vec::new(),
+            roles: Vec::new(),
+        })
+    }
+}
+
+// Zone names carriers put in headers and timestamps, plus "utc-5" style offsets
+fn zone_offset(zone: &str) -> Option<FixedOffset> {
+    let zone = zone.to_lowercase();
+    let hours = match zone.as_str() {
+        "utc" | "gmt" | "z" => 0,
+        "edt" => -4,
+        "est" | "cdt" => -5,
+        "cst" | "mdt" => -6,
+        "mst" | "pdt" => -7,
+        "pst" => -8,
+        _ => {
+            let rest = zone.strip_prefix("utc").or_else(|| zone.strip_prefix("gmt"))?;
+            rest.parse::<i32>().ok().filter(|h| (-14..=14).contains(h))?
+        }
+    };
+    FixedOffset::east_opt(hours * 3600)
+}
+
+const NAIVE_FORMATS: &[&str] = &[
+    "%m/%d/%Y %H:%M:%S",
+    "%m/%d/%Y %H:%M:%S%.f",
+    "%m/%d/%Y %H:%M",
+    "%m/%d/%Y %I:%M:%S %p",
+    "%m/%d/%Y %I:%M %p",
+    "%m/%d/%y %H:%M:%S",
+    "%m/%d/%y %H:%M",
+    "%m-%d-%Y %H:%M:%S",
+    "%Y-%m-%d %H:%M:%S",
+    "%Y-%m-%d %H:%M:%S%.f",
+    "%Y-%m-%dT%H:%M:%S",
+    "%Y-%m-%dT%H:%M:%S%.f",
+    "%Y-%m-%d %H:%M",
+    "%Y%m%d %H%M%S",
+    "%Y%m%d%H%M%S",
+];
+
+// Carrier timestamp as RFC 3339; an explicit zone in the value wins over the header's.
+// With neither, the time is kept as written, without an offset, rather than guessed at.
+pub fn parse_timestamp(text: &str, offset: Option<FixedOffset>) -> Option<String> {
+    let text = text.trim();
+
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(text) {
+        return Some(datetime.to_rfc3339());
+    }
+    for format in ["%Y-%m-%d %H:%M:%S %z", "%m/%d/%Y %H:%M:%S %z"] {
+        if let Ok(datetime) = DateTime::parse_from_str(text, format) {
+            return Some(datetime.to_rfc3339());
+        }
+    }
+
+    let (text, offset) = match text.rsplit_once(' ') {
+        Some((rest, zone)) if zone.chars().any(|c| c.is_ascii_alphabetic()) && !zone.eq_ignore_ascii_case("am") && !zone.eq_ignore_ascii_case("pm") => {
+            (rest, Some(zone_offset(zone)?))
+        }
+        _ => (text, offset),
+    };
+
+    let naive = NAIVE_FORMATS
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(text, format).ok())?;
+    match offset {
+        Some(offset) => offset.from_local_datetime(&naive).single().map(|datetime| datetime.to_rfc3339()),
+        None => Some(local_time(&naive)),
+    }
+}
+
+// A time in an unknown zone, e.g. "2022-12-31T23:59:58"
+pub fn local_time(naive: &NaiveDateTime) -> String {
+    naive.format("%Y-%m-%dT%H:%M:%S%.f").to_string()
+}
+
+// Whether a stored timestamp is a local time without an offset
+pub fn is_local_time(timestamp: &str) -> bool {
+    DateTime::parse_from_rfc3339(timestamp).is_err()
+        && NaiveDateTime::parse_from_str(timestamp, "%Y-%m-%dT%H:%M:%S%.f").is_ok()
+}
+
+// Seconds from "95", "95.0", "1:35" or "0:01:35"
+fn parse_duration(text: &str) -> Option<i64> {
+    if text.contains(':') {
+        return text
+            .split(':')
+            .try_fold(0i64, |total, part| part.trim().parse::<i64>().ok().map(|n| total * 60 + n));
+    }
+    text.parse::<f64>().ok().map(|seconds| seconds.round() as i64)
+}
+
+fn parse_direction(text: &str) -> Option<Direction> {
+    let text = text.trim().to_lowercase();
+    match text.as_str() {
+        "o" | "out" | "outgoing" | "outbound" | "mo" | "orig" | "originating" | "sent" => Some(Direction::Outgoing),
+        "i" | "t" | "in" | "inc" | "incoming" | "inbound" | "mt" | "term" | "terminating" | "received" => Some(Direction::Incoming),
+        _ if text.ends_with("mo") => Some(Direction::Outgoing),
+        _ if text.ends_with("mt") => Some(Direction::Incoming),
+        _ => None,
+    }
+}
+
+fn parse_event_type(text: &str) -> Option<EventType> {
+    let text = text.to_lowercase();
+    if ["sms", "mms", "text", "message"].iter().any(|k| text.contains(k)) {
+        Some(EventType::Sms)
+    } else if text.contains("volte") || text.contains("voice") {
+        Some(EventType::Call)
+    } else if ["data", "gprs", "packet", "internet", "ipdr"].iter().any(|k| text.contains(k)) {
+        Some(EventType::Data)
+    } else if text.contains("call") {
+        Some(EventType::Call)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timestamps_keep_the_zone_they_were_given() {
+        let eastern = zone_offset("est");
+        assert_eq!(parse_timestamp("01/15/2024 13:45:00", eastern).as_deref(), Some("2024-01-15T13:45:00-05:00"));
+        assert_eq!(parse_timestamp("01/15/2024 13:45:00 UTC", eastern).as_deref(), Some("2024-01-15T13:45:00+00:00"));
+        assert_eq!(parse_timestamp("2024-01-15T13:45:00Z", None).as_deref(), Some("2024-01-15T13:45:00+00:00"));
+        assert_eq!(parse_timestamp("01/15/2024 1:45 PM PST", None).as_deref(), Some("2024-01-15T13:45:00-08:00"));
+    }
+
+    #[test]
+    fn zone_less_timestamps_stay_local_time() {
+        let local = parse_timestamp("01/15/2024 13:45:00", None).unwrap();
+        assert_eq!(local, "2024-01-15T13:45:00");
+        assert!(is_local_time(&local));
+        assert!(!is_local_time("2024-01-15T13:45:00+00:00"));
+        assert_eq!(parse_timestamp("01/15/2024 13:45:00 XYZ", None), None);
+    }
+
+    #[test]
+    fn row_times_take_the_zone_from_the_header() {
+        let extractors = Extractors::new();
+        let start_time = |time_header: &str| {
+            let mut reader = CdrReader::new(&extractors);
+            reader.read_row(&["Calling Number", "Called Number", time_header]);
+            reader.read_row(&["2025550143", "3125550178", "2024-01-15 13:45:00"]).and_then(|event| event.start_time)
+        };
+
+        assert_eq!(start_time("Start Date Time (UTC)").as_deref(), Some("2024-01-15T13:45:00+00:00"));
+        assert_eq!(start_time("Start Date Time (CST)").as_deref(), Some("2024-01-15T13:45:00-06:00"));
+        assert_eq!(start_time("Start Date Time").as_deref(), Some("2024-01-15T13:45:00"));
+    }
+
+    fn read(rows: &[&[&str]]) -> Vec<CdrEvent> {
+        let extractors = Extractors::new();
+        let mut reader = CdrReader::new(&extractors);
+        rows.iter().filter_map(|row| reader.read_row(row)).collect()
+    }
+
+    #[test]
+    fn reads_att_tower_dumps_after_a_banner() {
+        let events = read(&[
+            &["AT&T Subpoena Compliance", "", ""],
+            &["Case 2024-017"],
+            &["Connection Date", "Connection Time (UTC)", "Originating Number", "Terminating Number", "Minutes", "Number Associated w/Tower", "IMEI Associated w/Tower", "IMSI Associated w/Tower", "Tower LAC/CID or ECGI", "Call Type"],
+            &["01/15/2024", "13:45:00", "(202) 555-0143", "3125550178", "1.5", "2025550143", "49015420323751", "310150123456789", "311480-1234", "Voice"],
+            &["Total records: 1"],
+        ]);
+        assert_eq!(events.len(), 1);
+        let event = &events[0];
+        assert_eq!(event.carrier, Some(Carrier::Att));
+        assert_eq!(event.event_type, EventType::Call);
+        assert_eq!(event.direction, Some(Direction::Outgoing));
+        assert_eq!(event.start_time.as_deref(), Some("2024-01-15T13:45:00+00:00"));
+        assert_eq!(event.duration_seconds, Some(90));
+        assert_eq!(event.imei.as_deref(), Some("490154203237518"));
+        assert_eq!(event.imsi.as_deref(), Some("310150123456789"));
+        assert_eq!(event.cell_site.as_deref(), Some("311480-1234"));
+    }
+
+    #[test]
+    fn resolves_parties_from_the_subscriber_side() {
+        let events = read(&[
+            &["Mobile Directory Number", "Dialed Digit Number", "Call Direction", "Seizure Dt Tm", "Seizure Duration", "First Serving Cell Site", "First Serving Cell Face"],
+            &["2025550143", "3125550178", "MT", "1/15/2024 1:45 PM", "0:01:35", "123", "2"],
+            &["2025550143", "3125550178", "O", "1/15/2024 1:50 PM", "5", "123", "3"],
+        ]);
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].carrier, Some(Carrier::Verizon));
+        assert_eq!((events[0].originating.as_deref(), events[0].terminating.as_deref()), (Some("+13125550178"), Some("+12025550143")));
+        assert_eq!(events[0].duration_seconds, Some(95));
+        assert_eq!(events[0].start_time.as_deref(), Some("2024-01-15T13:45:00"));
+        assert_eq!((events[1].originating.as_deref(), events[1].terminating.as_deref()), (Some("+12025550143"), Some("+13125550178")));
+        assert_eq!(events[1].sector.as_deref(), Some("3"));
+    }
+
+    #[test]
+    fn message_layouts_and_types() {
+        let events = read(&[
+            &["Originating MDN", "Terminating MDN", "Date Time", "Message Type"],
+            &["2025550143", "3125550178", "2024-01-15 13:45:00 -0500", "MMS"],
+        ]);
+        assert_eq!(events[0].event_type, EventType::Sms);
+        assert_eq!(events[0].start_time.as_deref(), Some("2024-01-15T13:45:00-05:00"));
+
+        assert_eq!(parse_event_type("GPRS session"), Some(EventType::Data));
+        assert_eq!(parse_event_type("VoLTE call"), Some(EventType::Call));
+        assert_eq!(parse_event_type("Registration"), None);
+        assert_eq!(parse_direction("SMS-MO"), Some(Direction::Outgoing));
+        assert_eq!(parse_direction("Forwarded"), None);
+        assert_eq!(parse_duration("1:02:03"), Some(3723));
+        assert_eq!(parse_duration("n/a"), None);
+    }
+
+    #[test]
+    fn unknown_headers_are_not_a_layout() {
+        assert!(CdrParser::detect(&["Name", "Phone", "Date"]).is_none());
+        let extractors = Extractors::new();
+        let mut reader = CdrReader::new(&extractors);
+        for _ in 0..HEADER_SEARCH_ROWS {
+            reader.read_row(&["banner"]);
+        }
+        // The header is only looked for near the top of the file
+        reader.read_row(&["Calling Number", "Called Number"]);
+        assert!(!reader.recognized());
+    }
+
+    #[test]
+    fn column_identifiers_are_found_in_the_row_text() {
+        let events = read(&[
+            &["Calling Number", "Called Number", "IMEI", "IMSI"],
+            &["2025550143", "3125550178", "49015420323751", "310150123456789"],
+        ]);
+        let text = "2025550143,3125550178,49015420323751,310150123456789";
+        let hits = column_hits(&events[0], text, &[]);
+        let found: Vec<(ItemType, &str, usize)> = hits.iter().map(|hit| (hit.item_type, hit.value.as_str(), hit.offset)).collect();
+        assert_eq!(found, [(ItemType::Imei, "490154203237518", 22), (ItemType::Imsi, "310150123456789", 37)]);
+    }
+}
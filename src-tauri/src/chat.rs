@@ -0,0 +1,812 @@
+// Messaging app exports and returns read as one record per message, each with its event:
+// WhatsApp "Export chat" text (or an HTML rendering of it), WhatsApp law-enforcement
+// returns, Telegram Desktop JSON exports and Signal Desktop messages dumped as JSON
+use std::collections::HashMap;
+use std::sync::LazyLock;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime};
+use regex::{Captures, Regex};
+use serde::Deserialize;
+use serde_json::value::RawValue;
+use serde_json::Value;
+use crate::cdr;
+use crate::extractors::Extractors;
+use crate::models::{CdrEvent, Direction, EventType, SourceRecord};
+
+// Non-blank lines looked at to recognize a chat export
+const DETECT_LINES: usize = 50;
+
+// "[12/31/22, 11:59:58 PM] Name: text" from iOS and "12/31/22, 11:59 PM - Name: text" from
+// Android; the date order and clock follow the phone's locale
+static WHATSAPP_LINE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^[\u{200E}\u{200F}]?(?:\[(?P<d1>\d{1,4}[./-]\d{1,2}[./-]\d{1,4}),?\s(?P<t1>\d{1,2}:\d{2}(?::\d{2})?(?:\s?[AaPp]\.?\s?[Mm]\.?)?)\]\s?|(?P<d2>\d{1,4}[./-]\d{1,2}[./-]\d{1,4}),?\s(?P<t2>\d{1,2}:\d{2}(?::\d{2})?(?:\s?[AaPp]\.?\s?[Mm]\.?)?)\s[-\u{2013}]\s)(?P<rest>.*)$").unwrap()
+});
+
+// iOS names media as "<attached: 00000012-PHOTO-2022-12-31-23-59-58.jpg>", Android as
+// "IMG-20221231-WA0001.jpg (file attached)"
+static WHATSAPP_ATTACHMENT: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?m)<attached: ([^>]+)>|^\u{200E}?(\S[^\n]*?) \(file attached\)\r?$").unwrap()
+});
+
+// Fields of a message in a WhatsApp return's Message Log, longest first so "Sender Ip" is
+// not read as "Sender"
+const RETURN_LABELS: &[&str] = &[
+    "Account Identifier",
+    "Message Style",
+    "Message Size",
+    "Sender Device",
+    "Sender Port",
+    "Message Id",
+    "Recipients",
+    "Sender Ip",
+    "Timestamp",
+    "Group Id",
+    "Sender",
+    "Type",
+];
+
+// Runs of spaces that separate the cells of a rendered return
+static RETURN_CELL_SEPARATOR: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\s{2,}").unwrap());
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChatFormat {
+    WhatsAppChat,
+    WhatsAppReturn,
+    TelegramJson,
+    SignalJson,
+}
+
+impl ChatFormat {
+    // Recorded as the file's "format" metadata
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChatFormat::WhatsAppChat => "whatsapp_chat",
+            ChatFormat::WhatsAppReturn => "whatsapp_return",
+            ChatFormat::TelegramJson => "telegram_json",
+            ChatFormat::SignalJson => "signal_json",
+        }
+    }
+}
+
+pub struct ChatExport {
+    pub format: ChatFormat,
+    pub records: Vec<SourceRecord>,
+}
+
+// A message and the bytes of the text it was read from
+struct MessageSpan {
+    start: usize,
+    end: usize,
+    event: CdrEvent,
+}
+
+// Format of a decoded text file, or of HTML already rendered with html::to_text. Judged
+// from the start of the file, so it also works on the first chunk of a large one.
+pub fn detect(text: &str) -> Option<ChatFormat> {
+    if looks_like_telegram(text) {
+        Some(ChatFormat::TelegramJson)
+    } else if looks_like_signal(text) {
+        Some(ChatFormat::SignalJson)
+    } else if looks_like_whatsapp_chat(text) {
+        Some(ChatFormat::WhatsAppChat)
+    } else if looks_like_whatsapp_return(text) {
+        Some(ChatFormat::WhatsAppReturn)
+    } else {
+        None
+    }
+}
+
+// The file's lines as records, those of each message joined into one that carries its
+// event. None when the text is not an export this module reads, e.g. JSON that only
+// resembled a Telegram or Signal export.
+pub fn read(text: &str, extractors: &Extractors) -> Option<ChatExport> {
+    let format = detect(text)?;
+    let spans = match format {
+        ChatFormat::WhatsAppChat => whatsapp_chat_spans(text, extractors),
+        ChatFormat::WhatsAppReturn => whatsapp_return_spans(text, extractors),
+        ChatFormat::TelegramJson => telegram_spans(text, extractors)?,
+        ChatFormat::SignalJson => signal_spans(text, extractors)?,
+    };
+
+    Some(ChatExport {
+        format,
+        records: group_lines(text, spans),
+    })
+}
+
+// Non-blank lines become records as in a plain text file, except that the lines a message
+// spans make up a single record. Messages sharing a line with an earlier one, as in
+// minified JSON, stay part of that record and lose their own event.
+fn group_lines(text: &str, spans: Vec<MessageSpan>) -> Vec<SourceRecord> {
+    let mut records = Vec::new();
+    let mut spans = spans.into_iter().peekable();
+    // The message record being built and the end of its span
+    let mut current: Option<(SourceRecord, usize)> = None;
+    let mut offset = 0;
+
+    for (i, line) in text.split('\n').enumerate() {
+        let start = offset;
+        offset += line.len() + 1;
+
+        if let Some((record, end)) = &mut current {
+            if start < *end {
+                record.text.push('\n');
+                record.text.push_str(line);
+                continue;
+            }
+            records.extend(current.take().map(|(record, _)| finish_message(record)));
+        }
+
+        while spans.peek().is_some_and(|span| span.end <= start) {
+            spans.next();
+        }
+
+        let mut record = SourceRecord {
+            line_number: i as i64 + 1,
+            byte_offset: Some(start as i64),
+            sheet_name: None,
+            page_number: None,
+            text: line.to_string(),
+            event: None,
+            contact: None,
+        };
+
+        match spans.next_if(|span| span.start < offset) {
+            Some(span) => {
+                record.event = Some(span.event);
+                current = Some((record, span.end));
+            }
+            None => {
+                record.text.truncate(line.trim_end_matches('\r').len());
+                if !record.text.trim().is_empty() {
+                    records.push(record);
+                }
+            }
+        }
+    }
+
+    records.extend(current.map(|(record, _)| finish_message(record)));
+    records
+}
+
+fn finish_message(mut record: SourceRecord) -> SourceRecord {
+    record.text.truncate(record.text.trim_end_matches(['\r', '\n']).len());
+    record
+}
+
+// Non-blank lines from the start of `text`, without their line breaks
+fn leading_lines(text: &str) -> impl Iterator<Item = &str> {
+    text.lines()
+        .map(|line| line.trim_start_matches('\u{FEFF}'))
+        .filter(|line| !line.trim().is_empty())
+        .take(DETECT_LINES)
+}
+
+// Zero-width direction marks WhatsApp puts around names and numbers
+fn strip_marks(text: &str) -> String {
+    text.chars()
+        .filter(|c| !matches!(c, '\u{200E}' | '\u{200F}' | '\u{202A}'..='\u{202E}' | '\u{2066}'..='\u{2069}'))
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+// How a sender or recipient is recorded: a name that is only a phone number is normalized
+// like any other number so it matches the items extracted from the text; anything else
+// (a contact name, a Telegram account id) is kept as the export shows it
+pub fn party(name: &str, extractors: &Extractors) -> Option<String> {
+    let name = strip_marks(name);
+    if name.is_empty() {
+        return None;
+    }
+
+    let digits = name.chars().filter(char::is_ascii_digit).count();
+    let phone_like = digits >= 7 && name.chars().all(|c| c.is_ascii_digit() || " +-().\u{A0}".contains(c));
+    match phone_like.then(|| extractors.phone_in(&name)).flatten() {
+        Some(phone) => Some(phone.e164),
+        None => Some(name),
+    }
+}
+
+// Everyone else seen in the conversation, in the order they first appear
+fn others(participants: &[String], sender: Option<&String>) -> Vec<String> {
+    participants.iter().filter(|p| Some(*p) != sender).cloned().collect()
+}
+
+fn message_event(
+    event_type: EventType,
+    sender: Option<String>,
+    recipients: Vec<String>,
+    subscriber: Option<&String>,
+    start_time: Option<String>,
+) -> CdrEvent {
+    let direction = subscriber.and_then(|subscriber| {
+        if sender.as_ref() == Some(subscriber) {
+            Some(Direction::Outgoing)
+        } else if recipients.contains(subscriber) {
+            Some(Direction::Incoming)
+        } else {
+            None
+        }
+    });
+
+    CdrEvent {
+        carrier: None,
+        event_type,
+        direction,
+        terminating: (recipients.len() == 1).then(|| recipients[0].clone()),
+        originating: sender,
+        dialed: None,
+        subscriber: subscriber.cloned(),
+        start_time,
+        duration_seconds: None,
+        cell_site: None,
+        sector: None,
+        imei: None,
+        imsi: None,
+        recipients,
+        attachments: Vec::new(),
+        roles: Vec::new(),
+    }
+}
+
+fn looks_like_whatsapp_chat(text: &str) -> bool {
+    let line = &*WHATSAPP_LINE;
+    let mut lines = leading_lines(text).peekable();
+    if lines.peek().is_some_and(|first| line.is_match(first)) {
+        return true;
+    }
+
+    // An HTML rendering may open with a title or the tool's banner
+    let (total, matching) = lines.fold((0, 0), |(total, matching), l| (total + 1, matching + line.is_match(l) as usize));
+    total > 0 && matching * 2 > total
+}
+
+// Day before month when any date shows it (or dots separate the parts, as in German
+// exports); otherwise month first, as US phones write it
+fn whatsapp_day_first(dates: &[&str]) -> bool {
+    dates.iter().any(|date| {
+        let parts: Vec<&str> = date.split(['.', '/', '-']).collect();
+        date.contains('.') || (parts[0].len() < 4 && parts[0].parse::<u32>().is_ok_and(|n| n > 12))
+    })
+}
+
+// Exports carry the phone's local time without a zone; like a CDR without one, it is kept
+// as local time
+fn whatsapp_timestamp(date: &str, time: &str, day_first: bool) -> Option<String> {
+    let parts: Vec<u32> = date.split(['.', '/', '-']).map(|p| p.parse().ok()).collect::<Option<_>>()?;
+    let [a, b, c] = parts[..] else { return None };
+    let (year, month, day) = if date.split(['.', '/', '-']).next()?.len() == 4 {
+        (a, b, c)
+    } else if day_first {
+        (c, b, a)
+    } else {
+        (c, a, b)
+    };
+    let year = if year < 100 { 2000 + year } else { year };
+
+    // "11:59:58 PM", "11:59\u{202F}p.m." or "23:59"
+    let time: String = time.chars().filter(|c| !c.is_whitespace() && *c != '.').collect::<String>().to_lowercase();
+    let (clock, pm) = match (time.strip_suffix("am"), time.strip_suffix("pm")) {
+        (Some(clock), _) => (clock, Some(false)),
+        (_, Some(clock)) => (clock, Some(true)),
+        _ => (time.as_str(), None),
+    };
+    let fields: Vec<u32> = clock.split(':').map(|p| p.parse().ok()).collect::<Option<_>>()?;
+    let (mut hour, minute, second) = match fields[..] {
+        [h, m] => (h, m, 0),
+        [h, m, s] => (h, m, s),
+        _ => return None,
+    };
+    match pm {
+        Some(false) if hour == 12 => hour = 0,
+        Some(true) if hour < 12 => hour += 12,
+        _ => {}
+    }
+
+    let naive = NaiveDateTime::new(
+        NaiveDate::from_ymd_opt(year as i32, month, day)?,
+        NaiveTime::from_hms_opt(hour, minute, second)?,
+    );
+    Some(cdr::local_time(&naive))
+}
+
+// A message runs from its header line to the line before the next header. The export does
+// not list a group's members, so recipients are the other senders seen in the chat.
+fn whatsapp_chat_spans(text: &str, extractors: &Extractors) -> Vec<MessageSpan> {
+    let header = &*WHATSAPP_LINE;
+    let attachment = &*WHATSAPP_ATTACHMENT;
+
+    // (start, header captures) of every message header line
+    let mut headers: Vec<(usize, Captures)> = Vec::new();
+    let mut offset = 0;
+    for line in text.split('\n') {
+        if let Some(caps) = header.captures(line.trim_end_matches('\r')) {
+            headers.push((offset, caps));
+        }
+        offset += line.len() + 1;
+    }
+
+    let dates: Vec<&str> = headers.iter().filter_map(|(_, caps)| caps.name("d1").or(caps.name("d2"))).map(|m| m.as_str()).collect();
+    let day_first = whatsapp_day_first(&dates);
+
+    // (start, end, sender, time, body)
+    let mut messages = Vec::new();
+    for (i, (start, caps)) in headers.iter().enumerate() {
+        let end = headers.get(i + 1).map(|(next, _)| *next).unwrap_or(text.len());
+        let rest = &caps["rest"];
+        // Lines without "Name: " are notices such as "Messages and calls are end-to-end encrypted"
+        let Some((sender, first_line)) = rest.split_once(": ").filter(|(name, _)| !name.contains('"')) else {
+            continue;
+        };
+        let Some(sender) = party(sender, extractors) else { continue };
+
+        let date = caps.name("d1").or(caps.name("d2")).map(|m| m.as_str()).unwrap_or_default();
+        let time = caps.name("t1").or(caps.name("t2")).map(|m| m.as_str()).unwrap_or_default();
+        let continuation = text[*start..end].split_once('\n').map(|(_, rest)| rest).unwrap_or("");
+        let body = format!("{}\n{}", first_line, continuation);
+
+        messages.push((*start, end, sender, whatsapp_timestamp(date, time, day_first), body));
+    }
+
+    let mut participants: Vec<String> = Vec::new();
+    for (_, _, sender, _, _) in &messages {
+        if !participants.contains(sender) {
+            participants.push(sender.clone());
+        }
+    }
+
+    messages
+        .into_iter()
+        .map(|(start, end, sender, start_time, body)| {
+            let recipients = others(&participants, Some(&sender));
+            let mut event = message_event(EventType::Message, Some(sender), recipients, None, start_time);
+            event.attachments = attachment
+                .captures_iter(&body)
+                .filter_map(|caps| caps.get(1).or(caps.get(2)))
+                .map(|m| strip_marks(m.as_str()))
+                .collect();
+            MessageSpan { start, end, event }
+        })
+        .collect()
+}
+
+// Rendered returns print each field as a label and its value in adjacent cells; cells are
+// separated by line breaks or by the runs of spaces tags leave behind
+fn return_cells(text: &str) -> Vec<(usize, usize, &str)> {
+    let mut cells = Vec::new();
+    let mut offset = 0;
+
+    for line in text.split('\n') {
+        let end = offset + line.len();
+        for cell in RETURN_CELL_SEPARATOR.split(line.trim_end_matches('\r')) {
+            if !cell.trim().is_empty() {
+                cells.push((offset, end, cell.trim()));
+            }
+        }
+        offset = end + 1;
+    }
+
+    cells
+}
+
+// The label a cell starts with, and the value when it shares the cell
+fn return_label(cell: &str) -> Option<(&'static str, Option<&str>)> {
+    RETURN_LABELS.iter().find_map(|label| {
+        if cell == *label {
+            Some((*label, None))
+        } else {
+            cell.strip_prefix(label)
+                .filter(|rest| rest.starts_with(' '))
+                .map(|rest| (*label, Some(rest.trim())))
+        }
+    })
+}
+
+fn looks_like_whatsapp_return(text: &str) -> bool {
+    text.to_lowercase().contains("whatsapp")
+        && return_cells(text).iter().any(|(_, _, cell)| return_label(cell).is_some_and(|(label, _)| label == "Message Id"))
+}
+
+// Each Timestamp in the Message Log opens a message that takes the fields after it. Call
+// log entries also carry timestamps, but no sender or recipients, and are left as text.
+fn whatsapp_return_spans(text: &str, extractors: &Extractors) -> Vec<MessageSpan> {
+    let cells = return_cells(text);
+    // Account ids are full international numbers written without the "+"
+    let number = |value: &str| {
+        let value = value.trim();
+        let value = if value.chars().all(|c| c.is_ascii_digit()) { format!("+{}", value) } else { value.to_string() };
+        party(&value, extractors)
+    };
+
+    let mut subscriber = None;
+    // (start, end, fields) of each timestamped entry
+    let mut entries: Vec<(usize, usize, HashMap<&str, &str>)> = Vec::new();
+    let mut in_entry = false;
+    let mut i = 0;
+
+    while i < cells.len() {
+        let (start, mut end, cell) = cells[i];
+        i += 1;
+        let Some((label, inline)) = return_label(cell) else {
+            in_entry = false;
+            continue;
+        };
+
+        // A label alone in its cell takes the next cell as its value, unless that is a label too
+        let value = match inline {
+            Some(value) => value,
+            None => match cells.get(i) {
+                Some((_, value_end, value)) if return_label(value).is_none() => {
+                    i += 1;
+                    end = *value_end;
+                    *value
+                }
+                _ => "",
+            },
+        };
+
+        match label {
+            "Account Identifier" => subscriber = number(value),
+            "Timestamp" => {
+                entries.push((start, end, HashMap::from([(label, value)])));
+                in_entry = true;
+            }
+            _ if in_entry => {
+                if let Some((_, entry_end, fields)) = entries.last_mut() {
+                    fields.insert(label, value);
+                    *entry_end = end;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    entries
+        .into_iter()
+        .filter(|(_, _, fields)| fields.contains_key("Sender") || fields.contains_key("Recipients"))
+        .map(|(start, end, fields)| {
+            let sender = fields.get("Sender").and_then(|v| number(v));
+            let recipients = fields
+                .get("Recipients")
+                .map(|v| v.split([',', ';', ' ']).filter_map(number).collect())
+                .unwrap_or_default();
+            let start_time = fields.get("Timestamp").and_then(|v| cdr::parse_timestamp(v, None));
+            MessageSpan {
+                start,
+                end,
+                event: message_event(EventType::Message, sender, recipients, subscriber.as_ref(), start_time),
+            }
+        })
+        .collect()
+}
+
+fn looks_like_telegram(text: &str) -> bool {
+    text.trim_start_matches('\u{FEFF}').trim_start().starts_with('{')
+        && (text.contains("\"messages\"") || text.contains("\"personal_information\""))
+}
+
+// result.json of a single chat, or of the whole account with its chats in lists
+#[derive(Deserialize)]
+struct TelegramExport<'a> {
+    id: Option<Value>,
+    #[serde(rename = "type")]
+    kind: Option<String>,
+    #[serde(default, borrow)]
+    messages: Vec<&'a RawValue>,
+    personal_information: Option<TelegramPersonalInformation>,
+    #[serde(default, borrow)]
+    chats: Option<TelegramChatList<'a>>,
+    #[serde(default, borrow)]
+    left_chats: Option<TelegramChatList<'a>>,
+}
+
+#[derive(Deserialize)]
+struct TelegramPersonalInformation {
+    user_id: Option<Value>,
+}
+
+#[derive(Deserialize)]
+struct TelegramChatList<'a> {
+    #[serde(default, borrow)]
+    list: Vec<TelegramChat<'a>>,
+}
+
+#[derive(Deserialize)]
+struct TelegramChat<'a> {
+    id: Option<Value>,
+    #[serde(rename = "type")]
+    kind: Option<String>,
+    #[serde(default, borrow)]
+    messages: Vec<&'a RawValue>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct TelegramMessage {
+    #[serde(rename = "type")]
+    kind: String,
+    date: Option<String>,
+    date_unixtime: Option<Value>,
+    from_id: Option<Value>,
+    from: Option<String>,
+    actor_id: Option<Value>,
+    actor: Option<String>,
+    action: Option<String>,
+    duration_seconds: Option<i64>,
+    photo: Option<String>,
+    file: Option<String>,
+    file_name: Option<String>,
+}
+
+// Ids are "user123" strings in current exports and bare numbers in older ones
+fn telegram_id(value: &Value) -> Option<String> {
+    match value {
+        Value::String(id) => Some(id.clone()),
+        Value::Number(id) => Some(format!("user{}", id)),
+        _ => None,
+    }
+}
+
+impl TelegramMessage {
+    // The account id where the export has one, otherwise the display name
+    fn sender(&self, extractors: &Extractors) -> Option<String> {
+        let id = self.from_id.as_ref().or(self.actor_id.as_ref()).and_then(telegram_id);
+        id.or_else(|| self.from.as_deref().or(self.actor.as_deref()).and_then(|name| party(name, extractors)))
+    }
+
+    fn start_time(&self) -> Option<String> {
+        let unixtime = match &self.date_unixtime {
+            Some(Value::String(seconds)) => seconds.parse().ok(),
+            Some(Value::Number(seconds)) => seconds.as_i64(),
+            _ => None,
+        };
+        match unixtime.and_then(|seconds| DateTime::from_timestamp(seconds, 0)) {
+            Some(datetime) => Some(datetime.fixed_offset().to_rfc3339()),
+            None => self.date.as_deref().and_then(|date| cdr::parse_timestamp(date, None)),
+        }
+    }
+
+    // Media left out of the export is noted as "(File not included. ...)" in place of a path
+    fn attachments(&self) -> Vec<String> {
+        let included = |path: &&String| !path.starts_with('(');
+        let base_name = |path: &String| path.rsplit(['/', '\\']).next().unwrap_or(path).to_string();
+
+        let mut names = Vec::new();
+        if let Some(photo) = self.photo.as_ref().filter(included) {
+            names.push(base_name(photo));
+        }
+        match (&self.file_name, self.file.as_ref().filter(included)) {
+            (Some(name), _) => names.push(name.clone()),
+            (None, Some(file)) => names.push(base_name(file)),
+            (None, None) => {}
+        }
+        names
+    }
+}
+
+// Messages become events and calls (service messages with a phone_call action) call
+// events. Recipients are the other senders of the chat, plus the peer of a one-to-one
+// chat and the account owner of a full export, who may never have written.
+fn telegram_spans(text: &str, extractors: &Extractors) -> Option<Vec<MessageSpan>> {
+    let export: TelegramExport = serde_json::from_str(text).ok()?;
+    let owner = export.personal_information.as_ref().and_then(|info| info.user_id.as_ref()).and_then(telegram_id);
+
+    let mut chats = vec![TelegramChat { id: export.id, kind: export.kind, messages: export.messages }];
+    for list in [export.chats, export.left_chats].into_iter().flatten() {
+        chats.extend(list.list);
+    }
+
+    let mut spans = Vec::new();
+    for chat in chats {
+        let messages: Vec<(&RawValue, TelegramMessage)> = chat.messages
+            .into_iter()
+            .map(|raw| (raw, serde_json::from_str(raw.get()).unwrap_or_default()))
+            .collect();
+
+        let mut participants: Vec<String> = Vec::new();
+        let peer = match chat.kind.as_deref() {
+            Some("personal_chat") | Some("bot_chat") => chat.id.as_ref().and_then(telegram_id),
+            _ => None,
+        };
+        let senders = messages.iter().filter_map(|(_, message)| message.sender(extractors));
+        for participant in owner.clone().into_iter().chain(peer).chain(senders) {
+            if !participants.contains(&participant) {
+                participants.push(participant);
+            }
+        }
+
+        for (raw, message) in messages {
+            let is_call = message.kind == "service" && message.action.as_deref() == Some("phone_call");
+            if message.kind != "message" && !is_call {
+                continue;
+            }
+
+            let sender = message.sender(extractors);
+            let recipients = others(&participants, sender.as_ref());
+            let event_type = if is_call { EventType::Call } else { EventType::Message };
+            let mut event = message_event(event_type, sender, recipients, owner.as_ref(), message.start_time());
+            event.duration_seconds = message.duration_seconds;
+            event.attachments = message.attachments();
+
+            // Raw values borrow from `text`, so their position is where they sit in it
+            let start = raw.get().as_ptr() as usize - text.as_ptr() as usize;
+            spans.push(MessageSpan { start, end: start + raw.get().len(), event });
+        }
+    }
+
+    spans.sort_by_key(|span| span.start);
+    Some(spans)
+}
+
+// An array of message records as Signal Desktop stores them, e.g. from sigtop's JSON export
+fn looks_like_signal(text: &str) -> bool {
+    text.trim_start_matches('\u{FEFF}').trim_start().starts_with('[')
+        && text.contains("\"conversationId\"")
+        && text.contains("\"sent_at\"")
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct SignalMessage {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(rename = "conversationId")]
+    conversation_id: Option<String>,
+    // Milliseconds since the epoch
+    sent_at: Option<i64>,
+    // The sender's number, or their account id in versions that no longer store it
+    source: Option<String>,
+    #[serde(rename = "sourceServiceId", alias = "sourceUuid")]
+    source_service_id: Option<String>,
+    attachments: Vec<SignalAttachment>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct SignalAttachment {
+    #[serde(rename = "fileName")]
+    file_name: Option<String>,
+}
+
+// Incoming and outgoing messages become events; the owner's own number is not stored with
+// what they sent, so outgoing messages have a direction but no sender. Recipients are the
+// other senders seen in the conversation.
+fn signal_spans(text: &str, extractors: &Extractors) -> Option<Vec<MessageSpan>> {
+    let raw_messages: Vec<&RawValue> = serde_json::from_str(text).ok()?;
+    let messages: Vec<(&RawValue, SignalMessage)> = raw_messages
+        .into_iter()
+        .map(|raw| (raw, serde_json::from_str(raw.get()).unwrap_or_default()))
+        .collect();
+
+    let sender = |message: &SignalMessage| match message.kind.as_str() {
+        "incoming" => message.source.as_deref().or(message.source_service_id.as_deref()).and_then(|id| party(id, extractors)),
+        _ => None,
+    };
+
+    let mut participants: HashMap<Option<&str>, Vec<String>> = HashMap::new();
+    for (_, message) in &messages {
+        if let Some(sender) = sender(message) {
+            let seen = participants.entry(message.conversation_id.as_deref()).or_default();
+            if !seen.contains(&sender) {
+                seen.push(sender);
+            }
+        }
+    }
+
+    let mut spans = Vec::new();
+    for (raw, message) in &messages {
+        let direction = match message.kind.as_str() {
+            "incoming" => Direction::Incoming,
+            "outgoing" => Direction::Outgoing,
+            _ => continue,
+        };
+
+        let sender = sender(message);
+        let conversation = participants.get(&message.conversation_id.as_deref()).map(Vec::as_slice).unwrap_or_default();
+        let start_time = message.sent_at
+            .and_then(DateTime::from_timestamp_millis)
+            .map(|datetime| datetime.fixed_offset().to_rfc3339());
+        let mut event = message_event(EventType::Message, sender.clone(), others(conversation, sender.as_ref()), None, start_time);
+        event.direction = Some(direction);
+        event.attachments = message.attachments.iter().filter_map(|a| a.file_name.clone()).collect();
+
+        // Raw values borrow from `text`, so their position is where they sit in it
+        let start = raw.get().as_ptr() as usize - text.as_ptr() as usize;
+        spans.push(MessageSpan { start, end: start + raw.get().len(), event });
+    }
+
+    Some(spans)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn events(export: &ChatExport) -> Vec<&CdrEvent> {
+        export.records.iter().filter_map(|record| record.event.as_ref()).collect()
+    }
+
+    #[test]
+    fn reads_whatsapp_chats_in_local_time() {
+        let extractors = Extractors::new();
+        let text = "[1/15/24, 1:45:00 PM] Alice: Running late\n\
+                    [1/15/24, 1:46:12 PM] +1 202-555-0143: See you there\n\
+                    with a second line\n";
+        assert_eq!(detect(text), Some(ChatFormat::WhatsAppChat));
+
+        let export = read(text, &extractors).unwrap();
+        let events = events(&export);
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].start_time.as_deref(), Some("2024-01-15T13:45:00"));
+        assert!(cdr::is_local_time(events[0].start_time.as_deref().unwrap()));
+        assert_eq!(events[1].originating.as_deref(), Some("+12025550143"));
+        assert_eq!(export.records[1].text, "[1/15/24, 1:46:12 PM] +1 202-555-0143: See you there\nwith a second line");
+    }
+
+    #[test]
+    fn whatsapp_dates_are_day_first_when_any_date_shows_it() {
+        let extractors = Extractors::new();
+        let text = "03/02/2024, 09:15 - Alice: Hallo\n\
+                    25/02/2024, 21:07 - Bob: Tschüss\n";
+        let export = read(text, &extractors).unwrap();
+        let times: Vec<_> = events(&export).iter().map(|event| event.start_time.clone()).collect();
+        assert_eq!(times, [Some("2024-02-03T09:15:00".to_string()), Some("2024-02-25T21:07:00".to_string())]);
+
+        assert_eq!(whatsapp_timestamp("12.31.24", "23:59", true), None);
+        assert_eq!(whatsapp_timestamp("2024-12-31", "12:05 a.m.", false).as_deref(), Some("2024-12-31T00:05:00"));
+    }
+
+    #[test]
+    fn reads_telegram_exports() {
+        let extractors = Extractors::new();
+        let text = r#"{
+ "name": "Bob",
+ "type": "personal_chat",
+ "id": 4242,
+ "messages": [
+  {"id": 1, "type": "message", "date": "2024-01-15T13:45:00", "date_unixtime": "1705326300", "from": "Alice", "from_id": "user1001", "text": "hi"},
+  {"id": 2, "type": "service", "date": "2024-01-15T13:50:00", "date_unixtime": "1705326600", "actor": "Bob", "actor_id": "user4242", "action": "phone_call", "duration_seconds": 65},
+  {"id": 3, "type": "service", "date": "2024-01-15T13:51:00", "actor": "Bob", "actor_id": "user4242", "action": "pin_message"}
+ ]
+}"#;
+        assert_eq!(detect(text), Some(ChatFormat::TelegramJson));
+
+        let export = read(text, &extractors).unwrap();
+        let events = events(&export);
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].originating.as_deref(), Some("user1001"));
+        assert_eq!(events[0].recipients, ["user4242"]);
+        assert_eq!(events[0].start_time.as_deref(), Some("2024-01-15T13:45:00+00:00"));
+        assert_eq!(events[1].event_type, EventType::Call);
+        assert_eq!(events[1].duration_seconds, Some(65));
+    }
+
+    #[test]
+    fn reads_signal_message_dumps() {
+        let extractors = Extractors::new();
+        let text = r#"[
+{"type": "incoming", "conversationId": "c1", "sent_at": 1705326300000, "source": "+12025550143", "body": "hi"},
+{"type": "outgoing", "conversationId": "c1", "sent_at": 1705326360000, "body": "photo", "attachments": [{"contentType": "image/jpeg", "fileName": "IMG_0001.jpg"}]},
+{"type": "keychange", "conversationId": "c1", "sent_at": 1705326400000}
+]"#;
+        assert_eq!(detect(text), Some(ChatFormat::SignalJson));
+
+        let export = read(text, &extractors).unwrap();
+        let events = events(&export);
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].originating.as_deref(), Some("+12025550143"));
+        assert_eq!(events[0].direction, Some(Direction::Incoming));
+        assert_eq!(events[0].start_time.as_deref(), Some("2024-01-15T13:45:00+00:00"));
+        assert_eq!(events[1].originating, None);
+        assert_eq!(events[1].direction, Some(Direction::Outgoing));
+        assert_eq!(events[1].recipients, ["+12025550143"]);
+        assert_eq!(events[1].attachments, ["IMG_0001.jpg"]);
+    }
+
+    #[test]
+    fn other_text_is_not_a_chat() {
+        assert_eq!(detect("Call log\n2024-01-15 13:45 2025550143\n"), None);
+        assert_eq!(detect("[1, 2, 3]"), None);
+        assert_eq!(detect(r#"{"records": []}"#), None);
+    }
+}
@@ -1,40 +1,145 @@
 use tauri::State;
-use crate::{AppState, error::AppError, models::*, file_processor::FileProcessor, database::Database};
+use tokio::sync::{MappedMutexGuard, MutexGuard};
+use crate::{AppState, cast_json, encoding::TextEncoding, error::AppError, models::*, extractors::Extractors, file_processor::{ContentStorage, FileProcessor}, database::Database, job::IngestJob, export, phone};
 
 #[tauri::command]
 pub async fn process_file(
     file_name: String,
     file_content: Vec<u8>,
     source_id: i32,
+    encoding: Option<String>,
     state: State<'_, AppState>,
 ) -> Result<ProcessResult, AppError> {
-    println!("process_file called with: {} ({} bytes), source_id: {}", file_name, file_content.len(), source_id);
+    ensure_no_jobs(&state).await?;
+    let db = case_db(&state).await?;
+    let region = state.default_region.lock().await.clone();
+    // The webview may name the encoding when detection guesses wrong
+    let encoding = encoding.map(|label| label.parse::<TextEncoding>()).transpose()?;
+    let processor = FileProcessor::with_region(&region)?.with_encoding(encoding);
+    let items = processor.process_file(&file_name, &file_content, source_id, &db).await?;
     
-    let mut db_lock = state.db.lock().await;
+    Ok(ProcessResult {
+        file_name,
+        records: items.into_iter().map(|content| Record { content }).collect(),
+    })
+}
+
+// Ingests a file from disk instead of over IPC; CSV and text are streamed in bounded
+// memory. With store_content false only the path is kept, not the extracted text.
+#[tauri::command]
+pub async fn process_path(
+    path: String,
+    source_id: i32,
+    encoding: Option<String>,
+    store_content: Option<bool>,
+    state: State<'_, AppState>,
+) -> Result<IngestStats, AppError> {
+    ensure_no_jobs(&state).await?;
+    let db = case_db(&state).await?;
+    let encoding = encoding.map(|label| label.parse::<TextEncoding>()).transpose()?;
+    let storage = if store_content.unwrap_or(true) { ContentStorage::Inline } else { ContentStorage::Path };
+    let region = state.default_region.lock().await.clone();
+    let processor = FileProcessor::with_region(&region)?.with_encoding(encoding);
     
-    // Initialize database if it doesn't exist
-    if db_lock.is_none() {
-        println!("Initializing database on first use...");
-        let new_db = Database::new_memory().await?;
-        *db_lock = Some(new_db);
-        println!("Database initialized successfully");
-    }
+    processor.process_path(std::path::Path::new(&path), source_id, storage, &db).await
+}
+
+// Ingests files from disk in the background and returns the job id straight away.
+// "ingest-progress" events carry an IngestProgress for the file being read, and one
+// "ingest-finished" event an IngestFinished; a file that fails is skipped.
+#[tauri::command]
+pub async fn start_ingest(
+    paths: Vec<String>,
+    source_id: i32,
+    encoding: Option<String>,
+    store_content: Option<bool>,
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+) -> Result<u64, AppError> {
+    use tauri::{Emitter, Manager};
     
-    let db = db_lock.as_mut().ok_or(AppError::General("Database initialization failed".to_string()))?;
+    ensure_no_jobs(&state).await?;
+    let db = case_db(&state).await?;
+    let encoding = encoding.map(|label| label.parse::<TextEncoding>()).transpose()?;
+    let storage = if store_content.unwrap_or(true) { ContentStorage::Inline } else { ContentStorage::Path };
+    let region = state.default_region.lock().await.clone();
     
-    println!("Database available, creating processor...");
-    let processor = FileProcessor::new();
-    let items = processor.process_file(&file_name, &file_content, source_id, db).await?;
+    let job_id = state.next_job_id.fetch_add(1, std::sync::atomic::Ordering::Relaxed) + 1;
+    let progress_app = app.clone();
+    // Progress is advisory; a window that cannot receive it must not fail the job
+    let job = IngestJob::new(job_id).on_progress(move |progress| {
+        let _ = progress_app.emit("ingest-progress", progress);
+    });
+    let processor = FileProcessor::with_region(&region)?
+        .with_encoding(encoding)
+        .with_job(job.clone());
+    state.jobs.lock().await.insert(job_id, job.clone());
     
-    println!("Processor returned {} items", items.len());
+    let work = tauri::async_runtime::spawn(async move {
+        let mut stats = IngestStats::default();
+        let mut errors = Vec::new();
+        
+        for path in &paths {
+            match processor.process_path(std::path::Path::new(path), source_id, storage, &db).await {
+                Ok(file_stats) => stats.add(&file_stats),
+                Err(AppError::Cancelled) => break,
+                Err(e) => errors.push(format!("{}: {}", path, e)),
+            }
+        }
+        
+        (stats, errors)
+    });
     
-    let result = ProcessResult {
-        file_name: file_name.clone(),
-        records: items.into_iter().map(|content| Record { content }).collect(),
-    };
+    // The job is cleared however the work ends, a panic included, so the case is not
+    // left refusing loads and new ingestion behind a job that no longer runs
+    tauri::async_runtime::spawn(async move {
+        let (stats, errors) = match work.await {
+            Ok(result) => result,
+            Err(e) => (IngestStats::default(), vec![format!("Ingestion stopped unexpectedly: {}", e)]),
+        };
+        
+        let state = app.state::<AppState>();
+        state.jobs.lock().await.remove(&job_id);
+        let _ = app.emit("ingest-finished", IngestFinished {
+            job_id,
+            stats,
+            cancelled: job.is_cancelled(),
+            errors,
+        });
+    });
     
-    println!("Returning ProcessResult for: {}", file_name);
-    Ok(result)
+    Ok(job_id)
+}
+
+// The file being written when the job stops is rolled back; files already finished stay
+#[tauri::command]
+pub async fn cancel_ingest(job_id: u64, state: State<'_, AppState>) -> Result<(), AppError> {
+    let jobs = state.jobs.lock().await;
+    let job = jobs.get(&job_id).ok_or(AppError::General(format!("No running ingestion job {}", job_id)))?;
+    job.cancel();
+    Ok(())
+}
+
+// A handle on the case, created on first use. Ingestion writes through its own handle
+// so the lock is not held while a file is processed.
+async fn case_db(state: &State<'_, AppState>) -> Result<Database, AppError> {
+    let mut db_lock = state.db.lock().await;
+    
+    if db_lock.is_none() {
+        *db_lock = Some(Database::new_scratch().await?);
+    }
+    
+    db_lock.as_ref().cloned().ok_or(AppError::General("Database initialization failed".to_string()))
+}
+
+// Replacing the case under a running job would send its rows to the discarded one, and a
+// file ingested alongside it would interleave with the job's deliveries
+async fn ensure_no_jobs(state: &State<'_, AppState>) -> Result<(), AppError> {
+    if state.jobs.lock().await.is_empty() {
+        Ok(())
+    } else {
+        Err(AppError::General("Ingestion is still running; wait for it to finish or cancel it".to_string()))
+    }
 }
 
 #[tauri::command]
@@ -45,35 +150,73 @@ pub async fn get_analysis(state: State<'_, AppState>) -> Result<AnalysisResult,
     let phones = db.get_extracted_items_by_type("phone").await?;
     let emails = db.get_extracted_items_by_type("email").await?;
     let ips = db.get_extracted_items_by_type("ip").await?;
+    let imsis = db.get_extracted_items_by_type("imsi").await?;
+    let imeis = db.get_extracted_items_by_type("imei").await?;
+    let iccids = db.get_extracted_items_by_type("iccid").await?;
     
     Ok(AnalysisResult {
         phones,
         emails,
         ips,
+        imsis,
+        imeis,
+        iccids,
     })
 }
 
 #[tauri::command]
-pub async fn analyze_cross_reference(state: State<'_, AppState>) -> Result<Vec<ExtractedItem>, AppError> {
+pub async fn analyze_cross_reference(state: State<'_, AppState>) -> Result<Vec<CrossReferenceItem>, AppError> {
     let mut db_lock = state.db.lock().await;
     let db = db_lock.as_mut().ok_or(AppError::General("Database not initialized".to_string()))?;
     
     db.get_cross_reference_items().await
 }
 
+#[tauri::command]
+pub async fn get_occurrences(
+    value: String,
+    item_type: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<Vec<Occurrence>, AppError> {
+    let mut db_lock = state.db.lock().await;
+    let db = db_lock.as_mut().ok_or(AppError::General("Database not initialized".to_string()))?;
+    
+    db.get_occurrences(&value, item_type.as_deref()).await
+}
+
+// Legacy CAST databases and CAST_JSON documents are imported into a new case rather than opened in place
 #[tauri::command]
 pub async fn load_database(path: String, state: State<'_, AppState>) -> Result<(), AppError> {
-    let new_db = Database::from_file(&path).await?;
+    ensure_no_jobs(&state).await?;
+    let region = state.default_region.lock().await.clone();
+    let new_db = Database::open(&path, &region).await?;
     let mut db_lock = state.db.lock().await;
     *db_lock = Some(new_db);
     Ok(())
 }
 
+// Emits "save-progress" events with a SaveProgress payload while the case is written
+#[tauri::command]
+pub async fn save_database(path: String, app: tauri::AppHandle, state: State<'_, AppState>) -> Result<(), AppError> {
+    use tauri::Emitter;
+    
+    let mut db_lock = state.db.lock().await;
+    let db = db_lock.as_mut().ok_or(AppError::General("Database not initialized".to_string()))?;
+    
+    // Progress is advisory; a window that cannot receive it must not fail the save
+    db.save_to_file_with_progress(&path, |progress| {
+        let _ = app.emit("save-progress", progress);
+    }).await
+}
+
+// Writes the case as a CAST_JSON v2 document the webview and earlier releases can load
 #[tauri::command]
-pub async fn save_database(path: String, state: State<'_, AppState>) -> Result<(), AppError> {
+pub async fn export_case_json(path: String, state: State<'_, AppState>) -> Result<(), AppError> {
     let mut db_lock = state.db.lock().await;
     let db = db_lock.as_mut().ok_or(AppError::General("Database not initialized".to_string()))?;
-    db.save_to_file(&path).await
+    
+    let case = db.export_cast_json().await?;
+    cast_json::write(&path, &case)
 }
 
 #[tauri::command]
@@ -81,42 +224,27 @@ pub async fn export_csv(
     data_type: String,
     path: String,
     data: Vec<ExtractedItem>,
+    state: State<'_, AppState>,
 ) -> Result<(), AppError> {
-    let mut wtr = csv::Writer::from_path(&path)?;
-    
-    match data_type.as_str() {
-        "phones" | "emails" | "ips" => {
-            wtr.write_record(&["Value", "Source", "Count"])?;
-            for item in data {
-                wtr.write_record(&[
-                    &item.value,
-                    &format!("Source {}", item.source),
-                    &item.count.to_string(),
-                ])?;
-            }
-        }
-        "cross-ref" => {
-            wtr.write_record(&["Value", "Status", "Total Count"])?;
-            for item in data {
-                wtr.write_record(&[
-                    &item.value,
-                    "Found in both sources",
-                    &item.count.to_string(),
-                ])?;
-            }
-        }
-        _ => {}
-    }
+    let sources = open_db(&state).await?.list_sources().await?;
+    export::write_items_csv(&data_type, &path, &data, &sources)
+}
+
+#[tauri::command]
+pub async fn export_cross_reference(path: String, state: State<'_, AppState>) -> Result<(), AppError> {
+    let mut db_lock = state.db.lock().await;
+    let db = db_lock.as_mut().ok_or(AppError::General("Database not initialized".to_string()))?;
     
-    wtr.flush()?;
-    Ok(())
+    let items = db.get_cross_reference_items().await?;
+    export::write_cross_reference_csv(&path, &items)
 }
 
 #[tauri::command]
 pub async fn clear_all(state: State<'_, AppState>) -> Result<(), AppError> {
+    ensure_no_jobs(&state).await?;
     let mut db_lock = state.db.lock().await;
     
-    let new_db = Database::new_memory().await?;
+    let new_db = Database::new_scratch().await?;
     *db_lock = Some(new_db);
     
     Ok(())
@@ -127,32 +255,154 @@ pub async fn get_database_info(state: State<'_, AppState>) -> Result<DatabaseInf
     let mut db_lock = state.db.lock().await;
     let db = db_lock.as_mut().ok_or(AppError::General("Database not initialized".to_string()))?;
     
-    let all_files = db.get_all_files().await?;
+    let mut sources: Vec<SourceInfo> = db.list_sources().await?
+        .into_iter()
+        .map(|source| SourceInfo {
+            source,
+            files: Vec::new(),
+            records: Vec::new(),
+        })
+        .collect();
     
-    let mut source1 = SourceInfo {
-        files: Vec::new(),
-        records: Vec::new(),
-    };
-    
-    let mut source2 = SourceInfo {
-        files: Vec::new(),
-        records: Vec::new(),
-    };
-    
-    for file in all_files {
+    for file in db.get_all_files().await? {
         let records: Vec<Record> = file.content
             .lines()
             .map(|line| Record { content: line.to_string() })
             .collect();
             
-        if file.source_id == 1 {
-            source1.files.push(file.file_name);
-            source1.records.extend(records);
-        } else {
-            source2.files.push(file.file_name);
-            source2.records.extend(records);
+        if let Some(info) = sources.iter_mut().find(|info| info.source.id == file.source_id) {
+            info.files.push(file.file_name);
+            info.records.extend(records);
         }
     }
     
-    Ok(DatabaseInfo { source1, source2 })
-}
\ No newline at end of file
+    Ok(DatabaseInfo { sources })
+}
+
+// Creates the case database on first use
+async fn open_db(state: &AppState) -> Result<MappedMutexGuard<'_, Database>, AppError> {
+    let mut db_lock = state.db.lock().await;
+    if db_lock.is_none() {
+        *db_lock = Some(Database::new_scratch().await?);
+    }
+    MutexGuard::try_map(db_lock, |db| db.as_mut())
+        .map_err(|_| AppError::General("Database initialization failed".to_string()))
+}
+
+#[tauri::command]
+pub async fn list_sources(state: State<'_, AppState>) -> Result<Vec<Source>, AppError> {
+    open_db(&state).await?.list_sources().await
+}
+
+#[tauri::command]
+pub async fn create_source(
+    name: String,
+    provider: Option<String>,
+    warrant_number: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<Source, AppError> {
+    open_db(&state).await?.create_source(&name, provider.as_deref(), warrant_number.as_deref()).await
+}
+
+#[tauri::command]
+pub async fn rename_source(source_id: i32, name: String, state: State<'_, AppState>) -> Result<Source, AppError> {
+    open_db(&state).await?.rename_source(source_id, &name).await
+}
+
+#[tauri::command]
+pub async fn update_source(
+    source_id: i32,
+    name: String,
+    provider: Option<String>,
+    warrant_number: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<Source, AppError> {
+    open_db(&state).await?.update_source(source_id, &name, provider.as_deref(), warrant_number.as_deref()).await
+}
+
+#[tauri::command]
+pub async fn delete_source(source_id: i32, state: State<'_, AppState>) -> Result<(), AppError> {
+    open_db(&state).await?.delete_source(source_id).await
+}
+
+// Identifiers seen at `min_sites` of the tower dumps (all of them when unset)
+#[tauri::command]
+pub async fn analyze_tower_intersection(
+    sites: Vec<TowerSite>,
+    min_sites: Option<usize>,
+    item_type: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<Vec<TowerIntersectionItem>, AppError> {
+    let min_sites = min_sites.unwrap_or(sites.len());
+    open_db(&state).await?.get_tower_intersection(&sites, min_sites, item_type.as_deref()).await
+}
+
+#[tauri::command]
+pub async fn export_tower_intersection(
+    path: String,
+    sites: Vec<TowerSite>,
+    min_sites: Option<usize>,
+    item_type: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<(), AppError> {
+    let min_sites = min_sites.unwrap_or(sites.len());
+    let items = open_db(&state).await?.get_tower_intersection(&sites, min_sites, item_type.as_deref()).await?;
+    export::write_tower_intersection_csv(&path, &items)
+}
+
+// Typed party values are normalized like extracted ones, so "(555) 123-4567" finds +15551234567
+async fn normalize_party(party: String, state: &AppState) -> Result<String, AppError> {
+    let region = state.default_region.lock().await.clone();
+    let extractors = Extractors::with_region(&region)?;
+    Ok(extractors.phone_in(&party).map(|phone| phone.e164).unwrap_or(party))
+}
+
+#[tauri::command]
+pub async fn get_events_by_party(party: String, state: State<'_, AppState>) -> Result<Vec<CommEvent>, AppError> {
+    let filter = EventFilter {
+        party: Some(normalize_party(party, &state).await?),
+        ..Default::default()
+    };
+    open_db(&state).await?.get_events(&filter).await
+}
+
+#[tauri::command]
+pub async fn get_events_in_range(
+    start: Option<String>,
+    end: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<Vec<CommEvent>, AppError> {
+    let filter = EventFilter {
+        start,
+        end,
+        ..Default::default()
+    };
+    open_db(&state).await?.get_events(&filter).await
+}
+
+#[tauri::command]
+pub async fn get_events_by_source(source_id: i32, state: State<'_, AppState>) -> Result<Vec<CommEvent>, AppError> {
+    let db = open_db(&state).await?;
+    db.get_source(source_id).await?;
+    
+    let filter = EventFilter {
+        source_id: Some(source_id),
+        ..Default::default()
+    };
+    db.get_events(&filter).await
+}
+
+#[tauri::command]
+pub async fn get_default_region(state: State<'_, AppState>) -> Result<String, AppError> {
+    Ok(state.default_region.lock().await.clone())
+}
+
+#[tauri::command]
+pub async fn set_default_region(region: String, state: State<'_, AppState>) -> Result<String, AppError> {
+    let country = phone::country_by_region(&region)
+        .ok_or_else(|| AppError::Parse(format!("Unknown region: {}", region)))?;
+    
+    let mut current = state.default_region.lock().await;
+    *current = country.region.to_string();
+    Ok(current.clone())
+}
@@ -0,0 +1,430 @@
+// Address books: vCard files (versions 2.1, 3.0 and 4.0) and contact lists exported as CSV
+// by phones, Google, Outlook and the like. Each card or row becomes one record naming the
+// phone numbers and email addresses it lists.
+use encoding_rs::{Encoding, UTF_8};
+use crate::email;
+use crate::extractors::Extractors;
+use crate::models::{Contact, ContactValue, ItemType, SourceRecord};
+
+// Properties holding binary data; their lines are left out of the record text so encoded
+// images are not scanned for numbers
+const BINARY_PROPERTIES: &[&str] = &["PHOTO", "LOGO", "SOUND", "KEY"];
+// Type parameters that say nothing about which of a contact's numbers it is
+const PLAIN_TYPES: &[&str] = &["voice", "pref", "internet", "x400"];
+
+// Contact list header names, lowercase
+const FULL_NAME_COLUMNS: &[&str] = &["name", "full name", "display name", "contact name", "contact"];
+const NAME_PART_COLUMNS: &[&[&str]] = &[
+    &["first name", "given name", "first"],
+    &["middle name", "additional name"],
+    &["last name", "family name", "surname", "last"],
+];
+// Words of a value column's header that are not its label, e.g. "Business Phone 2" is "business"
+const VALUE_WORDS: &[&str] = &["phone", "telephone", "tel", "number", "e-mail", "email", "address"];
+
+pub fn is_vcard(text: &str) -> bool {
+    text.trim_start_matches('\u{FEFF}')
+        .trim_start()
+        .get(..11)
+        .is_some_and(|start| start.eq_ignore_ascii_case("begin:vcard"))
+}
+
+// One record per card, its lines as they are in the file; lines outside any card are
+// read like plain text
+pub fn read_vcards(text: &str, extractors: &Extractors) -> Vec<SourceRecord> {
+    let mut records = Vec::new();
+    let mut card: Option<Card> = None;
+    let mut offset = 0;
+
+    for (i, line) in text.split('\n').enumerate() {
+        let start = offset;
+        offset += line.len() + 1;
+        let keyword = line.trim_start_matches('\u{FEFF}').trim();
+
+        match &mut card {
+            // Lines keep their carriage returns so byte offsets within the card hold
+            Some(open) => {
+                open.lines.push(line);
+                if keyword.eq_ignore_ascii_case("end:vcard") {
+                    records.extend(card.take().map(|card| card.finish(extractors)));
+                }
+            }
+            None if keyword.eq_ignore_ascii_case("begin:vcard") => {
+                card = Some(Card { line_number: i as i64 + 1, byte_offset: start as i64, lines: vec![line] });
+            }
+            None if !keyword.is_empty() => records.push(SourceRecord {
+                line_number: i as i64 + 1,
+                byte_offset: Some(start as i64),
+                sheet_name: None,
+                page_number: None,
+                text: line.trim_end_matches('\r').to_string(),
+                event: None,
+                contact: None,
+            }),
+            None => {}
+        }
+    }
+
+    // A card cut short still names its contact
+    records.extend(card.map(|card| card.finish(extractors)));
+    records
+}
+
+struct Card<'a> {
+    line_number: i64,
+    byte_offset: i64,
+    lines: Vec<&'a str>,
+}
+
+impl Card<'_> {
+    fn finish(self, extractors: &Extractors) -> SourceRecord {
+        // Long values are folded onto lines starting with a space (3.0 and 4.0) or, in 2.1,
+        // broken with quoted-printable soft line breaks
+        let mut properties: Vec<String> = Vec::new();
+        let mut owner = Vec::with_capacity(self.lines.len());
+        for line in &self.lines {
+            let line = line.trim_end_matches('\r');
+            match properties.last_mut() {
+                Some(property) if is_quoted_printable(property) && property.ends_with('=') => {
+                    property.pop();
+                    property.push_str(line);
+                }
+                Some(property) if line.starts_with([' ', '\t']) => property.push_str(&line[1..]),
+                _ => properties.push(line.to_string()),
+            }
+            owner.push(properties.len().saturating_sub(1));
+        }
+        let properties: Vec<Option<Property>> = properties.iter().map(|p| Property::parse(p)).collect();
+
+        let binary = |i: usize| {
+            properties[owner[i]].as_ref().is_some_and(|p| BINARY_PROPERTIES.contains(&p.name.as_str()))
+        };
+        let text = self.lines
+            .iter()
+            .enumerate()
+            .map(|(i, line)| if binary(i) { "" } else { line })
+            .collect::<Vec<_>>()
+            .join("\n")
+            .trim_end_matches(['\r', '\n'])
+            .to_string();
+        let complete = !(0..self.lines.len()).any(binary);
+
+        SourceRecord {
+            line_number: self.line_number,
+            byte_offset: complete.then_some(self.byte_offset),
+            sheet_name: None,
+            page_number: None,
+            text,
+            event: None,
+            contact: card_contact(properties.iter().flatten(), extractors),
+        }
+    }
+}
+
+fn is_quoted_printable(property: &str) -> bool {
+    property.split(':').next().unwrap_or_default().to_ascii_uppercase().contains("QUOTED-PRINTABLE")
+}
+
+// A content line: "item1.TEL;TYPE=cell,voice:+1 555 123 4567"
+struct Property {
+    name: String,
+    // Lowercase TYPE values, including 2.1's bare "CELL;VOICE" parameters
+    types: Vec<String>,
+    value: String,
+}
+
+impl Property {
+    fn parse(line: &str) -> Option<Self> {
+        // Parameter values may be quoted and contain colons
+        let mut quoted = false;
+        let colon = line.char_indices().find(|(_, c)| {
+            quoted ^= *c == '"';
+            *c == ':' && !quoted
+        })?.0;
+        let (head, value) = (&line[..colon], &line[colon + 1..]);
+
+        let mut params = head.split(';');
+        let name = params.next()?;
+        let name = name.rsplit('.').next().unwrap_or(name).trim().to_ascii_uppercase();
+
+        let mut types = Vec::new();
+        let mut quoted_printable = false;
+        let mut charset = None;
+        for param in params {
+            let (key, values) = param.split_once('=').unwrap_or(("TYPE", param));
+            let values = values.trim_matches('"');
+            match key.trim().to_ascii_uppercase().as_str() {
+                "TYPE" if values.eq_ignore_ascii_case("quoted-printable") => quoted_printable = true,
+                "TYPE" => types.extend(values.split(',').map(|t| t.trim().to_lowercase()).filter(|t| !t.is_empty())),
+                "ENCODING" => quoted_printable = values.eq_ignore_ascii_case("quoted-printable"),
+                "CHARSET" => charset = Encoding::for_label(values.as_bytes()),
+                _ => {}
+            }
+        }
+
+        let value = if quoted_printable {
+            let bytes = email::decode_quoted_printable(value.as_bytes());
+            charset.unwrap_or(UTF_8).decode_without_bom_handling(&bytes).0.into_owned()
+        } else {
+            value.to_string()
+        };
+
+        Some(Self { name, types, value })
+    }
+
+    // Structured values, e.g. N's family;given;additional;prefix;suffix
+    fn components(&self) -> Vec<String> {
+        let mut components = vec![String::new()];
+        let mut chars = self.value.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some('n' | 'N') => components.last_mut().unwrap().push(' '),
+                    Some(escaped) => components.last_mut().unwrap().push(escaped),
+                    None => {}
+                },
+                ';' => components.push(String::new()),
+                _ => components.last_mut().unwrap().push(c),
+            }
+        }
+        components.iter().map(|c| c.trim().to_string()).collect()
+    }
+
+    fn text(&self) -> String {
+        self.components().join(";")
+    }
+
+    fn label(&self) -> Option<String> {
+        let types: Vec<&str> = self.types
+            .iter()
+            .map(String::as_str)
+            .filter(|t| !PLAIN_TYPES.contains(t))
+            .collect();
+        (!types.is_empty()).then(|| types.join(","))
+    }
+}
+
+fn card_contact<'a>(properties: impl Iterator<Item = &'a Property>, extractors: &Extractors) -> Option<Contact> {
+    let mut full_name = None;
+    let mut structured_name = None;
+    let mut nickname = None;
+    let mut organization = None;
+    let mut values = Vec::new();
+
+    for property in properties {
+        match property.name.as_str() {
+            "FN" => full_name = full_name.or(Some(property.text())),
+            "N" => {
+                // Written as prefix, given, additional, family, suffix
+                let parts = property.components();
+                let order = [3, 1, 2, 0, 4];
+                let name = order.iter().filter_map(|&i| parts.get(i)).filter(|p| !p.is_empty()).cloned().collect::<Vec<_>>();
+                structured_name = structured_name.or(Some(name.join(" ")));
+            }
+            "NICKNAME" => nickname = nickname.or(Some(property.text())),
+            "ORG" => organization = organization.or(property.components().into_iter().next()),
+            // 4.0 writes numbers as URIs: "tel:+1-555-123-4567;ext=12"
+            "TEL" => {
+                let number = property.value.trim();
+                let number = number.get(..4).filter(|scheme| scheme.eq_ignore_ascii_case("tel:")).map_or(number, |_| &number[4..]);
+                let number = number.split(";ext=").next().unwrap_or(number);
+                push_value(&mut values, identifier(number, ItemType::Phone, extractors), ItemType::Phone, property.label());
+            }
+            "EMAIL" => {
+                let address = property.value.trim();
+                let address = address.strip_prefix("mailto:").unwrap_or(address);
+                push_value(&mut values, identifier(address, ItemType::Email, extractors), ItemType::Email, property.label());
+            }
+            _ => {}
+        }
+    }
+
+    let name = [full_name, structured_name, nickname, organization]
+        .into_iter()
+        .flatten()
+        .find(|name| !name.trim().is_empty())?;
+    (!values.is_empty()).then(|| Contact { name: name.trim().to_string(), values })
+}
+
+// The value as extraction stores it, so a contact joins the items found in text
+pub fn identifier(text: &str, item_type: ItemType, extractors: &Extractors) -> Option<String> {
+    match item_type {
+        ItemType::Phone => extractors.phone_in(text).map(|phone| phone.e164),
+        _ => extractors.extract_all(text).into_iter().find(|hit| hit.item_type == item_type).map(|hit| hit.value),
+    }
+}
+
+pub fn push_value(values: &mut Vec<ContactValue>, value: Option<String>, item_type: ItemType, label: Option<String>) {
+    let Some(value) = value else { return };
+    if !values.iter().any(|v| v.item_type == item_type && v.value == value) {
+        values.push(ContactValue { value, item_type, label });
+    }
+}
+
+// Which columns of a contact list hold the name and which the numbers and addresses
+pub struct ContactColumns {
+    // A whole name, or its parts in the order they are written
+    name: Vec<usize>,
+    values: Vec<ValueColumn>,
+}
+
+struct ValueColumn {
+    index: usize,
+    item_type: ItemType,
+    // From the header, e.g. "mobile" for Outlook's "Mobile Phone"
+    label: Option<String>,
+    // Google writes each value's label in a column of its own, e.g. "Phone 1 - Type"
+    label_column: Option<usize>,
+}
+
+impl ContactColumns {
+    // From the header row; a list needs a name column and a phone or email column
+    pub fn detect(header: &[&str]) -> Option<Self> {
+        let columns: Vec<String> = header.iter().map(|h| h.trim().trim_start_matches('\u{FEFF}').to_lowercase()).collect();
+        let find = |names: &[&str]| columns.iter().position(|c| names.contains(&c.as_str()));
+
+        let name = match find(FULL_NAME_COLUMNS) {
+            Some(index) => vec![index],
+            None => NAME_PART_COLUMNS.iter().filter_map(|names| find(names)).collect(),
+        };
+
+        let mut values = Vec::new();
+        for (index, column) in columns.iter().enumerate() {
+            if column.contains("type") || column.contains("label") {
+                continue;
+            }
+            let item_type = if column.contains("e-mail") || column.contains("email") {
+                ItemType::Email
+            } else if column.contains("phone") || column.contains("mobile") || column == "tel" || column == "number" {
+                ItemType::Phone
+            } else {
+                continue;
+            };
+
+            let (label, label_column) = match column.strip_suffix(" - value") {
+                Some(prefix) => {
+                    let label_names = [format!("{} - type", prefix), format!("{} - label", prefix)];
+                    (None, columns.iter().position(|c| label_names.contains(c)))
+                }
+                None => (header_label(column), None),
+            };
+            values.push(ValueColumn { index, item_type, label, label_column });
+        }
+
+        (!name.is_empty() && !values.is_empty()).then_some(Self { name, values })
+    }
+
+    pub fn read_row(&self, fields: &[&str], extractors: &Extractors) -> Option<Contact> {
+        let name = self.name
+            .iter()
+            .filter_map(|&i| fields.get(i))
+            .map(|part| part.trim())
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ");
+        if name.is_empty() {
+            return None;
+        }
+
+        let mut values = Vec::new();
+        for column in &self.values {
+            let Some(cell) = fields.get(column.index) else { continue };
+            // Google marks the primary value "* Mobile"
+            let label = column.label_column
+                .and_then(|i| fields.get(i))
+                .map(|label| label.trim().trim_start_matches("* ").to_lowercase())
+                .filter(|label| !label.is_empty())
+                .or_else(|| column.label.clone());
+            // and separates several values in one cell with " ::: "
+            for value in cell.split(":::") {
+                push_value(&mut values, identifier(value, column.item_type, extractors), column.item_type, label.clone());
+            }
+        }
+
+        (!values.is_empty()).then_some(Contact { name, values })
+    }
+}
+
+fn header_label(column: &str) -> Option<String> {
+    let label = column
+        .split_whitespace()
+        .filter(|word| !VALUE_WORDS.contains(word) && !word.chars().all(|c| c.is_ascii_digit()))
+        .collect::<Vec<_>>()
+        .join(" ");
+    (!label.is_empty()).then_some(label)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(contact: &Contact) -> Vec<(&str, Option<&str>)> {
+        contact.values.iter().map(|v| (v.value.as_str(), v.label.as_deref())).collect()
+    }
+
+    #[test]
+    fn reads_cards_of_every_version() {
+        let text = "\u{FEFF}BEGIN:VCARD\r\n\
+            VERSION:3.0\r\n\
+            N:Doe;John;Q;Dr;\r\n\
+            TEL;TYPE=CELL,VOICE:(202) 555-0143\r\n\
+            item1.EMAIL;TYPE=INTERNET:John@Example.com\r\n\
+            PHOTO;ENCODING=b:MTIwMjU1NTAxNzgxMjAyNTU1MDE3\r\n \
+             ODEyMDI1NTUwMTc4\r\n\
+            END:VCARD\r\n\
+            BEGIN:VCARD\n\
+            VERSION:2.1\n\
+            FN;CHARSET=UTF-8;ENCODING=QUOTED-PRINTABLE:Zo=C3=AB =\n\
+            Smith\n\
+            TEL;WORK:312.555.0178\n\
+            END:VCARD\n\
+            BEGIN:VCARD\n\
+            VERSION:4.0\n\
+            FN:Support\n\
+            TEL;VALUE=uri;TYPE=\"voice,work\":tel:+44-20-7946-0958;ext=12\n\
+            END:VCARD\n";
+        assert!(is_vcard(text));
+
+        let records = read_vcards(text, &Extractors::new());
+        assert_eq!(records.len(), 3);
+
+        let john = records[0].contact.as_ref().unwrap();
+        assert_eq!(john.name, "Dr John Q Doe");
+        assert_eq!(values(john), [("+12025550143", Some("cell")), ("john@example.com", None)]);
+        // The photo's lines are not part of the text, so the record is not the file's bytes
+        assert!(!records[0].text.contains("MTIw"));
+        assert_eq!(records[0].byte_offset, None);
+
+        let zoe = records[1].contact.as_ref().unwrap();
+        assert_eq!(zoe.name, "Zoë Smith");
+        assert_eq!(values(zoe), [("+13125550178", Some("work"))]);
+        assert_eq!(records[1].line_number, 9);
+        assert!(records[1].byte_offset.is_some());
+
+        assert_eq!(values(records[2].contact.as_ref().unwrap()), [("+442079460958", Some("work"))]);
+    }
+
+    #[test]
+    fn cards_without_a_name_or_value_name_no_contact() {
+        let records = read_vcards("BEGIN:VCARD\nFN:Nobody\nEND:VCARD\nnote 202-555-0143\n", &Extractors::new());
+        assert!(records[0].contact.is_none());
+        assert_eq!(records[1].text, "note 202-555-0143");
+        assert!(!is_vcard("Name,Phone\n"));
+    }
+
+    #[test]
+    fn reads_contact_list_columns() {
+        let extractors = Extractors::new();
+        let outlook = ContactColumns::detect(&["First Name", "Last Name", "Mobile Phone", "Business Phone 2", "E-mail Address"]).unwrap();
+        let contact = outlook.read_row(&["Ann", "Lee", "202-555-0143", "", "ann@example.com"], &extractors).unwrap();
+        assert_eq!(contact.name, "Ann Lee");
+        assert_eq!(values(&contact), [("+12025550143", Some("mobile")), ("ann@example.com", None)]);
+
+        let google = ContactColumns::detect(&["Name", "Phone 1 - Type", "Phone 1 - Value"]).unwrap();
+        let contact = google.read_row(&["Bob", "* Mobile", "202-555-0143 ::: 312-555-0178"], &extractors).unwrap();
+        assert_eq!(values(&contact), [("+12025550143", Some("mobile")), ("+13125550178", Some("mobile"))]);
+
+        assert!(google.read_row(&["", "Mobile", "202-555-0143"], &extractors).is_none());
+        assert!(ContactColumns::detect(&["Calling Number", "Called Number"]).is_none());
+    }
+}
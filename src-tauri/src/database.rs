@@ -1,46 +1,75 @@
-use sqlx::{sqlite::SqlitePool, Row};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::ptr::NonNull;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use libsqlite3_sys as ffi;
+use sqlx::{
+    sqlite::{
+        LockedSqliteHandle, SqliteConnectOptions, SqliteConnection, SqliteJournalMode, SqlitePool, SqlitePoolOptions,
+        SqliteRow, SqliteSynchronous,
+    },
+    Column, Connection, QueryBuilder, Row, Sqlite, Transaction,
+};
+use crate::cast_json::{self, CaseMetadata, CastJson, Tile, TileId, TileMetadata, TileRow, TileRows};
+use crate::device_id;
 use crate::error::AppError;
+use crate::extractors::Extractors;
 use crate::models::*;
 
+// Clones share the same connection pool
+#[derive(Clone)]
 pub struct Database {
     pool: SqlitePool,
+    // Held by the open IngestBatch, so deliveries written at the same time take turns
+    writer: Arc<tokio::sync::Mutex<()>>,
+    _scratch: Option<Arc<ScratchFile>>,
 }
 
-impl Database {
-    pub async fn new_memory() -> Result<Self, AppError> {
-        let pool = SqlitePool::connect(":memory:").await?;
-        let db = Self { pool };
-        db.init_schema().await?;
-        Ok(db)
-    }
-    
-    pub async fn from_file(path: &str) -> Result<Self, AppError> {
-        let pool = SqlitePool::connect(&format!("sqlite:{}", path)).await?;
-        let db = Self { pool };
-        Ok(db)
+// Working file of a case that has not been saved yet, removed with the last handle on it
+struct ScratchFile {
+    path: PathBuf,
+}
+
+impl Drop for ScratchFile {
+    fn drop(&mut self) {
+        for suffix in ["", "-wal", "-shm"] {
+            let mut path = self.path.clone().into_os_string();
+            path.push(suffix);
+            let _ = std::fs::remove_file(path);
+        }
     }
-    
-    pub async fn save_to_file(&self, path: &str) -> Result<(), AppError> {
-        // SQLite backup using simple approach for in-memory database
-        let backup_conn = SqlitePool::connect(&format!("sqlite:{}", path)).await?;
-        
-        // Create schema in backup
-        sqlx::query(
-            r#"
+}
+
+static SCRATCH_FILES: AtomicU64 = AtomicU64::new(0);
+
+// Rows per multi-row INSERT. Full batches share one statement text, which sqlx prepares
+// once per connection and reuses; 500 rows stay well under SQLite's bound-parameter limit.
+const BATCH_ROWS: usize = 500;
+
+// Version written by this build; cases from newer builds are refused rather than misread
+pub const SCHEMA_VERSION: i64 = 13;
+
+struct Migration {
+    version: i64,
+    description: &'static str,
+    sql: &'static str,
+}
+
+// Append only: a released migration is never edited, a new version is added instead
+static MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "files and extracted items",
+        sql: r#"
             CREATE TABLE IF NOT EXISTS files (
                 id INTEGER PRIMARY KEY AUTOINCREMENT,
                 file_name TEXT NOT NULL,
                 source_id INTEGER NOT NULL,
                 content TEXT NOT NULL,
                 processed_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
-            )
-            "#
-        )
-        .execute(&backup_conn)
-        .await?;
-        
-        sqlx::query(
-            r#"
+            );
             CREATE TABLE IF NOT EXISTS extracted_items (
                 id INTEGER PRIMARY KEY AUTOINCREMENT,
                 value TEXT NOT NULL,
@@ -48,90 +77,538 @@ impl Database {
                 source_id INTEGER NOT NULL,
                 file_id INTEGER NOT NULL,
                 FOREIGN KEY (file_id) REFERENCES files(id)
-            )
-            "#
-        )
-        .execute(&backup_conn)
-        .await?;
+            );
+            CREATE INDEX IF NOT EXISTS idx_extracted_value ON extracted_items(value);
+            CREATE INDEX IF NOT EXISTS idx_extracted_type ON extracted_items(item_type);
+            CREATE INDEX IF NOT EXISTS idx_extracted_source ON extracted_items(source_id);
+        "#,
+    },
+    Migration {
+        version: 2,
+        description: "item attributes",
+        sql: "ALTER TABLE extracted_items ADD COLUMN attributes TEXT;",
+    },
+    Migration {
+        version: 3,
+        description: "occurrences",
+        sql: r#"
+            CREATE TABLE IF NOT EXISTS occurrences (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                item_id INTEGER NOT NULL,
+                file_id INTEGER NOT NULL,
+                line_number INTEGER NOT NULL,
+                column_number INTEGER NOT NULL,
+                byte_offset INTEGER,
+                row_data TEXT NOT NULL,
+                FOREIGN KEY (item_id) REFERENCES extracted_items(id),
+                FOREIGN KEY (file_id) REFERENCES files(id)
+            );
+            CREATE INDEX IF NOT EXISTS idx_occurrences_item ON occurrences(item_id);
+        "#,
+    },
+    Migration {
+        version: 4,
+        description: "named sources",
+        sql: r#"
+            CREATE TABLE IF NOT EXISTS sources (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name TEXT NOT NULL,
+                provider TEXT,
+                warrant_number TEXT,
+                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+            );
+            INSERT OR IGNORE INTO sources (id, name)
+                SELECT DISTINCT source_id, 'Source ' || source_id FROM files;
+            INSERT OR IGNORE INTO sources (id, name)
+                SELECT DISTINCT source_id, 'Source ' || source_id FROM extracted_items;
+        "#,
+    },
+    Migration {
+        version: 5,
+        description: "occurrence roles",
+        sql: "ALTER TABLE occurrences ADD COLUMN role TEXT;",
+    },
+    Migration {
+        version: 6,
+        description: "communication events",
+        sql: r#"
+            CREATE TABLE IF NOT EXISTS events (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                source_id INTEGER NOT NULL,
+                file_id INTEGER NOT NULL,
+                line_number INTEGER,
+                event_type TEXT NOT NULL,
+                direction TEXT,
+                from_party TEXT,
+                to_party TEXT,
+                start_time TEXT,
+                start_utc TEXT,
+                local_time INTEGER NOT NULL DEFAULT 0,
+                duration_seconds INTEGER,
+                cell_site TEXT,
+                sector TEXT,
+                imei TEXT,
+                imsi TEXT,
+                FOREIGN KEY (file_id) REFERENCES files(id)
+            );
+            CREATE INDEX IF NOT EXISTS idx_events_from ON events(from_party);
+            CREATE INDEX IF NOT EXISTS idx_events_to ON events(to_party);
+            CREATE INDEX IF NOT EXISTS idx_events_start ON events(start_utc);
+            CREATE INDEX IF NOT EXISTS idx_events_source ON events(source_id);
+            CREATE INDEX IF NOT EXISTS idx_events_row ON events(file_id, line_number);
+        "#,
+    },
+    Migration {
+        version: 7,
+        description: "CAST_JSON case and tile metadata",
+        sql: r#"
+            CREATE TABLE IF NOT EXISTS case_metadata (
+                key TEXT PRIMARY KEY,
+                value TEXT NOT NULL
+            );
+            ALTER TABLE sources ADD COLUMN metadata TEXT;
+            ALTER TABLE files ADD COLUMN listed INTEGER NOT NULL DEFAULT 1;
+            ALTER TABLE occurrences ADD COLUMN details TEXT;
+        "#,
+    },
+    Migration {
+        version: 8,
+        description: "spreadsheet sheet names",
+        sql: r#"
+            ALTER TABLE occurrences ADD COLUMN sheet_name TEXT;
+            ALTER TABLE events ADD COLUMN sheet_name TEXT;
+        "#,
+    },
+    Migration {
+        version: 9,
+        description: "file metadata and PDF pages",
+        sql: r#"
+            ALTER TABLE files ADD COLUMN metadata TEXT;
+            ALTER TABLE occurrences ADD COLUMN page_number INTEGER;
+        "#,
+    },
+    Migration {
+        version: 10,
+        description: "archive members",
+        sql: r#"
+            ALTER TABLE files ADD COLUMN parent_id INTEGER REFERENCES files(id);
+            CREATE INDEX IF NOT EXISTS idx_files_parent ON files(parent_id);
+        "#,
+    },
+    Migration {
+        version: 11,
+        description: "out-of-line file content",
+        sql: "ALTER TABLE files ADD COLUMN content_path TEXT;",
+    },
+    Migration {
+        version: 12,
+        description: "message recipients and attachments",
+        sql: r#"
+            ALTER TABLE events ADD COLUMN recipients TEXT;
+            ALTER TABLE events ADD COLUMN attachments TEXT;
+        "#,
+    },
+    Migration {
+        version: 13,
+        description: "address-book contacts",
+        sql: r#"
+            CREATE TABLE IF NOT EXISTS contacts (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                source_id INTEGER NOT NULL,
+                file_id INTEGER NOT NULL,
+                line_number INTEGER,
+                name TEXT NOT NULL,
+                value TEXT NOT NULL,
+                item_type TEXT NOT NULL,
+                label TEXT,
+                FOREIGN KEY (file_id) REFERENCES files(id)
+            );
+            CREATE INDEX IF NOT EXISTS idx_contacts_value ON contacts(value, item_type);
+            CREATE INDEX IF NOT EXISTS idx_contacts_source ON contacts(source_id);
+        "#,
+    },
+];
+
+impl Database {
+    // A new case lives in a temp file until it is saved. In WAL mode readers keep seeing
+    // the last committed state while an ingestion holds the write transaction, where a
+    // single in-memory connection would leave every other command waiting for the pool.
+    pub async fn new_scratch() -> Result<Self, AppError> {
+        let path = std::env::temp_dir().join(format!(
+            "cast-case-{}-{}.db",
+            std::process::id(),
+            SCRATCH_FILES.fetch_add(1, Ordering::Relaxed)
+        ));
+        let scratch = Arc::new(ScratchFile { path });
         
-        // Copy files data
-        let files = self.get_all_files().await?;
-        for file in files {
-            sqlx::query(
-                "INSERT INTO files (file_name, source_id, content, processed_at) VALUES (?, ?, ?, ?)"
-            )
-            .bind(&file.file_name)
-            .bind(file.source_id)
-            .bind(&file.content)
-            .bind(file.processed_at)
-            .execute(&backup_conn)
+        // Nothing in the file needs to survive a crash, so writes are never synced
+        let options = SqliteConnectOptions::new()
+            .filename(&scratch.path)
+            .create_if_missing(true)
+            .journal_mode(SqliteJournalMode::Wal)
+            .synchronous(SqliteSynchronous::Off);
+        Self::connect(options, Some(scratch)).await
+    }
+    
+    // Older cases are upgraded in place; foreign or newer databases are refused
+    pub async fn from_file(path: &str) -> Result<Self, AppError> {
+        Self::connect(SqliteConnectOptions::new().filename(path), None).await
+    }
+    
+    // Migrations run on a connection of their own that is closed before the case is used;
+    // a pooled connection that had read the schema before an ALTER TABLE would still
+    // expand "SELECT *" to the old columns
+    async fn connect(options: SqliteConnectOptions, scratch: Option<Arc<ScratchFile>>) -> Result<Self, AppError> {
+        let migrator = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect_with(options.clone())
             .await?;
+        let db = Self { pool: migrator, writer: Arc::default(), _scratch: scratch };
+        db.migrate().await?;
+        db.pool.close().await;
+        
+        Ok(Self { pool: SqlitePool::connect_with(options).await?, ..db })
+    }
+    
+    // Opens a saved case for reading only; one from an older build is refused rather than
+    // upgraded, so reports never rewrite the file they read
+    pub async fn from_file_read_only(path: &str) -> Result<Self, AppError> {
+        let options = SqliteConnectOptions::new().filename(path).read_only(true);
+        let db = Self { pool: SqlitePool::connect_with(options).await?, writer: Arc::default(), _scratch: None };
+        
+        let found = db.schema_version().await?;
+        if found > SCHEMA_VERSION {
+            return Err(AppError::SchemaTooNew { found, supported: SCHEMA_VERSION });
+        }
+        if found < SCHEMA_VERSION {
+            return Err(AppError::SchemaOutdated { found, current: SCHEMA_VERSION });
+        }
+        Ok(db)
+    }
+    
+    // Opens a saved case; a legacy CAST database or a CAST_JSON document is imported into
+    // a new case instead, leaving the original file untouched. `region` reads
+    // legacy phone numbers.
+    pub async fn open(path: &str, region: &str) -> Result<Self, AppError> {
+        if cast_json::looks_like_cast_json(path)? {
+            let db = Self::new_scratch().await?;
+            db.import_cast_json(&cast_json::read(path)?).await?;
+            return Ok(db);
         }
         
-        // Copy extracted items
-        let items_query = sqlx::query("SELECT * FROM extracted_items")
-            .fetch_all(&self.pool)
+        let options = SqliteConnectOptions::new()
+            .filename(path)
+            .read_only(true);
+        let legacy = SqlitePool::connect_with(options).await?;
+        
+        if is_legacy_database(&legacy).await? {
+            let db = Self::new_scratch().await?;
+            db.import_legacy(&legacy, &Extractors::with_region(region)?).await?;
+            legacy.close().await;
+            return Ok(db);
+        }
+        
+        legacy.close().await;
+        Self::from_file(path).await
+    }
+    
+    pub async fn save_to_file(&self, path: &str) -> Result<(), AppError> {
+        self.save_to_file_with_progress(path, |_| {}).await
+    }
+    
+    // Snapshots the case with the SQLite online backup API into a temp file next to `path`,
+    // checks its integrity and only then renames it over `path`, so a failed save never
+    // leaves a partial case behind and ids, indexes and foreign keys are kept as they are
+    pub async fn save_to_file_with_progress<F>(&self, path: &str, mut progress: F) -> Result<(), AppError>
+    where
+        F: FnMut(SaveProgress),
+    {
+        let temp_path = format!("{}.tmp", path);
+        if Path::new(&temp_path).exists() {
+            std::fs::remove_file(&temp_path)?;
+        }
+        
+        let result = self.write_snapshot(&temp_path, &mut progress).await;
+        if let Err(e) = result {
+            let _ = std::fs::remove_file(&temp_path);
+            return Err(e);
+        }
+        
+        std::fs::rename(&temp_path, path)?;
+        progress(SaveProgress {
+            stage: SaveStage::Done,
+            pages_copied: 0,
+            pages_total: 0,
+        });
+        
+        Ok(())
+    }
+    
+    async fn write_snapshot<F>(&self, temp_path: &str, progress: &mut F) -> Result<(), AppError>
+    where
+        F: FnMut(SaveProgress),
+    {
+        // copy_pages paces its own retries, so a locked target fails each step at once
+        let options = SqliteConnectOptions::new()
+            .filename(temp_path)
+            .create_if_missing(true)
+            .busy_timeout(std::time::Duration::ZERO);
+        let mut target = SqliteConnection::connect_with(&options).await?;
+        let mut source = self.pool.acquire().await?;
+        
+        {
+            let mut source_handle = source.lock_handle().await?;
+            let mut target_handle = target.lock_handle().await?;
+            copy_pages(&mut source_handle, &mut target_handle, progress).await?;
+        }
+        
+        progress(SaveProgress {
+            stage: SaveStage::Verifying,
+            pages_copied: 0,
+            pages_total: 0,
+        });
+        
+        let check: String = sqlx::query_scalar("PRAGMA integrity_check")
+            .fetch_one(&mut target)
             .await?;
-            
-        for row in items_query {
+        target.close().await?;
+        
+        if check != "ok" {
+            return Err(AppError::Backup(format!("Integrity check failed: {}", check)));
+        }
+        
+        Ok(())
+    }
+    
+    // Brings the case up to SCHEMA_VERSION, one migration per transaction
+    async fn migrate(&self) -> Result<(), AppError> {
+        let current = self.schema_version().await?;
+        if current > SCHEMA_VERSION {
+            return Err(AppError::SchemaTooNew {
+                found: current,
+                supported: SCHEMA_VERSION,
+            });
+        }
+        
+        // A case saved before versioning records the version its tables were inferred as
+        if !table_names(&self.pool).await?.iter().any(|t| t == "schema_version") {
             sqlx::query(
-                "INSERT INTO extracted_items (value, item_type, source_id, file_id) VALUES (?, ?, ?, ?)"
+                r#"
+                CREATE TABLE schema_version (
+                    version INTEGER NOT NULL,
+                    description TEXT NOT NULL,
+                    applied_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+                )
+                "#
             )
-            .bind(row.get::<String, _>("value"))
-            .bind(row.get::<String, _>("item_type"))
-            .bind(row.get::<i32, _>("source_id"))
-            .bind(row.get::<i64, _>("file_id"))
-            .execute(&backup_conn)
+            .execute(&self.pool)
             .await?;
+            
+            if current > 0 {
+                sqlx::query("INSERT INTO schema_version (version, description) VALUES (?, 'inferred from existing tables')")
+                    .bind(current)
+                    .execute(&self.pool)
+                    .await?;
+            }
+        }
+        
+        for migration in MIGRATIONS.iter().filter(|m| m.version > current) {
+            let mut tx = self.pool.begin().await?;
+            
+            sqlx::query(migration.sql)
+                .execute(&mut *tx)
+                .await?;
+                
+            sqlx::query("INSERT INTO schema_version (version, description) VALUES (?, ?)")
+                .bind(migration.version)
+                .bind(migration.description)
+                .execute(&mut *tx)
+                .await?;
+                
+            tx.commit().await?;
         }
         
         Ok(())
     }
     
-    async fn init_schema(&self) -> Result<(), AppError> {
+    // Recorded version, or the one inferred from the tables of a case saved before versioning
+    async fn schema_version(&self) -> Result<i64, AppError> {
+        let tables = table_names(&self.pool).await?;
+        let has_table = |name: &str| tables.iter().any(|t| t == name);
+        
+        if !has_table("schema_version") {
+            let version = if tables.iter().all(|t| t.starts_with("sqlite_")) {
+                0
+            } else if !has_table("files") || !has_table("extracted_items") {
+                return Err(AppError::UnrecognizedDatabase);
+            } else if has_table("events") {
+                6
+            } else if self.has_column("occurrences", "role").await? {
+                5
+            } else if has_table("sources") {
+                4
+            } else if has_table("occurrences") {
+                3
+            } else if self.has_column("extracted_items", "attributes").await? {
+                2
+            } else {
+                1
+            };
+            return Ok(version);
+        }
+        
+        let version: Option<i64> = sqlx::query_scalar("SELECT MAX(version) FROM schema_version")
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(version.unwrap_or(0))
+    }
+    
+    async fn has_column(&self, table: &str, column: &str) -> Result<bool, AppError> {
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM pragma_table_info(?) WHERE name = ?")
+            .bind(table)
+            .bind(column)
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(count > 0)
+    }
+    
+    // Files may name a source id the case has not seen yet; it gets a placeholder name
+    pub async fn ensure_source(&self, source_id: i32) -> Result<(), AppError> {
         sqlx::query(
-            r#"
-            CREATE TABLE IF NOT EXISTS files (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                file_name TEXT NOT NULL,
-                source_id INTEGER NOT NULL,
-                content TEXT NOT NULL,
-                processed_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
-            )
-            "#
+            "INSERT OR IGNORE INTO sources (id, name) VALUES (?, 'Source ' || ?)"
         )
+        .bind(source_id)
+        .bind(source_id)
         .execute(&self.pool)
         .await?;
         
-        sqlx::query(
-            r#"
-            CREATE TABLE IF NOT EXISTS extracted_items (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                value TEXT NOT NULL,
-                item_type TEXT NOT NULL,
-                source_id INTEGER NOT NULL,
-                file_id INTEGER NOT NULL,
-                FOREIGN KEY (file_id) REFERENCES files(id)
-            )
-            "#
+        Ok(())
+    }
+    
+    pub async fn create_source(
+        &self,
+        name: &str,
+        provider: Option<&str>,
+        warrant_number: Option<&str>,
+    ) -> Result<Source, AppError> {
+        let result = sqlx::query(
+            "INSERT INTO sources (name, provider, warrant_number) VALUES (?, ?, ?)"
         )
+        .bind(name)
+        .bind(provider)
+        .bind(warrant_number)
         .execute(&self.pool)
         .await?;
         
+        self.get_source(result.last_insert_rowid() as i32).await
+    }
+    
+    pub async fn get_source(&self, source_id: i32) -> Result<Source, AppError> {
+        let row = sqlx::query(
+            "SELECT id, name, provider, warrant_number, created_at FROM sources WHERE id = ?"
+        )
+        .bind(source_id)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or(AppError::SourceNotFound(source_id))?;
+        
+        Ok(source_from_row(&row))
+    }
+    
+    pub async fn find_source_by_name(&self, name: &str) -> Result<Option<Source>, AppError> {
+        let row = sqlx::query(
+            "SELECT id, name, provider, warrant_number, created_at FROM sources WHERE name = ? ORDER BY id LIMIT 1"
+        )
+        .bind(name)
+        .fetch_optional(&self.pool)
+        .await?;
+        
+        Ok(row.as_ref().map(source_from_row))
+    }
+    
+    pub async fn list_sources(&self) -> Result<Vec<Source>, AppError> {
+        let rows = sqlx::query(
+            "SELECT id, name, provider, warrant_number, created_at FROM sources ORDER BY id"
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        
+        Ok(rows.iter().map(source_from_row).collect())
+    }
+    
+    pub async fn rename_source(&self, source_id: i32, name: &str) -> Result<Source, AppError> {
+        sqlx::query("UPDATE sources SET name = ? WHERE id = ?")
+            .bind(name)
+            .bind(source_id)
+            .execute(&self.pool)
+            .await?;
+            
+        self.get_source(source_id).await
+    }
+    
+    pub async fn update_source(
+        &self,
+        source_id: i32,
+        name: &str,
+        provider: Option<&str>,
+        warrant_number: Option<&str>,
+    ) -> Result<Source, AppError> {
+        sqlx::query("UPDATE sources SET name = ?, provider = ?, warrant_number = ? WHERE id = ?")
+            .bind(name)
+            .bind(provider)
+            .bind(warrant_number)
+            .bind(source_id)
+            .execute(&self.pool)
+            .await?;
+            
+        self.get_source(source_id).await
+    }
+    
+    // Removes the source together with its files, extracted items, occurrences, events and contacts
+    pub async fn delete_source(&self, source_id: i32) -> Result<(), AppError> {
+        self.get_source(source_id).await?;
+        
+        let mut tx = self.pool.begin().await?;
+        
+        sqlx::query("DELETE FROM events WHERE source_id = ?")
+            .bind(source_id)
+            .execute(&mut *tx)
+            .await?;
+        
+        sqlx::query("DELETE FROM contacts WHERE source_id = ?")
+            .bind(source_id)
+            .execute(&mut *tx)
+            .await?;
+        
         sqlx::query(
-            r#"
-            CREATE INDEX IF NOT EXISTS idx_extracted_value ON extracted_items(value);
-            CREATE INDEX IF NOT EXISTS idx_extracted_type ON extracted_items(item_type);
-            CREATE INDEX IF NOT EXISTS idx_extracted_source ON extracted_items(source_id);
-            "#
+            "DELETE FROM occurrences WHERE item_id IN (SELECT id FROM extracted_items WHERE source_id = ?)"
         )
-        .execute(&self.pool)
+        .bind(source_id)
+        .execute(&mut *tx)
         .await?;
         
+        sqlx::query("DELETE FROM extracted_items WHERE source_id = ?")
+            .bind(source_id)
+            .execute(&mut *tx)
+            .await?;
+            
+        sqlx::query("DELETE FROM files WHERE source_id = ?")
+            .bind(source_id)
+            .execute(&mut *tx)
+            .await?;
+            
+        sqlx::query("DELETE FROM sources WHERE id = ?")
+            .bind(source_id)
+            .execute(&mut *tx)
+            .await?;
+            
+        tx.commit().await?;
         Ok(())
     }
     
     pub async fn insert_file(&self, file_name: &str, source_id: i32, content: &str) -> Result<i64, AppError> {
+        self.ensure_source(source_id).await?;
+        
         let result = sqlx::query(
             "INSERT INTO files (file_name, source_id, content) VALUES (?, ?, ?)"
         )
@@ -144,101 +621,805 @@ impl Database {
         Ok(result.last_insert_rowid())
     }
     
-    pub async fn insert_extracted_item(&self, value: &str, item_type: &str, source_id: i32, file_id: i64) -> Result<(), AppError> {
-        sqlx::query(
-            "INSERT INTO extracted_items (value, item_type, source_id, file_id) VALUES (?, ?, ?, ?)"
+    // Starts writing one delivery to `source_id`; nothing is visible until it is committed
+    pub async fn begin_batch(&self, source_id: i32) -> Result<IngestBatch, AppError> {
+        let writer = self.writer.clone().lock_owned().await;
+        let mut tx = self.pool.begin().await?;
+        
+        // The first write takes SQLite's write lock, so the ids read next stay free
+        sqlx::query("INSERT OR IGNORE INTO sources (id, name) VALUES (?, 'Source ' || ?)")
+            .bind(source_id)
+            .bind(source_id)
+            .execute(&mut *tx)
+            .await?;
+        
+        // AUTOINCREMENT never reuses an id, even one whose row was deleted
+        let next_item_id: i64 = sqlx::query_scalar(
+            r#"
+            SELECT MAX(COALESCE((SELECT seq FROM sqlite_sequence WHERE name = 'extracted_items'), 0),
+                       COALESCE((SELECT MAX(id) FROM extracted_items), 0)) + 1
+            "#
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        
+        Ok(IngestBatch {
+            tx,
+            _writer: writer,
+            source_id,
+            next_item_id,
+            items: Vec::new(),
+            occurrences: Vec::new(),
+            events: Vec::new(),
+            contacts: Vec::new(),
+        })
+    }
+    
+    pub async fn insert_extracted_item(
+        &self,
+        value: &str,
+        item_type: &str,
+        source_id: i32,
+        file_id: i64,
+        attributes: Option<&ItemAttributes>,
+    ) -> Result<i64, AppError> {
+        let attributes = attributes
+            .map(serde_json::to_string)
+            .transpose()?;
+        
+        let result = sqlx::query(
+            "INSERT INTO extracted_items (value, item_type, source_id, file_id, attributes) VALUES (?, ?, ?, ?, ?)"
         )
         .bind(value)
         .bind(item_type)
         .bind(source_id)
         .bind(file_id)
+        .bind(attributes)
+        .execute(&self.pool)
+        .await?;
+        
+        Ok(result.last_insert_rowid())
+    }
+    
+    #[allow(clippy::too_many_arguments)]
+    pub async fn insert_occurrence(
+        &self,
+        item_id: i64,
+        file_id: i64,
+        record: &SourceRecord,
+        line_number: i64,
+        column_number: i64,
+        byte_offset: Option<i64>,
+        role: Option<&str>,
+    ) -> Result<(), AppError> {
+        sqlx::query(
+            "INSERT INTO occurrences (item_id, file_id, sheet_name, page_number, line_number, column_number, byte_offset, row_data, role) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)"
+        )
+        .bind(item_id)
+        .bind(file_id)
+        .bind(&record.sheet_name)
+        .bind(record.page_number)
+        .bind(line_number)
+        .bind(column_number)
+        .bind(byte_offset)
+        .bind(&record.text)
+        .bind(role)
         .execute(&self.pool)
         .await?;
         
         Ok(())
     }
     
-    pub async fn get_extracted_items_by_type(&self, item_type: &str) -> Result<Vec<ExtractedItem>, AppError> {
+    // Every recorded hit of `value`, optionally narrowed to one item type, in file order
+    pub async fn get_occurrences(&self, value: &str, item_type: Option<&str>) -> Result<Vec<Occurrence>, AppError> {
         let rows = sqlx::query(
             r#"
-            SELECT value, source_id, COUNT(*) as count
-            FROM extracted_items
-            WHERE item_type = ?
-            GROUP BY value, source_id
-            ORDER BY count DESC, value
+            SELECT e.value, e.item_type, o.file_id, f.file_name, e.source_id, o.sheet_name, o.page_number,
+                   o.line_number, o.column_number, o.byte_offset, o.row_data, o.role, ev.start_time
+            FROM occurrences o
+            JOIN extracted_items e ON e.id = o.item_id
+            JOIN files f ON f.id = o.file_id
+            LEFT JOIN events ev ON ev.file_id = o.file_id AND ev.sheet_name IS o.sheet_name AND ev.line_number = o.line_number
+            WHERE e.value = ? AND (? IS NULL OR e.item_type = ?)
+            ORDER BY e.source_id, o.file_id, o.id
             "#
         )
+        .bind(value)
+        .bind(item_type)
         .bind(item_type)
         .fetch_all(&self.pool)
         .await?;
         
-        let items = rows.into_iter().map(|row| {
-            ExtractedItem {
-                value: row.get("value"),
-                source: row.get("source_id"),
-                count: row.get("count"),
-                item_type: match item_type {
-                    "phone" => ItemType::Phone,
-                    "email" => ItemType::Email,
-                    "ip" => ItemType::Ip,
-                    _ => ItemType::Phone,
-                },
+        Ok(rows.iter().map(occurrence_from_row).collect())
+    }
+    
+    // Identifiers seen at `min_sites` or more of the given tower dumps, each with the
+    // records that place it at every matching site
+    pub async fn get_tower_intersection(
+        &self,
+        sites: &[TowerSite],
+        min_sites: usize,
+        item_type: Option<&str>,
+    ) -> Result<Vec<TowerIntersectionItem>, AppError> {
+        if sites.is_empty() {
+            return Err(AppError::General("No tower sites given".to_string()));
+        }
+        if min_sites == 0 || min_sites > sites.len() {
+            return Err(AppError::General(format!(
+                "Minimum site count must be between 1 and {}", sites.len()
+            )));
+        }
+        
+        let mut matches: HashMap<(String, ItemType), Vec<TowerSiteMatch>> = HashMap::new();
+        
+        for (index, site) in sites.iter().enumerate() {
+            let source = self.get_source(site.source_id).await?;
+            let (start, end) = utc_range(site.start.as_deref(), site.end.as_deref())?;
+            
+            // With a window set, only rows read as carrier records carry a time to test
+            let rows = sqlx::query(
+                r#"
+                SELECT e.value, e.item_type, o.file_id, f.file_name, e.source_id, o.sheet_name, o.page_number,
+                       o.line_number, o.column_number, o.byte_offset, o.row_data, o.role, ev.start_time
+                FROM occurrences o
+                JOIN extracted_items e ON e.id = o.item_id
+                JOIN files f ON f.id = o.file_id
+                LEFT JOIN events ev ON ev.file_id = o.file_id AND ev.sheet_name IS o.sheet_name AND ev.line_number = o.line_number
+                WHERE e.source_id = ?1 AND (?2 IS NULL OR e.item_type = ?2)
+                  AND (?3 IS NULL OR ev.start_utc >= ?3)
+                  AND (?4 IS NULL OR ev.start_utc <= ?4)
+                ORDER BY o.file_id, o.id
+                "#
+            )
+            .bind(site.source_id)
+            .bind(item_type)
+            .bind(start)
+            .bind(end)
+            .fetch_all(&self.pool)
+            .await?;
+            
+            for occurrence in rows.iter().map(occurrence_from_row) {
+                let site_matches = matches
+                    .entry((occurrence.value.clone(), occurrence.item_type))
+                    .or_default();
+                
+                if site_matches.last().map(|m| m.site) != Some(index) {
+                    site_matches.push(TowerSiteMatch {
+                        site: index,
+                        label: site.label.clone().unwrap_or_else(|| format!("Site {}: {}", index + 1, source.name)),
+                        source: SourceRef { id: source.id, name: source.name.clone() },
+                        records: Vec::new(),
+                    });
+                }
+                if let Some(site_match) = site_matches.last_mut() {
+                    site_match.records.push(occurrence);
+                }
             }
-        }).collect();
+        }
+        
+        let mut items: Vec<TowerIntersectionItem> = matches
+            .into_iter()
+            .filter(|(_, site_matches)| site_matches.len() >= min_sites)
+            .map(|((value, item_type), site_matches)| TowerIntersectionItem {
+                value,
+                item_type,
+                site_count: site_matches.len(),
+                sites: site_matches,
+            })
+            .collect();
+        items.sort_by(|a, b| b.site_count.cmp(&a.site_count).then_with(|| a.value.cmp(&b.value)));
         
         Ok(items)
     }
     
-    pub async fn get_cross_reference_items(&self) -> Result<Vec<ExtractedItem>, AppError> {
-        let rows = sqlx::query(
+    pub async fn insert_event(&self, event: &CommEvent) -> Result<i64, AppError> {
+        let start_utc = event.start_time.as_deref().map(utc_key).transpose()?;
+        
+        let result = sqlx::query(
             r#"
-            SELECT e1.value, COUNT(DISTINCT e1.source_id) as source_count, COUNT(*) as total_count
-            FROM extracted_items e1
-            WHERE EXISTS (
-                SELECT 1 FROM extracted_items e2
-                WHERE e1.value = e2.value AND e1.source_id != e2.source_id
-            )
-            GROUP BY e1.value
-            HAVING source_count > 1
-            ORDER BY total_count DESC, e1.value
+            INSERT INTO events (source_id, file_id, sheet_name, line_number, event_type, direction, from_party, to_party,
+                                start_time, start_utc, local_time, duration_seconds, cell_site, sector, imei, imsi, recipients, attachments)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
             "#
         )
-        .fetch_all(&self.pool)
+        .bind(event.source_id)
+        .bind(event.file_id)
+        .bind(&event.sheet_name)
+        .bind(event.line_number)
+        .bind(event.event_type.as_str())
+        .bind(event.direction.map(|d| d.as_str()))
+        .bind(&event.from)
+        .bind(&event.to)
+        .bind(&event.start_time)
+        .bind(start_utc)
+        .bind(event.local_time)
+        .bind(event.duration_seconds)
+        .bind(&event.cell_site)
+        .bind(&event.sector)
+        .bind(&event.imei)
+        .bind(&event.imsi)
+        .bind(json_list(&event.recipients)?)
+        .bind(json_list(&event.attachments)?)
+        .execute(&self.pool)
         .await?;
         
-        let items = rows.into_iter().map(|row| {
-            ExtractedItem {
-                value: row.get("value"),
-                source: 0,
-                count: row.get("total_count"),
-                item_type: ItemType::Phone,
-            }
-        }).collect();
-        
-        Ok(items)
+        Ok(result.last_insert_rowid())
     }
     
-    pub async fn get_all_files(&self) -> Result<Vec<FileRecord>, AppError> {
+    // Events matching every set field of `filter`, in time order
+    pub async fn get_events(&self, filter: &EventFilter) -> Result<Vec<CommEvent>, AppError> {
+        let (start, end) = utc_range(filter.start.as_deref(), filter.end.as_deref())?;
+        
         let rows = sqlx::query(
-            "SELECT id, file_name, source_id, content, processed_at FROM files ORDER BY id"
+            r#"
+            SELECT ev.*, f.file_name
+            FROM events ev
+            JOIN files f ON f.id = ev.file_id
+            WHERE (?1 IS NULL OR ev.from_party = ?1 OR ev.to_party = ?1 OR ev.imei = ?1 OR ev.imsi = ?1
+                   OR EXISTS (SELECT 1 FROM json_each(ev.recipients) WHERE json_each.value = ?1))
+              AND (?2 IS NULL OR ev.start_utc >= ?2)
+              AND (?3 IS NULL OR ev.start_utc <= ?3)
+              AND (?4 IS NULL OR ev.source_id = ?4)
+            ORDER BY ev.start_utc, ev.id
+            "#
         )
+        .bind(&filter.party)
+        .bind(start)
+        .bind(end)
+        .bind(filter.source_id)
         .fetch_all(&self.pool)
         .await?;
         
-        let files = rows.into_iter().map(|row| {
-            FileRecord {
+        let events = rows.into_iter().map(|row| {
+            CommEvent {
                 id: Some(row.get("id")),
-                file_name: row.get("file_name"),
                 source_id: row.get("source_id"),
-                content: row.get("content"),
+                file_id: row.get("file_id"),
+                file_name: row.get("file_name"),
+                sheet_name: row.get("sheet_name"),
+                line_number: row.get("line_number"),
+                event_type: row.get::<String, _>("event_type").parse().unwrap_or(EventType::Call),
+                direction: row.get::<Option<String>, _>("direction").and_then(|d| d.parse().ok()),
+                from: row.get("from_party"),
+                to: row.get("to_party"),
+                start_time: row.get("start_time"),
+                local_time: row.get("local_time"),
+                duration_seconds: row.get("duration_seconds"),
+                cell_site: row.get("cell_site"),
+                sector: row.get("sector"),
+                imei: row.get("imei"),
+                imsi: row.get("imsi"),
+                recipients: parse_list(row.get("recipients")),
+                attachments: parse_list(row.get("attachments")),
+            }
+        }).collect();
+        
+        Ok(events)
+    }
+    
+    pub async fn get_extracted_items_by_type(&self, item_type: &str) -> Result<Vec<ExtractedItem>, AppError> {
+        let rows = sqlx::query(
+            r#"
+            SELECT value, source_id, COUNT(*) as count, MAX(attributes) as attributes
+            FROM extracted_items
+            WHERE item_type = ?
+            GROUP BY value, source_id
+            ORDER BY count DESC, value
+            "#
+        )
+        .bind(item_type)
+        .fetch_all(&self.pool)
+        .await?;
+        
+        let aliases = self.get_aliases().await?;
+        
+        let items = rows.into_iter().map(|row| {
+            let value: String = row.get("value");
+            ExtractedItem {
+                aliases: aliases.get(&(item_type.to_string(), value.clone())).cloned().unwrap_or_default(),
+                value,
+                source: row.get("source_id"),
+                count: row.get("count"),
+                attributes: parse_attributes(row.get("attributes")),
+                item_type: item_type.parse().unwrap_or(ItemType::Phone),
+            }
+        }).collect();
+        
+        Ok(items)
+    }
+    
+    // Values seen in more than one source, with exactly which sources each appears in
+    pub async fn get_cross_reference_items(&self) -> Result<Vec<CrossReferenceItem>, AppError> {
+        let rows = sqlx::query(
+            r#"
+            SELECT value, item_type, COUNT(*) as total_count, MAX(attributes) as attributes,
+                   GROUP_CONCAT(DISTINCT source_id) as source_ids
+            FROM extracted_items
+            GROUP BY value, item_type
+            HAVING COUNT(DISTINCT source_id) > 1
+            ORDER BY total_count DESC, value
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        
+        let names: HashMap<i32, String> = self.list_sources().await?
+            .into_iter()
+            .map(|source| (source.id, source.name))
+            .collect();
+        let aliases = self.get_aliases().await?;
+        
+        let items = rows.into_iter().map(|row| {
+            let mut source_ids: Vec<i32> = row.get::<String, _>("source_ids")
+                .split(',')
+                .filter_map(|id| id.parse().ok())
+                .collect();
+            source_ids.sort_unstable();
+            
+            let value: String = row.get("value");
+            let item_type: String = row.get("item_type");
+            
+            CrossReferenceItem {
+                aliases: aliases.get(&(item_type.clone(), value.clone())).cloned().unwrap_or_default(),
+                value,
+                item_type: item_type.parse().unwrap_or(ItemType::Phone),
+                count: row.get("total_count"),
+                attributes: parse_attributes(row.get("attributes")),
+                sources: source_ids.into_iter().map(|id| SourceRef {
+                    id,
+                    name: names.get(&id).cloned().unwrap_or_else(|| format!("Source {}", id)),
+                }).collect(),
+            }
+        }).collect();
+        
+        Ok(items)
+    }
+    
+    // Names address books give each (item type, value), alphabetically
+    pub async fn get_aliases(&self) -> Result<HashMap<(String, String), Vec<String>>, AppError> {
+        let rows = sqlx::query("SELECT DISTINCT item_type, value, name FROM contacts ORDER BY name")
+            .fetch_all(&self.pool)
+            .await?;
+        
+        let mut aliases: HashMap<(String, String), Vec<String>> = HashMap::new();
+        for row in rows {
+            aliases.entry((row.get("item_type"), row.get("value"))).or_default().push(row.get("name"));
+        }
+        
+        Ok(aliases)
+    }
+    
+    pub async fn get_all_files(&self) -> Result<Vec<FileRecord>, AppError> {
+        let rows = sqlx::query(
+            "SELECT id, file_name, source_id, parent_id, content, content_path, processed_at, metadata FROM files ORDER BY id"
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        
+        let files = rows.into_iter().map(|row| {
+            FileRecord {
+                id: Some(row.get("id")),
+                file_name: row.get("file_name"),
+                source_id: row.get("source_id"),
+                parent_id: row.get("parent_id"),
+                content: row.get("content"),
+                content_path: row.get("content_path"),
                 processed_at: row.get("processed_at"),
+                metadata: parse_attributes(row.get("metadata")),
             }
         }).collect();
         
         Ok(files)
     }
     
+    // Copies a legacy database (sources, files, phone_numbers, email_addresses and
+    // ip_addresses, as the old Python converter read them) into this case. Legacy columns
+    // without a place of their own (imsi, ip_type, extraction_date, ...) become item
+    // attributes, and record_data becomes the occurrence row.
+    pub async fn import_legacy(&self, legacy: &SqlitePool, extractors: &Extractors) -> Result<(), AppError> {
+        let tables = table_names(legacy).await?;
+        let has_table = |name: &str| tables.iter().any(|t| t == name);
+        
+        // Legacy source ids are kept where they are numbers
+        let mut sources: HashMap<String, i32> = HashMap::new();
+        if has_table("sources") {
+            for row in sqlx::query("SELECT * FROM sources").fetch_all(legacy).await? {
+                let fields = legacy_fields(&row);
+                let Some(legacy_id) = fields.get("source_id").or_else(|| fields.get("id")).cloned() else {
+                    continue;
+                };
+                let name = fields.get("source_name").or_else(|| fields.get("name")).cloned()
+                    .unwrap_or_else(|| format!("Source {}", legacy_id));
+                
+                let result = sqlx::query(
+                    "INSERT INTO sources (id, name, created_at) VALUES (?, ?, COALESCE(?, CURRENT_TIMESTAMP))"
+                )
+                .bind(legacy_id.parse::<i32>().ok())
+                .bind(&name)
+                .bind(fields.get("created_date"))
+                .execute(&self.pool)
+                .await?;
+                
+                sources.insert(legacy_id, result.last_insert_rowid() as i32);
+            }
+        }
+        
+        let mut files: HashMap<(i32, String), i64> = HashMap::new();
+        if has_table("files") {
+            for row in sqlx::query("SELECT * FROM files").fetch_all(legacy).await? {
+                let fields = legacy_fields(&row);
+                let (Some(legacy_source), Some(file_name)) = (fields.get("source_id"), fields.get("file_name")) else {
+                    continue;
+                };
+                let source_id = self.legacy_source(&mut sources, legacy_source).await?;
+                let content = fields.get("content").map(String::as_str).unwrap_or("");
+                let file_id = self.insert_file(file_name, source_id, content).await?;
+                files.insert((source_id, file_name.clone()), file_id);
+            }
+        }
+        
+        let mut providers: HashMap<i32, HashMap<String, usize>> = HashMap::new();
+        
+        for (table, value_column, item_type) in LEGACY_ITEM_TABLES {
+            if !has_table(table) {
+                continue;
+            }
+            
+            let rows = sqlx::query(&format!("SELECT * FROM {} ORDER BY rowid", table))
+                .fetch_all(legacy)
+                .await?;
+                
+            for row in rows {
+                let mut fields = legacy_fields(&row);
+                let (Some(raw_value), Some(legacy_source)) = (fields.remove(*value_column), fields.remove("source_id")) else {
+                    continue;
+                };
+                let source_id = self.legacy_source(&mut sources, &legacy_source).await?;
+                
+                let file_name = fields.remove("file_name").unwrap_or_else(|| "Unknown".to_string());
+                let file_id = match files.get(&(source_id, file_name.clone())) {
+                    Some(id) => *id,
+                    None => {
+                        let id = self.insert_file(&file_name, source_id, "").await?;
+                        files.insert((source_id, file_name.clone()), id);
+                        id
+                    }
+                };
+                
+                let record_data = fields.remove("record_data");
+                let record = legacy_record(record_data.as_ref());
+                let provider = record.get("Provider_Detected").and_then(|p| p.as_str()).filter(|p| !p.is_empty());
+                if let Some(provider) = provider {
+                    *providers.entry(source_id).or_default().entry(provider.to_string()).or_default() += 1;
+                }
+                
+                let mut attributes: ItemAttributes = fields.iter()
+                    .filter(|(_, value)| !value.trim().is_empty())
+                    .map(|(column, value)| match column.as_str() {
+                        "id" => ("legacy_id".to_string(), value.clone()),
+                        _ => (column.clone(), value.clone()),
+                    })
+                    .collect();
+                if let Some(provider) = provider {
+                    attributes.insert("provider".to_string(), provider.to_string());
+                }
+                
+                // Phones are brought to E.164 so they cross-reference with newly processed returns
+                let value = match item_type {
+                    ItemType::Phone => match extractors.phone_in(&raw_value) {
+                        Some(phone) => {
+                            attributes.insert("country".to_string(), phone.region.to_string());
+                            phone.e164
+                        }
+                        None => raw_value.clone(),
+                    },
+                    _ => raw_value.trim().to_string(),
+                };
+                if value != raw_value {
+                    attributes.insert("legacy_value".to_string(), raw_value.clone());
+                }
+                
+                // The legacy schema kept no positions; line and column 0 mean unknown
+                let source_record = SourceRecord {
+                    line_number: 0,
+                    byte_offset: None,
+                    sheet_name: None,
+                    page_number: None,
+                    text: record_data.clone().unwrap_or_default(),
+                    event: None,
+                    contact: None,
+                };
+                
+                let item_id = self.insert_extracted_item(&value, item_type.as_str(), source_id, file_id, Some(&attributes)).await?;
+                self.insert_occurrence(item_id, file_id, &source_record, 0, 0, None, None).await?;
+                
+                // The IMSI recorded beside a phone number is an identifier in its own right
+                if let Some(imsi) = fields.get("imsi").and_then(|imsi| device_id::parse_imsi(imsi.trim())) {
+                    let item_id = self.insert_extracted_item(&imsi.value, ItemType::Imsi.as_str(), source_id, file_id, Some(&imsi.attributes)).await?;
+                    self.insert_occurrence(item_id, file_id, &source_record, 0, 0, None, None).await?;
+                }
+            }
+        }
+        
+        // Each source takes the provider most of its records were detected as
+        for (source_id, counts) in providers {
+            if let Some((provider, _)) = counts.into_iter().max_by_key(|(_, count)| *count) {
+                sqlx::query("UPDATE sources SET provider = ? WHERE id = ?")
+                    .bind(provider)
+                    .bind(source_id)
+                    .execute(&self.pool)
+                    .await?;
+            }
+        }
+        
+        Ok(())
+    }
+    
+    // Source for a legacy source id, created when rows name a source the sources table lacks
+    async fn legacy_source(&self, sources: &mut HashMap<String, i32>, legacy_id: &str) -> Result<i32, AppError> {
+        if let Some(id) = sources.get(legacy_id) {
+            return Ok(*id);
+        }
+        
+        let id = match legacy_id.parse::<i32>() {
+            Ok(id) => {
+                self.ensure_source(id).await?;
+                id
+            }
+            Err(_) => self.create_source(&format!("Source {}", legacy_id), None, None).await?.id,
+        };
+        sources.insert(legacy_id.to_string(), id);
+        Ok(id)
+    }
+    
+    // Loads a validated CAST_JSON document into this (empty) case. Tile N becomes source N,
+    // each row becomes an item with its occurrence, and whatever has no column of its own
+    // (tile and case metadata, extra row fields) is kept as JSON so export gives the same
+    // document back.
+    pub async fn import_cast_json(&self, case: &CastJson) -> Result<(), AppError> {
+        case.validate()?;
+        
+        if let Some(counter) = case.tile_counter {
+            self.set_case_metadata("tileCounter", &counter.to_string()).await?;
+        }
+        if let Some(timestamp) = &case.timestamp {
+            self.set_case_metadata("timestamp", timestamp).await?;
+        }
+        if let Some(conversion) = &case.conversion {
+            self.set_case_metadata("conversion", &conversion.to_string()).await?;
+        }
+        // Totals are recomputed on export
+        self.set_case_metadata("metadata", &match &case.metadata {
+            Some(metadata) => serde_json::to_string(&CaseMetadata {
+                total_tiles: None,
+                total_phones: None,
+                total_emails: None,
+                total_ips: None,
+                ..metadata.clone()
+            })?,
+            None => "null".to_string(),
+        }).await?;
+        
+        for (TileId(source_id), tile) in &case.tiles {
+            let metadata = tile.metadata.as_ref().map(serde_json::to_string).transpose()?;
+            let created_date = tile.metadata.as_ref().and_then(|m| m.created_date.as_deref());
+            
+            sqlx::query(
+                "INSERT INTO sources (id, name, created_at, metadata) VALUES (?, ?, COALESCE(?, CURRENT_TIMESTAMP), ?)"
+            )
+            .bind(source_id)
+            .bind(&tile.name)
+            .bind(created_date)
+            .bind(metadata)
+            .execute(&self.pool)
+            .await?;
+            
+            // Rows may name files missing from the tile's list; those are stored unlisted
+            let mut files: HashMap<&str, i64> = HashMap::new();
+            for file_name in &tile.files {
+                let file_id = self.insert_file(file_name, *source_id, "").await?;
+                files.entry(file_name).or_insert(file_id);
+            }
+            
+            for list in tile.value_lists() {
+                let item_type = list.item_type;
+                for value in list.values {
+                    let rows = list.rows.get(value).map(Vec::as_slice).unwrap_or_default();
+                    
+                    if rows.is_empty() {
+                        let file_id = match tile.files.first() {
+                            Some(name) => files[name.as_str()],
+                            None => self.unlisted_file(&mut files, "Unknown", *source_id).await?,
+                        };
+                        self.insert_extracted_item(value, item_type.as_str(), *source_id, file_id, None).await?;
+                        continue;
+                    }
+                    
+                    for row in rows {
+                        let file_id = self.unlisted_file(&mut files, &row.file_name, *source_id).await?;
+                        let item_id = self.insert_extracted_item(value, item_type.as_str(), *source_id, file_id, None).await?;
+                        let details = (!row.extra.is_empty())
+                            .then(|| serde_json::to_string(&row.extra))
+                            .transpose()?;
+                        
+                        sqlx::query(
+                            "INSERT INTO occurrences (item_id, file_id, line_number, column_number, row_data, details) VALUES (?, ?, ?, 0, ?, ?)"
+                        )
+                        .bind(item_id)
+                        .bind(file_id)
+                        .bind(row.line_number)
+                        .bind(&row.row_data)
+                        .bind(details)
+                        .execute(&self.pool)
+                        .await?;
+                    }
+                }
+            }
+        }
+        
+        Ok(())
+    }
+    
+    // File id for `file_name` in this source, adding it outside the tile's file list if new
+    async fn unlisted_file<'a>(&self, files: &mut HashMap<&'a str, i64>, file_name: &'a str, source_id: i32) -> Result<i64, AppError> {
+        if let Some(file_id) = files.get(file_name) {
+            return Ok(*file_id);
+        }
+        
+        let result = sqlx::query(
+            "INSERT INTO files (file_name, source_id, content, listed) VALUES (?, ?, '', 0)"
+        )
+        .bind(file_name)
+        .bind(source_id)
+        .execute(&self.pool)
+        .await?;
+        
+        files.insert(file_name, result.last_insert_rowid());
+        Ok(result.last_insert_rowid())
+    }
+    
+    // The case as a CAST_JSON document. Only phones, emails and IPs have a place in it;
+    // device identifiers and events stay in the SQLite case.
+    pub async fn export_cast_json(&self) -> Result<CastJson, AppError> {
+        let stored: HashMap<String, String> = sqlx::query("SELECT key, value FROM case_metadata")
+            .fetch_all(&self.pool)
+            .await?
+            .into_iter()
+            .map(|row| (row.get("key"), row.get("value")))
+            .collect();
+        
+        let mut tiles = BTreeMap::new();
+        for row in sqlx::query("SELECT id, name, metadata FROM sources ORDER BY id").fetch_all(&self.pool).await? {
+            let source_id: i32 = row.get("id");
+            let metadata: Option<TileMetadata> = row.get::<Option<String>, _>("metadata")
+                .map(|metadata| serde_json::from_str(&metadata))
+                .transpose()?;
+            
+            let files = sqlx::query_scalar("SELECT file_name FROM files WHERE source_id = ? AND listed = 1 ORDER BY id")
+                .bind(source_id)
+                .fetch_all(&self.pool)
+                .await?;
+            
+            let (phones, phone_rows) = self.tile_values(source_id, ItemType::Phone).await?;
+            let (emails, email_rows) = self.tile_values(source_id, ItemType::Email).await?;
+            let (ips, ip_rows) = self.tile_values(source_id, ItemType::Ip).await?;
+            
+            tiles.insert(TileId(source_id), Tile {
+                name: row.get("name"),
+                files,
+                phones,
+                emails,
+                ips,
+                phone_rows,
+                email_rows,
+                ip_rows,
+                metadata,
+            });
+        }
+        
+        // Cases that did not come from CAST_JSON get the metadata the webview writes
+        let metadata = match stored.get("metadata") {
+            Some(metadata) => serde_json::from_str::<Option<CaseMetadata>>(metadata)?,
+            None => Some(CaseMetadata {
+                application_version: Some(format!("CAST Database Analyzer v{}", env!("CARGO_PKG_VERSION"))),
+                total_tiles: None,
+                total_phones: None,
+                total_emails: None,
+                total_ips: None,
+                extra: Default::default(),
+            }),
+        };
+        let metadata = metadata.map(|metadata| CaseMetadata {
+            total_tiles: Some(tiles.len()),
+            total_phones: Some(tiles.values().map(|t: &Tile| t.phones.len()).sum()),
+            total_emails: Some(tiles.values().map(|t: &Tile| t.emails.len()).sum()),
+            total_ips: Some(tiles.values().map(|t: &Tile| t.ips.len()).sum()),
+            ..metadata
+        });
+        
+        let last_tile = tiles.keys().next_back().map(|TileId(id)| *id as u32).unwrap_or(0);
+        let tile_counter = match stored.get("tileCounter") {
+            Some(counter) => counter.parse::<u32>().ok().map(|counter| counter.max(last_tile)),
+            None if stored.contains_key("metadata") => None,
+            None => Some(last_tile),
+        };
+        
+        Ok(CastJson {
+            version: cast_json::VERSION.to_string(),
+            format: cast_json::FORMAT.to_string(),
+            timestamp: stored.get("timestamp").cloned().or_else(|| Some(Utc::now().to_rfc3339())),
+            conversion: stored.get("conversion").map(|c| serde_json::from_str(c)).transpose()?,
+            tile_counter,
+            metadata,
+            tiles,
+        })
+    }
+    
+    // A source's values of one type in the order first found, with their rows
+    async fn tile_values(&self, source_id: i32, item_type: ItemType) -> Result<(Vec<String>, TileRows), AppError> {
+        let rows = sqlx::query(
+            r#"
+            SELECT e.value, f.file_name, o.id AS occurrence_id, o.line_number, o.row_data, o.details
+            FROM extracted_items e
+            LEFT JOIN occurrences o ON o.item_id = e.id
+            LEFT JOIN files f ON f.id = o.file_id
+            WHERE e.source_id = ? AND e.item_type = ?
+            ORDER BY e.id, o.id
+            "#
+        )
+        .bind(source_id)
+        .bind(item_type.as_str())
+        .fetch_all(&self.pool)
+        .await?;
+        
+        let mut values: Vec<String> = Vec::new();
+        let mut seen = HashSet::new();
+        let mut value_rows = TileRows::new();
+        for row in rows {
+            let value: String = row.get("value");
+            if seen.insert(value.clone()) {
+                values.push(value.clone());
+            }
+            if row.get::<Option<i64>, _>("occurrence_id").is_none() {
+                continue;
+            }
+            
+            value_rows.entry(value).or_default().push(TileRow {
+                file_name: row.get::<Option<String>, _>("file_name").unwrap_or_default(),
+                line_number: row.get("line_number"),
+                row_data: row.get("row_data"),
+                extra: row.get::<Option<String>, _>("details")
+                    .map(|details| serde_json::from_str(&details))
+                    .transpose()?
+                    .unwrap_or_default(),
+            });
+        }
+        
+        Ok((values, value_rows))
+    }
+    
+    async fn set_case_metadata(&self, key: &str, value: &str) -> Result<(), AppError> {
+        sqlx::query("INSERT OR REPLACE INTO case_metadata (key, value) VALUES (?, ?)")
+            .bind(key)
+            .bind(value)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+    
     pub async fn clear_all(&self) -> Result<(), AppError> {
+        sqlx::query("DELETE FROM events")
+            .execute(&self.pool)
+            .await?;
+            
+        sqlx::query("DELETE FROM contacts")
+            .execute(&self.pool)
+            .await?;
+            
+        sqlx::query("DELETE FROM occurrences")
+            .execute(&self.pool)
+            .await?;
+            
         sqlx::query("DELETE FROM extracted_items")
             .execute(&self.pool)
             .await?;
@@ -247,6 +1428,699 @@ impl Database {
             .execute(&self.pool)
             .await?;
             
+        sqlx::query("DELETE FROM sources")
+            .execute(&self.pool)
+            .await?;
+            
+        sqlx::query("DELETE FROM case_metadata")
+            .execute(&self.pool)
+            .await?;
+            
+        Ok(())
+    }
+}
+
+// One delivery's rows, written in a single transaction. Files are inserted at once since
+// their ids are needed straight away; items, occurrences and events are buffered and
+// written BATCH_ROWS at a time. Item ids are handed out in advance so occurrences can
+// refer to items not yet written. Dropping the batch without commit() rolls back every
+// row it wrote.
+pub struct IngestBatch {
+    tx: Transaction<'static, Sqlite>,
+    _writer: tokio::sync::OwnedMutexGuard<()>,
+    source_id: i32,
+    next_item_id: i64,
+    items: Vec<PendingItem>,
+    occurrences: Vec<PendingOccurrence>,
+    events: Vec<PendingEvent>,
+    contacts: Vec<PendingContact>,
+}
+
+struct PendingItem {
+    id: i64,
+    value: String,
+    item_type: &'static str,
+    file_id: i64,
+    attributes: Option<String>,
+}
+
+struct PendingOccurrence {
+    item_id: i64,
+    file_id: i64,
+    sheet_name: Option<String>,
+    page_number: Option<i64>,
+    line_number: i64,
+    column_number: i64,
+    byte_offset: Option<i64>,
+    row_data: String,
+    role: Option<String>,
+}
+
+// An event with its UTC sort key and lists encoded for storage
+struct PendingEvent {
+    event: CommEvent,
+    start_utc: Option<String>,
+    recipients: Option<String>,
+    attachments: Option<String>,
+}
+
+// One name and value of an address-book entry
+struct PendingContact {
+    file_id: i64,
+    line_number: i64,
+    name: String,
+    value: ContactValue,
+}
+
+impl IngestBatch {
+    pub fn source_id(&self) -> i32 {
+        self.source_id
+    }
+    
+    // `parent_id` is the archive the file was unpacked from, if any
+    pub async fn insert_file(&mut self, file_name: &str, content: &str, parent_id: Option<i64>) -> Result<i64, AppError> {
+        let result = sqlx::query(
+            "INSERT INTO files (file_name, source_id, parent_id, content) VALUES (?, ?, ?, ?)"
+        )
+        .bind(file_name)
+        .bind(self.source_id)
+        .bind(parent_id)
+        .bind(content)
+        .execute(&mut *self.tx)
+        .await?;
+        
+        Ok(result.last_insert_rowid())
+    }
+    
+    // Streamed files are inserted before their text is known
+    pub async fn set_file_content(&mut self, file_id: i64, content: &str) -> Result<(), AppError> {
+        sqlx::query("UPDATE files SET content = ? WHERE id = ?")
+            .bind(content)
+            .bind(file_id)
+            .execute(&mut *self.tx)
+            .await?;
+        Ok(())
+    }
+    
+    // The text stays in the original file; only where to find it is kept
+    pub async fn set_file_content_path(&mut self, file_id: i64, path: &str) -> Result<(), AppError> {
+        sqlx::query("UPDATE files SET content_path = ? WHERE id = ?")
+            .bind(path)
+            .bind(file_id)
+            .execute(&mut *self.tx)
+            .await?;
+        Ok(())
+    }
+    
+    pub async fn set_file_metadata(&mut self, file_id: i64, metadata: &ItemAttributes) -> Result<(), AppError> {
+        sqlx::query("UPDATE files SET metadata = ? WHERE id = ?")
+            .bind(serde_json::to_string(metadata)?)
+            .bind(file_id)
+            .execute(&mut *self.tx)
+            .await?;
+        Ok(())
+    }
+    
+    pub async fn insert_extracted_item(
+        &mut self,
+        value: &str,
+        item_type: ItemType,
+        file_id: i64,
+        attributes: Option<&ItemAttributes>,
+    ) -> Result<i64, AppError> {
+        let id = self.next_item_id;
+        self.next_item_id += 1;
+        
+        self.items.push(PendingItem {
+            id,
+            value: value.to_string(),
+            item_type: item_type.as_str(),
+            file_id,
+            attributes: attributes.map(serde_json::to_string).transpose()?,
+        });
+        if self.items.len() >= BATCH_ROWS {
+            self.flush_items().await?;
+        }
+        
+        Ok(id)
+    }
+    
+    #[allow(clippy::too_many_arguments)]
+    pub async fn insert_occurrence(
+        &mut self,
+        item_id: i64,
+        file_id: i64,
+        record: &SourceRecord,
+        line_number: i64,
+        column_number: i64,
+        byte_offset: Option<i64>,
+        role: Option<&str>,
+    ) -> Result<(), AppError> {
+        self.occurrences.push(PendingOccurrence {
+            item_id,
+            file_id,
+            sheet_name: record.sheet_name.clone(),
+            page_number: record.page_number,
+            line_number,
+            column_number,
+            byte_offset,
+            row_data: record.text.clone(),
+            role: role.map(str::to_string),
+        });
+        if self.occurrences.len() >= BATCH_ROWS {
+            self.flush_occurrences().await?;
+        }
+        Ok(())
+    }
+    
+    pub async fn insert_event(&mut self, event: &CommEvent) -> Result<(), AppError> {
+        let start_utc = event.start_time.as_deref().map(utc_key).transpose()?;
+        self.events.push(PendingEvent {
+            event: event.clone(),
+            start_utc,
+            recipients: json_list(&event.recipients)?,
+            attachments: json_list(&event.attachments)?,
+        });
+        if self.events.len() >= BATCH_ROWS {
+            self.flush_events().await?;
+        }
+        Ok(())
+    }
+    
+    // A row per number or address the entry lists
+    pub async fn insert_contact(&mut self, contact: &Contact, file_id: i64, line_number: i64) -> Result<(), AppError> {
+        self.contacts.extend(contact.values.iter().map(|value| PendingContact {
+            file_id,
+            line_number,
+            name: contact.name.clone(),
+            value: value.clone(),
+        }));
+        if self.contacts.len() >= BATCH_ROWS {
+            self.flush_contacts().await?;
+        }
+        Ok(())
+    }
+    
+    // Writes what is still buffered and makes the whole delivery visible at once
+    pub async fn commit(mut self) -> Result<(), AppError> {
+        self.flush_occurrences().await?;
+        self.flush_events().await?;
+        self.flush_contacts().await?;
+        self.tx.commit().await?;
+        Ok(())
+    }
+    
+    async fn flush_items(&mut self) -> Result<(), AppError> {
+        if self.items.is_empty() {
+            return Ok(());
+        }
+        
+        let mut query = QueryBuilder::<Sqlite>::new(
+            "INSERT INTO extracted_items (id, value, item_type, source_id, file_id, attributes) "
+        );
+        query.push_values(self.items.drain(..), |mut row, item| {
+            row.push_bind(item.id)
+                .push_bind(item.value)
+                .push_bind(item.item_type)
+                .push_bind(self.source_id)
+                .push_bind(item.file_id)
+                .push_bind(item.attributes);
+        });
+        query.build().execute(&mut *self.tx).await?;
+        Ok(())
+    }
+    
+    // Items go first so every occurrence finds its item
+    async fn flush_occurrences(&mut self) -> Result<(), AppError> {
+        self.flush_items().await?;
+        if self.occurrences.is_empty() {
+            return Ok(());
+        }
+        
+        let mut query = QueryBuilder::<Sqlite>::new(
+            "INSERT INTO occurrences (item_id, file_id, sheet_name, page_number, line_number, column_number, byte_offset, row_data, role) "
+        );
+        query.push_values(self.occurrences.drain(..), |mut row, occurrence| {
+            row.push_bind(occurrence.item_id)
+                .push_bind(occurrence.file_id)
+                .push_bind(occurrence.sheet_name)
+                .push_bind(occurrence.page_number)
+                .push_bind(occurrence.line_number)
+                .push_bind(occurrence.column_number)
+                .push_bind(occurrence.byte_offset)
+                .push_bind(occurrence.row_data)
+                .push_bind(occurrence.role);
+        });
+        query.build().execute(&mut *self.tx).await?;
         Ok(())
     }
-}
\ No newline at end of file
+    
+    async fn flush_events(&mut self) -> Result<(), AppError> {
+        if self.events.is_empty() {
+            return Ok(());
+        }
+        
+        let mut query = QueryBuilder::<Sqlite>::new(
+            r#"
+            INSERT INTO events (source_id, file_id, sheet_name, line_number, event_type, direction, from_party, to_party,
+                                start_time, start_utc, local_time, duration_seconds, cell_site, sector, imei, imsi, recipients, attachments)
+            "#
+        );
+        query.push_values(self.events.drain(..), |mut row, PendingEvent { event, start_utc, recipients, attachments }| {
+            row.push_bind(event.source_id)
+                .push_bind(event.file_id)
+                .push_bind(event.sheet_name)
+                .push_bind(event.line_number)
+                .push_bind(event.event_type.as_str())
+                .push_bind(event.direction.map(|d| d.as_str()))
+                .push_bind(event.from)
+                .push_bind(event.to)
+                .push_bind(event.start_time)
+                .push_bind(start_utc)
+                .push_bind(event.local_time)
+                .push_bind(event.duration_seconds)
+                .push_bind(event.cell_site)
+                .push_bind(event.sector)
+                .push_bind(event.imei)
+                .push_bind(event.imsi)
+                .push_bind(recipients)
+                .push_bind(attachments);
+        });
+        query.build().execute(&mut *self.tx).await?;
+        Ok(())
+    }
+    
+    async fn flush_contacts(&mut self) -> Result<(), AppError> {
+        if self.contacts.is_empty() {
+            return Ok(());
+        }
+        
+        let mut query = QueryBuilder::<Sqlite>::new(
+            "INSERT INTO contacts (source_id, file_id, line_number, name, value, item_type, label) "
+        );
+        query.push_values(self.contacts.drain(..), |mut row, contact| {
+            row.push_bind(self.source_id)
+                .push_bind(contact.file_id)
+                .push_bind(contact.line_number)
+                .push_bind(contact.name)
+                .push_bind(contact.value.value)
+                .push_bind(contact.value.item_type.as_str())
+                .push_bind(contact.value.label);
+        });
+        query.build().execute(&mut *self.tx).await?;
+        Ok(())
+    }
+}
+
+// Item tables of the pre-1.5 CAST database and the column holding each value
+const LEGACY_ITEM_TABLES: &[(&str, &str, ItemType)] = &[
+    ("phone_numbers", "phone_number", ItemType::Phone),
+    ("email_addresses", "email_address", ItemType::Email),
+    ("ip_addresses", "ip_address", ItemType::Ip),
+];
+
+async fn table_names(pool: &SqlitePool) -> Result<Vec<String>, AppError> {
+    sqlx::query_scalar("SELECT name FROM sqlite_master WHERE type = 'table'")
+        .fetch_all(pool)
+        .await
+        .map_err(not_a_database)
+}
+
+async fn is_legacy_database(pool: &SqlitePool) -> Result<bool, AppError> {
+    let tables = table_names(pool).await?;
+    let has_table = |name: &str| tables.iter().any(|t| t == name);
+    
+    Ok(!has_table("extracted_items") && LEGACY_ITEM_TABLES.iter().any(|(table, _, _)| has_table(table)))
+}
+
+// SQLITE_NOTADB: e.g. a JSON export picked in the open dialog
+fn not_a_database(e: sqlx::Error) -> AppError {
+    match &e {
+        sqlx::Error::Database(db_error) if db_error.code().as_deref() == Some("26") => AppError::UnrecognizedDatabase,
+        _ => AppError::Database(e),
+    }
+}
+
+// Every non-null column of a legacy row as text, whatever type SQLite stored it as
+fn legacy_fields(row: &SqliteRow) -> BTreeMap<String, String> {
+    row.columns()
+        .iter()
+        .filter_map(|column| {
+            let value = row.try_get_unchecked::<Option<String>, _>(column.ordinal()).ok().flatten()?;
+            Some((column.name().to_string(), value))
+        })
+        .collect()
+}
+
+// Legacy record_data is the original row serialized as JSON, with the detected provider
+fn legacy_record(record_data: Option<&String>) -> serde_json::Map<String, serde_json::Value> {
+    record_data
+        .and_then(|data| serde_json::from_str(data).ok())
+        .unwrap_or_default()
+}
+
+fn source_from_row(row: &SqliteRow) -> Source {
+    Source {
+        id: row.get("id"),
+        name: row.get("name"),
+        provider: row.get("provider"),
+        warrant_number: row.get("warrant_number"),
+        created_at: row.get("created_at"),
+    }
+}
+
+fn parse_attributes(raw: Option<String>) -> ItemAttributes {
+    raw.and_then(|s| serde_json::from_str(&s).ok()).unwrap_or_default()
+}
+
+// Lists are stored as JSON arrays, NULL when empty
+fn json_list(values: &[String]) -> Result<Option<String>, AppError> {
+    Ok((!values.is_empty()).then(|| serde_json::to_string(values)).transpose()?)
+}
+
+fn parse_list(raw: Option<String>) -> Vec<String> {
+    raw.and_then(|s| serde_json::from_str(&s).ok()).unwrap_or_default()
+}
+
+// Pages copied per backup step; each step reports progress
+const BACKUP_STEP_PAGES: i32 = 256;
+
+// A step that finds either database locked is retried after a pause, for about 5 seconds
+// in all before the save gives up
+const BACKUP_RETRY_DELAY: std::time::Duration = std::time::Duration::from_millis(25);
+const BACKUP_MAX_RETRIES: u32 = 200;
+
+// Runs an online backup of `source`'s main database into `target`. Retries wait on the
+// runtime's timer, so a locked case does not hold up the worker thread the save runs on.
+async fn copy_pages<F>(
+    source: &mut LockedSqliteHandle<'_>,
+    target: &mut LockedSqliteHandle<'_>,
+    progress: &mut F,
+) -> Result<(), AppError>
+where
+    F: FnMut(SaveProgress),
+{
+    let main = c"main";
+    
+    // SAFETY: both handles come from locked, open sqlx connections that stay locked for as
+    // long as the backup object, which is finished before they are released
+    let backup = unsafe {
+        ffi::sqlite3_backup_init(target.as_raw_handle().as_ptr(), main.as_ptr(), source.as_raw_handle().as_ptr(), main.as_ptr())
+    };
+    let Some(backup) = NonNull::new(backup).map(Backup) else {
+        return Err(AppError::Backup(sqlite_message(target.as_raw_handle())));
+    };
+    
+    let mut retries = 0;
+    loop {
+        let rc = backup.step();
+        progress(backup.progress());
+        
+        match rc {
+            ffi::SQLITE_OK => retries = 0,
+            ffi::SQLITE_BUSY | ffi::SQLITE_LOCKED if retries < BACKUP_MAX_RETRIES => {
+                retries += 1;
+                tokio::time::sleep(BACKUP_RETRY_DELAY).await;
+            }
+            ffi::SQLITE_BUSY | ffi::SQLITE_LOCKED => {
+                return Err(AppError::Backup("The case stayed locked by another connection".to_string()));
+            }
+            ffi::SQLITE_DONE => break,
+            _ => {
+                backup.finish();
+                return Err(AppError::Backup(sqlite_message(target.as_raw_handle())));
+            }
+        }
+    }
+    
+    if backup.finish() != ffi::SQLITE_OK {
+        return Err(AppError::Backup(sqlite_message(target.as_raw_handle())));
+    }
+    
+    Ok(())
+}
+
+// An sqlite3_backup object, finished when dropped so a save abandoned mid-copy releases it
+struct Backup(NonNull<ffi::sqlite3_backup>);
+
+// SAFETY: the object is only used through &self by the one task that runs the backup
+unsafe impl Send for Backup {}
+
+impl Backup {
+    fn step(&self) -> i32 {
+        // SAFETY: the object is live until finish or drop
+        unsafe { ffi::sqlite3_backup_step(self.0.as_ptr(), BACKUP_STEP_PAGES) }
+    }
+    
+    fn progress(&self) -> SaveProgress {
+        // SAFETY: as for step
+        let (total, remaining) = unsafe {
+            (ffi::sqlite3_backup_pagecount(self.0.as_ptr()) as i64, ffi::sqlite3_backup_remaining(self.0.as_ptr()) as i64)
+        };
+        SaveProgress {
+            stage: SaveStage::Copying,
+            pages_copied: total - remaining,
+            pages_total: total,
+        }
+    }
+    
+    // The result of the backup as a whole; the target's error message describes a failure
+    fn finish(self) -> i32 {
+        let backup = std::mem::ManuallyDrop::new(self);
+        // SAFETY: ManuallyDrop keeps Drop from finishing the object a second time
+        unsafe { ffi::sqlite3_backup_finish(backup.0.as_ptr()) }
+    }
+}
+
+impl Drop for Backup {
+    fn drop(&mut self) {
+        // SAFETY: the object has not been finished, or finish would have skipped this
+        unsafe { ffi::sqlite3_backup_finish(self.0.as_ptr()) };
+    }
+}
+
+fn sqlite_message(handle: NonNull<ffi::sqlite3>) -> String {
+    // SAFETY: sqlite3_errmsg always returns a valid, NUL-terminated string for an open handle
+    unsafe { std::ffi::CStr::from_ptr(ffi::sqlite3_errmsg(handle.as_ptr())) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+fn occurrence_from_row(row: &SqliteRow) -> Occurrence {
+    Occurrence {
+        value: row.get("value"),
+        item_type: row.get::<String, _>("item_type").parse().unwrap_or(ItemType::Phone),
+        file_id: row.get("file_id"),
+        file_name: row.get("file_name"),
+        source_id: row.get("source_id"),
+        sheet_name: row.get("sheet_name"),
+        page_number: row.get("page_number"),
+        line_number: row.get("line_number"),
+        column_number: row.get("column_number"),
+        byte_offset: row.get("byte_offset"),
+        row_data: row.get("row_data"),
+        role: row.get("role"),
+        start_time: row.get("start_time"),
+    }
+}
+
+// Inclusive UTC bounds for a time-range query; a bare end date covers that whole day
+fn utc_range(start: Option<&str>, end: Option<&str>) -> Result<(Option<String>, Option<String>), AppError> {
+    let start = start.map(utc_key).transpose()?;
+    let end = end.map(|end| match NaiveDate::parse_from_str(end, "%Y-%m-%d") {
+        Ok(date) => Ok(format!("{}T23:59:59Z", date.format("%Y-%m-%d"))),
+        Err(_) => utc_key(end),
+    }).transpose()?;
+    Ok((start, end))
+}
+
+// Sortable UTC form of an RFC 3339 timestamp or bare date, used for time-range queries. A
+// local time in an unknown zone is read as UTC, so it still sorts and falls into ranges
+// within a day of where it belongs.
+fn utc_key(timestamp: &str) -> Result<String, AppError> {
+    let invalid = || AppError::Parse(format!("Invalid timestamp: {}", timestamp));
+    let utc = match DateTime::parse_from_rfc3339(timestamp) {
+        Ok(datetime) => datetime.with_timezone(&Utc),
+        Err(_) if timestamp.contains('T') => NaiveDateTime::parse_from_str(timestamp, "%Y-%m-%dT%H:%M:%S%.f")
+            .map_err(|_| invalid())?
+            .and_utc(),
+        Err(_) => NaiveDate::parse_from_str(timestamp, "%Y-%m-%d")
+            .map_err(|_| invalid())?
+            .and_time(NaiveTime::MIN)
+            .and_utc(),
+    };
+    Ok(utc.format("%Y-%m-%dT%H:%M:%SZ").to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn local_times_are_flagged_and_found_by_range() {
+        let db = Database::new_scratch().await.unwrap();
+        let mut batch = db.begin_batch(1).await.unwrap();
+        let file_id = batch.insert_file("calls.csv", "", None).await.unwrap();
+        let record = SourceRecord {
+            line_number: 2,
+            byte_offset: None,
+            sheet_name: None,
+            page_number: None,
+            text: String::new(),
+            event: None,
+            contact: None,
+        };
+        for start_time in ["2024-01-15T13:45:00", "2024-01-16T09:00:00-05:00"] {
+            let event = CdrEvent {
+                carrier: None,
+                event_type: EventType::Call,
+                direction: None,
+                originating: Some("+12025550143".to_string()),
+                terminating: None,
+                dialed: None,
+                subscriber: None,
+                start_time: Some(start_time.to_string()),
+                duration_seconds: None,
+                cell_site: None,
+                sector: None,
+                imei: None,
+                imsi: None,
+                recipients: Vec::new(),
+                attachments: Vec::new(),
+                roles: Vec::new(),
+            };
+            batch.insert_event(&CommEvent::from_cdr(&event, 1, file_id, &record)).await.unwrap();
+        }
+        batch.commit().await.unwrap();
+
+        let filter = EventFilter {
+            start: Some("2024-01-15".to_string()),
+            end: Some("2024-01-15".to_string()),
+            ..EventFilter::default()
+        };
+        let events = db.get_events(&filter).await.unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].start_time.as_deref(), Some("2024-01-15T13:45:00"));
+        assert!(events[0].local_time);
+
+        let events = db.get_events(&EventFilter::default()).await.unwrap();
+        assert!(!events[1].local_time);
+    }
+
+    #[tokio::test]
+    async fn concurrent_batches_keep_item_ids_apart() {
+        let db = Database::new_scratch().await.unwrap();
+        let ingest = |db: Database, source_id: i32| async move {
+            let mut batch = db.begin_batch(source_id).await.unwrap();
+            let file_id = batch.insert_file("calls.csv", "", None).await.unwrap();
+            let mut ids = Vec::new();
+            for n in 0..BATCH_ROWS + 10 {
+                let value = format!("+1202555{:04}", n);
+                ids.push(batch.insert_extracted_item(&value, ItemType::Phone, file_id, None).await.unwrap());
+                tokio::task::yield_now().await;
+            }
+            batch.commit().await.unwrap();
+            ids
+        };
+
+        let (first, second) = tokio::join!(
+            tokio::spawn(ingest(db.clone(), 1)),
+            tokio::spawn(ingest(db.clone(), 2))
+        );
+        let mut ids = first.unwrap();
+        ids.extend(second.unwrap());
+        let count = ids.len();
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), count);
+    }
+
+    #[tokio::test]
+    async fn backup_gives_up_on_a_locked_target() {
+        let db = Database::new_scratch().await.unwrap();
+        let path = std::env::temp_dir().join(format!("cast-locked-{}.db", std::process::id()));
+        let options = SqliteConnectOptions::new()
+            .filename(&path)
+            .create_if_missing(true)
+            .busy_timeout(Duration::ZERO);
+        let mut holder = SqliteConnection::connect_with(&options).await.unwrap();
+        sqlx::query("CREATE TABLE t (x)").execute(&mut holder).await.unwrap();
+        sqlx::query("BEGIN EXCLUSIVE").execute(&mut holder).await.unwrap();
+
+        let mut target = SqliteConnection::connect_with(&options).await.unwrap();
+        let mut source = db.pool.acquire().await.unwrap();
+        let mut source_handle = source.lock_handle().await.unwrap();
+        let mut target_handle = target.lock_handle().await.unwrap();
+
+        // Waiting out the lock leaves the runtime free to run other tasks
+        let mut progress = |_| {};
+        let copy = copy_pages(&mut source_handle, &mut target_handle, &mut progress);
+        assert!(tokio::time::timeout(Duration::from_millis(100), copy).await.is_err());
+
+        let result = copy_pages(&mut source_handle, &mut target_handle, &mut progress).await;
+        assert!(matches!(result, Err(AppError::Backup(_))));
+        drop(source_handle);
+        drop(target_handle);
+
+        drop(holder);
+        drop(target);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn read_only_open_refuses_an_outdated_case_without_touching_it() {
+        let path = std::env::temp_dir().join(format!("cast-outdated-{}.db", std::process::id()));
+        let path_str = path.to_string_lossy();
+        Database::new_scratch().await.unwrap().save_to_file(&path_str).await.unwrap();
+        assert!(Database::from_file_read_only(&path_str).await.is_ok());
+
+        let mut conn = SqliteConnection::connect(&format!("sqlite:{}", path_str)).await.unwrap();
+        sqlx::query("DELETE FROM schema_version WHERE version = ?")
+            .bind(SCHEMA_VERSION)
+            .execute(&mut conn)
+            .await
+            .unwrap();
+        conn.close().await.unwrap();
+        let before = std::fs::read(&path).unwrap();
+
+        let result = Database::from_file_read_only(&path_str).await;
+        assert!(matches!(result, Err(AppError::SchemaOutdated { found, .. }) if found == SCHEMA_VERSION - 1));
+        assert_eq!(std::fs::read(&path).unwrap(), before);
+
+        drop(ScratchFile { path });
+    }
+
+    #[tokio::test]
+    async fn reads_are_not_blocked_by_an_open_batch() {
+        let db = Database::new_scratch().await.unwrap();
+        let mut batch = db.begin_batch(1).await.unwrap();
+        let file_id = batch.insert_file("calls.csv", "+12025550143", None).await.unwrap();
+        batch.insert_extracted_item("+12025550143", ItemType::Phone, file_id, None).await.unwrap();
+
+        // Another command sees the case as it was before the batch began
+        let files = tokio::time::timeout(Duration::from_secs(2), db.get_all_files())
+            .await
+            .expect("read waited on the ingestion")
+            .unwrap();
+        assert!(files.is_empty());
+
+        batch.commit().await.unwrap();
+        assert_eq!(db.get_all_files().await.unwrap().len(), 1);
+        assert_eq!(db.get_cross_reference_items().await.unwrap().len(), 0);
+    }
+
+    #[tokio::test]
+    async fn scratch_file_is_removed_with_the_last_handle() {
+        let db = Database::new_scratch().await.unwrap();
+        let path = db._scratch.as_ref().unwrap().path.clone();
+        assert!(path.exists());
+
+        let clone = db.clone();
+        db.pool.close().await;
+        drop(db);
+        assert!(path.exists());
+        drop(clone);
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,643 @@
+// SQLite databases pulled from handsets. The message and call tables of iOS sms.db and
+// CallHistory.storedata and of Android mmssms.db and calllog.db (or the older
+// contacts2.db) are read as events; every other table, and a known table whose columns
+// are not the expected ones, is read as the text in its text columns. Rows are numbered
+// by rowid within their table, the way spreadsheet rows are within their sheet.
+use std::collections::HashMap;
+use std::ptr::NonNull;
+use chrono::DateTime;
+use libsqlite3_sys as ffi;
+use sqlx::sqlite::{SqliteConnectOptions, SqliteConnection};
+use sqlx::{Connection, Row, TypeInfo, ValueRef};
+use crate::chat;
+use crate::error::AppError;
+use crate::extractors::Extractors;
+use crate::models::{CdrEvent, Contact, ContactValue, Direction, EventType, ItemType, SourceRecord};
+
+pub const SQLITE_MAGIC: &[u8] = b"SQLite format 3\0";
+
+// Apple stores times from 2001-01-01, as seconds before iOS 11 and nanoseconds since
+const APPLE_EPOCH: i64 = 978_307_200;
+// Separates the values GROUP_CONCAT collects
+const LIST_SEPARATOR: char = '\u{1F}';
+// Stands in for the device's own number as the sender of Android MMS it sent
+const MMS_OWN_ADDRESS: &str = "insert-address-token";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceSchema {
+    IosSms,
+    IosCallHistory,
+    AndroidSms,
+    AndroidCalls,
+    // Any other SQLite database, read table by table
+    Unknown,
+}
+
+impl DeviceSchema {
+    // Recorded as the file's "format" metadata
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeviceSchema::IosSms => "ios_sms",
+            DeviceSchema::IosCallHistory => "ios_call_history",
+            DeviceSchema::AndroidSms => "android_sms",
+            DeviceSchema::AndroidCalls => "android_calls",
+            DeviceSchema::Unknown => "sqlite",
+        }
+    }
+
+    // Recognized by the tables each one is built around
+    fn detect(tables: &[String]) -> Self {
+        let has = |name: &str| tables.iter().any(|t| t == name);
+
+        if has("message") && has("handle") {
+            DeviceSchema::IosSms
+        } else if has("ZCALLRECORD") {
+            DeviceSchema::IosCallHistory
+        } else if has("sms") {
+            DeviceSchema::AndroidSms
+        } else if has("calls") {
+            DeviceSchema::AndroidCalls
+        } else {
+            DeviceSchema::Unknown
+        }
+    }
+}
+
+pub struct DeviceDatabase {
+    pub schema: DeviceSchema,
+    pub tables: usize,
+    pub records: Vec<SourceRecord>,
+}
+
+pub fn is_sqlite(content: &[u8]) -> bool {
+    content.starts_with(SQLITE_MAGIC)
+}
+
+// Every table in name order, the known ones as events
+pub async fn read(content: &[u8], extractors: &Extractors) -> Result<DeviceDatabase, AppError> {
+    let mut conn = open(content).await?;
+
+    // Virtual tables may need modules this build lacks; their shadow tables hold the data
+    let tables: Vec<String> = sqlx::query_scalar(
+        r#"
+        SELECT name FROM sqlite_master
+        WHERE type = 'table' AND name NOT LIKE 'sqlite_%' AND sql NOT LIKE 'CREATE VIRTUAL TABLE%'
+        ORDER BY name
+        "#
+    )
+    .fetch_all(&mut conn)
+    .await
+    .map_err(|e| AppError::DeviceDatabase(e.to_string()))?;
+    let schema = DeviceSchema::detect(&tables);
+
+    let mut records = Vec::new();
+    for table in &tables {
+        let mapped = match (schema, table.as_str()) {
+            (DeviceSchema::IosSms, "message") => Some(ios_messages(&mut conn, extractors).await),
+            (DeviceSchema::IosCallHistory, "ZCALLRECORD") => Some(ios_calls(&mut conn, extractors).await),
+            (DeviceSchema::AndroidSms, "sms") => Some(android_sms(&mut conn, extractors).await),
+            (DeviceSchema::AndroidSms, "pdu") => Some(android_mms(&mut conn, extractors).await),
+            (DeviceSchema::AndroidCalls, "calls") => Some(android_calls(&mut conn, extractors).await),
+            _ => None,
+        };
+
+        match mapped {
+            Some(Ok(rows)) => records.extend(rows),
+            // Versions differ; a table without the expected columns is still read as text
+            Some(Err(_)) | None => records.extend(text_rows(&mut conn, table).await?),
+        }
+    }
+
+    conn.close().await?;
+    Ok(DeviceDatabase { schema, tables: tables.len(), records })
+}
+
+// A private in-memory database holding a copy of the file, so nothing is written next to
+// the evidence and files inside archives need no temporary copy
+async fn open(content: &[u8]) -> Result<SqliteConnection, AppError> {
+    let options = SqliteConnectOptions::new().filename(":memory:");
+    let mut conn = SqliteConnection::connect_with(&options).await?;
+    {
+        let mut handle = conn.lock_handle().await?;
+        deserialize(handle.as_raw_handle(), content)?;
+    }
+    Ok(conn)
+}
+
+fn deserialize(handle: NonNull<ffi::sqlite3>, content: &[u8]) -> Result<(), AppError> {
+    let size = content.len() as i64;
+    let flags = ffi::SQLITE_DESERIALIZE_FREEONCLOSE | ffi::SQLITE_DESERIALIZE_READONLY;
+
+    // SAFETY: the buffer comes from sqlite3_malloc64 and is filled before SQLite sees it;
+    // with FREEONCLOSE SQLite owns it from the call on, freeing it on close or on failure
+    unsafe {
+        let buffer = ffi::sqlite3_malloc64(content.len() as u64) as *mut u8;
+        if buffer.is_null() {
+            return Err(AppError::DeviceDatabase("not enough memory to open the database".to_string()));
+        }
+        std::ptr::copy_nonoverlapping(content.as_ptr(), buffer, content.len());
+
+        // Handsets keep these databases in WAL mode, which cannot be read from memory;
+        // header bytes 18 and 19 switch the copy to the rollback journal
+        if content.len() > 19 {
+            *buffer.add(18) = 1;
+            *buffer.add(19) = 1;
+        }
+
+        let rc = ffi::sqlite3_deserialize(handle.as_ptr(), c"main".as_ptr(), buffer, size, size, flags);
+        if rc != ffi::SQLITE_OK {
+            let message = std::ffi::CStr::from_ptr(ffi::sqlite3_errmsg(handle.as_ptr())).to_string_lossy().into_owned();
+            return Err(AppError::DeviceDatabase(message));
+        }
+    }
+
+    Ok(())
+}
+
+fn quote(identifier: &str) -> String {
+    format!("\"{}\"", identifier.replace('"', "\"\""))
+}
+
+// Values of the row's TEXT cells, comma-joined like spreadsheet cells. Numbers, dates and
+// blobs are left out: bare integers would read as phone numbers.
+async fn text_rows(conn: &mut SqliteConnection, table: &str) -> Result<Vec<SourceRecord>, AppError> {
+    let rows = match sqlx::query(&format!("SELECT rowid AS __rowid, * FROM {}", quote(table))).fetch_all(&mut *conn).await {
+        Ok(rows) => rows,
+        // WITHOUT ROWID tables are numbered by position instead
+        Err(_) => sqlx::query(&format!("SELECT NULL AS __rowid, * FROM {}", quote(table)))
+            .fetch_all(&mut *conn)
+            .await
+            .map_err(|e| AppError::DeviceDatabase(format!("{}: {}", table, e)))?,
+    };
+
+    let mut records = Vec::new();
+    for (i, row) in rows.iter().enumerate() {
+        let cells: Vec<String> = (1..row.len())
+            .filter(|&column| row.try_get_raw(column).is_ok_and(|value| value.type_info().name() == "TEXT"))
+            .filter_map(|column| row.try_get::<String, _>(column).ok())
+            .filter(|cell| !cell.trim().is_empty())
+            .collect();
+        if cells.is_empty() {
+            continue;
+        }
+
+        let rowid = row.try_get::<Option<i64>, _>(0).ok().flatten();
+        records.push(table_record(table, rowid.unwrap_or(i as i64 + 1), cells.join(","), None));
+    }
+
+    Ok(records)
+}
+
+fn table_record(table: &str, rowid: i64, text: String, mut event: Option<CdrEvent>) -> SourceRecord {
+    if let Some(event) = event.as_mut() {
+        event.add_address_roles();
+    }
+
+    SourceRecord {
+        line_number: rowid,
+        byte_offset: None,
+        sheet_name: Some(table.to_string()),
+        page_number: None,
+        text,
+        event,
+        contact: None,
+    }
+}
+
+// Fields that are set, comma-joined, with times written out so no epoch reads as a number
+fn event_text(fields: &[Option<&str>]) -> String {
+    fields.iter().flatten().filter(|field| !field.trim().is_empty()).copied().collect::<Vec<_>>().join(",")
+}
+
+fn list(value: Option<String>, extractors: &Extractors) -> Vec<String> {
+    let mut parties: Vec<String> = Vec::new();
+    for party in value.iter().flat_map(|value| value.split(LIST_SEPARATOR)).filter_map(|p| chat::party(p, extractors)) {
+        if !parties.contains(&party) {
+            parties.push(party);
+        }
+    }
+    parties
+}
+
+fn unix_time(seconds: i64, nanos: u32) -> Option<String> {
+    DateTime::from_timestamp(seconds, nanos).map(|datetime| datetime.fixed_offset().to_rfc3339())
+}
+
+fn apple_time(value: f64) -> Option<String> {
+    let seconds = if value.abs() > 1e11 { value / 1e9 } else { value };
+    unix_time(APPLE_EPOCH + seconds.trunc() as i64, (seconds.fract().abs() * 1e9) as u32)
+}
+
+fn android_time(millis: i64) -> Option<String> {
+    unix_time(millis.div_euclid(1000), (millis.rem_euclid(1000) * 1_000_000) as u32)
+}
+
+fn device_event(
+    event_type: EventType,
+    direction: Option<Direction>,
+    party: Option<String>,
+    mut recipients: Vec<String>,
+    start_time: Option<String>,
+) -> CdrEvent {
+    // The handset's own number is rarely stored; the other party is the sender of what
+    // came in and a recipient of what went out
+    let originating = match direction {
+        Some(Direction::Outgoing) => None,
+        _ => party.clone(),
+    };
+    if direction == Some(Direction::Outgoing) {
+        if let Some(party) = party.filter(|p| !recipients.contains(p)) {
+            recipients.insert(0, party);
+        }
+    }
+    recipients.retain(|r| Some(r) != originating.as_ref());
+
+    CdrEvent {
+        carrier: None,
+        event_type,
+        direction,
+        originating,
+        terminating: (recipients.len() == 1).then(|| recipients[0].clone()),
+        dialed: None,
+        subscriber: None,
+        start_time,
+        duration_seconds: None,
+        cell_site: None,
+        sector: None,
+        imei: None,
+        imsi: None,
+        recipients,
+        attachments: Vec::new(),
+        roles: Vec::new(),
+    }
+}
+
+fn direction_name(direction: Option<Direction>) -> Option<&'static str> {
+    direction.map(|d| d.as_str())
+}
+
+// sms.db: each message with its handle, the members of its chat and its attachments
+async fn ios_messages(conn: &mut SqliteConnection, extractors: &Extractors) -> Result<Vec<SourceRecord>, AppError> {
+    let rows = sqlx::query(
+        r#"
+        SELECT m.ROWID AS row_id, CAST(m.text AS TEXT) AS text, CAST(m.date AS REAL) AS date,
+               CAST(m.is_from_me AS INTEGER) AS is_from_me, CAST(m.service AS TEXT) AS service,
+               CAST(h.id AS TEXT) AS handle,
+               (SELECT GROUP_CONCAT(member.id, char(31))
+                FROM chat_message_join cm
+                JOIN chat_handle_join ch ON ch.chat_id = cm.chat_id
+                JOIN handle member ON member.ROWID = ch.handle_id
+                WHERE cm.message_id = m.ROWID) AS members,
+               (SELECT GROUP_CONCAT(a.transfer_name, char(31))
+                FROM message_attachment_join ma
+                JOIN attachment a ON a.ROWID = ma.attachment_id
+                WHERE ma.message_id = m.ROWID) AS attachments
+        FROM message m
+        LEFT JOIN handle h ON h.ROWID = m.handle_id
+        ORDER BY m.ROWID
+        "#
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let records = rows.iter().map(|row| {
+        let service: Option<String> = row.get("service");
+        let direction = Some(if row.get::<Option<i64>, _>("is_from_me") == Some(1) { Direction::Outgoing } else { Direction::Incoming });
+        let handle: Option<String> = row.get("handle");
+        let start_time = row.get::<Option<f64>, _>("date").filter(|date| *date != 0.0).and_then(apple_time);
+        let attachments: Vec<String> = row.get::<Option<String>, _>("attachments")
+            .map(|names| names.split(LIST_SEPARATOR).map(str::to_string).collect())
+            .unwrap_or_default();
+        let text: Option<String> = row.get("text");
+
+        let event_type = match service.as_deref() {
+            Some("SMS" | "MMS" | "RCS") => EventType::Sms,
+            _ => EventType::Message,
+        };
+        let mut event = device_event(
+            event_type,
+            direction,
+            handle.as_deref().and_then(|h| chat::party(h, extractors)),
+            list(row.get("members"), extractors),
+            start_time.clone(),
+        );
+        event.attachments = attachments.clone();
+
+        let members = event.recipients.join(";");
+        let text = event_text(&[
+            start_time.as_deref(),
+            direction_name(direction),
+            service.as_deref(),
+            handle.as_deref(),
+            Some(&members),
+            text.as_deref(),
+            Some(&attachments.join(";")),
+        ]);
+        table_record("message", row.get("row_id"), text, Some(event))
+    }).collect();
+
+    Ok(records)
+}
+
+// CallHistory.storedata: Core Data's ZCALLRECORD, addresses stored as text or as blobs
+async fn ios_calls(conn: &mut SqliteConnection, extractors: &Extractors) -> Result<Vec<SourceRecord>, AppError> {
+    let rows = sqlx::query(
+        r#"
+        SELECT ROWID AS row_id, CAST(ZADDRESS AS TEXT) AS address, CAST(ZDATE AS REAL) AS date,
+               CAST(ZDURATION AS REAL) AS duration, CAST(ZORIGINATED AS INTEGER) AS originated,
+               CAST(ZANSWERED AS INTEGER) AS answered, CAST(ZCALLTYPE AS INTEGER) AS call_type
+        FROM ZCALLRECORD
+        ORDER BY ROWID
+        "#
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let records = rows.iter().map(|row| {
+        let address: Option<String> = row.get("address");
+        let direction = Some(if row.get::<Option<i64>, _>("originated") == Some(1) { Direction::Outgoing } else { Direction::Incoming });
+        let start_time = row.get::<Option<f64>, _>("date").and_then(apple_time);
+        let duration = row.get::<Option<f64>, _>("duration").map(|seconds| seconds.round() as i64);
+        let answered = row.get::<Option<i64>, _>("answered") == Some(1);
+        // 1 is a cellular call; 8 and 16 are FaceTime video and audio
+        let kind = match row.get::<Option<i64>, _>("call_type") {
+            Some(8) => Some("facetime video"),
+            Some(16) => Some("facetime audio"),
+            _ => None,
+        };
+
+        let mut event = device_event(EventType::Call, direction, address.as_deref().and_then(|a| chat::party(a, extractors)), Vec::new(), start_time.clone());
+        event.duration_seconds = duration;
+
+        let duration = duration.map(|seconds| seconds.to_string());
+        let text = event_text(&[
+            start_time.as_deref(),
+            direction_name(direction),
+            kind,
+            (!answered && direction == Some(Direction::Incoming)).then_some("missed"),
+            address.as_deref(),
+            duration.as_deref(),
+        ]);
+        table_record("ZCALLRECORD", row.get("row_id"), text, Some(event))
+    }).collect();
+
+    Ok(records)
+}
+
+// mmssms.db SMS, by box: 1 inbox, 2 sent, 3 draft, 4 outbox, 5 failed, 6 queued. Drafts
+// were never sent and get no event.
+async fn android_sms(conn: &mut SqliteConnection, extractors: &Extractors) -> Result<Vec<SourceRecord>, AppError> {
+    let rows = sqlx::query(
+        r#"
+        SELECT _id AS row_id, CAST(address AS TEXT) AS address, CAST(date AS INTEGER) AS date,
+               CAST(type AS INTEGER) AS type, CAST(body AS TEXT) AS body
+        FROM sms
+        ORDER BY _id
+        "#
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let records = rows.iter().map(|row| {
+        let address: Option<String> = row.get("address");
+        let start_time = row.get::<Option<i64>, _>("date").and_then(android_time);
+        let (direction, status) = match row.get::<Option<i64>, _>("type") {
+            Some(1) => (Some(Direction::Incoming), None),
+            Some(2) => (Some(Direction::Outgoing), None),
+            Some(3) => (None, Some("draft")),
+            Some(4) => (Some(Direction::Outgoing), Some("outbox")),
+            Some(5) => (Some(Direction::Outgoing), Some("failed")),
+            Some(6) => (Some(Direction::Outgoing), Some("queued")),
+            _ => (None, None),
+        };
+
+        let event = direction.map(|direction| {
+            device_event(EventType::Sms, Some(direction), address.as_deref().and_then(|a| chat::party(a, extractors)), Vec::new(), start_time.clone())
+        });
+        let body: Option<String> = row.get("body");
+        let text = event_text(&[start_time.as_deref(), direction_name(direction), status, address.as_deref(), body.as_deref()]);
+        table_record("sms", row.get("row_id"), text, event)
+    }).collect();
+
+    Ok(records)
+}
+
+// mmssms.db MMS: addresses by PDU header type (137 from, 151 to, 130 cc, 129 bcc), text
+// and media in parts. Times are in seconds.
+async fn android_mms(conn: &mut SqliteConnection, extractors: &Extractors) -> Result<Vec<SourceRecord>, AppError> {
+    let rows = sqlx::query(
+        r#"
+        SELECT p._id AS row_id, CAST(p.date AS INTEGER) AS date, CAST(p.msg_box AS INTEGER) AS msg_box,
+               (SELECT GROUP_CONCAT(a.address, char(31)) FROM addr a WHERE a.msg_id = p._id AND a.type = 137) AS sender,
+               (SELECT GROUP_CONCAT(a.address, char(31)) FROM addr a WHERE a.msg_id = p._id AND a.type IN (151, 130, 129)) AS recipients,
+               (SELECT GROUP_CONCAT(pt.text, char(31)) FROM part pt WHERE pt.mid = p._id AND pt.ct = 'text/plain') AS body,
+               (SELECT GROUP_CONCAT(COALESCE(pt.name, pt.cl), char(31)) FROM part pt
+                WHERE pt.mid = p._id AND pt.ct NOT IN ('text/plain', 'application/smil')) AS attachments
+        FROM pdu p
+        ORDER BY p._id
+        "#
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let records = rows.iter().map(|row| {
+        let start_time = row.get::<Option<i64>, _>("date").and_then(|seconds| unix_time(seconds, 0));
+        let direction = match row.get::<Option<i64>, _>("msg_box") {
+            Some(1) => Some(Direction::Incoming),
+            Some(2 | 4) => Some(Direction::Outgoing),
+            _ => None,
+        };
+        let sender: Option<String> = row.get::<Option<String>, _>("sender").filter(|s| s != MMS_OWN_ADDRESS);
+        let recipients = list(row.get("recipients"), extractors);
+        let attachments: Vec<String> = row.get::<Option<String>, _>("attachments")
+            .map(|names| names.split(LIST_SEPARATOR).map(str::to_string).collect())
+            .unwrap_or_default();
+
+        let mut event = device_event(
+            EventType::Sms,
+            direction,
+            None,
+            recipients,
+            start_time.clone(),
+        );
+        // Unlike SMS, MMS name the sender, the handset's own number included when known
+        event.originating = sender.as_deref().and_then(|s| chat::party(s, extractors));
+        event.attachments = attachments.clone();
+
+        let body: Option<String> = row.get("body");
+        let recipients = event.recipients.join(";");
+        let text = event_text(&[
+            start_time.as_deref(),
+            direction_name(direction),
+            sender.as_deref(),
+            Some(&recipients),
+            body.map(|body| body.replace(LIST_SEPARATOR, " ")).as_deref(),
+            Some(&attachments.join(";")),
+        ]);
+        table_record("pdu", row.get("row_id"), text, Some(event))
+    }).collect();
+
+    Ok(records)
+}
+
+// calllog.db or contacts2.db calls, by type: 1 incoming, 2 outgoing, 3 missed,
+// 4 voicemail, 5 rejected, 6 blocked, 7 answered elsewhere. The cached contact name is
+// kept as a contact of the number.
+async fn android_calls(conn: &mut SqliteConnection, extractors: &Extractors) -> Result<Vec<SourceRecord>, AppError> {
+    let rows = sqlx::query(
+        r#"
+        SELECT _id AS row_id, CAST(number AS TEXT) AS number, CAST(date AS INTEGER) AS date,
+               CAST(duration AS INTEGER) AS duration, CAST(type AS INTEGER) AS type, CAST(name AS TEXT) AS name
+        FROM calls
+        ORDER BY _id
+        "#
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let kinds: HashMap<i64, &str> = [(3, "missed"), (4, "voicemail"), (5, "rejected"), (6, "blocked"), (7, "answered externally")].into();
+    let records = rows.iter().map(|row| {
+        let number: Option<String> = row.get("number");
+        let name: Option<String> = row.get("name");
+        let start_time = row.get::<Option<i64>, _>("date").and_then(android_time);
+        let duration: Option<i64> = row.get("duration");
+        let kind = row.get::<Option<i64>, _>("type");
+        let direction = match kind {
+            Some(2) => Some(Direction::Outgoing),
+            Some(_) => Some(Direction::Incoming),
+            None => None,
+        };
+
+        let party = number.as_deref().and_then(|n| chat::party(n, extractors));
+        let mut event = device_event(EventType::Call, direction, party, Vec::new(), start_time.clone());
+        event.duration_seconds = duration;
+
+        let duration = duration.map(|seconds| seconds.to_string());
+        let text = event_text(&[
+            start_time.as_deref(),
+            direction_name(direction),
+            kind.and_then(|kind| kinds.get(&kind).copied()),
+            number.as_deref(),
+            name.as_deref(),
+            duration.as_deref(),
+        ]);
+
+        let mut record = table_record("calls", row.get("row_id"), text, Some(event));
+        let phone = number.as_deref().and_then(|n| extractors.phone_in(n));
+        if let (Some(name), Some(phone)) = (name.filter(|n| !n.trim().is_empty()), phone) {
+            record.contact = Some(Contact {
+                name: name.trim().to_string(),
+                values: vec![ContactValue { value: phone.e164, item_type: ItemType::Phone, label: None }],
+            });
+        }
+        record
+    }).collect();
+
+    Ok(records)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The bytes of a database built by `sql`
+    async fn sqlite(name: &str, sql: &str) -> Vec<u8> {
+        let path = std::env::temp_dir().join(format!("cast-{}-{}.db", name, std::process::id()));
+        let options = SqliteConnectOptions::new().filename(&path).create_if_missing(true);
+        let mut conn = SqliteConnection::connect_with(&options).await.unwrap();
+        sqlx::raw_sql(sql).execute(&mut conn).await.unwrap();
+        conn.close().await.unwrap();
+        let content = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        content
+    }
+
+    fn events(database: &DeviceDatabase) -> Vec<&CdrEvent> {
+        database.records.iter().filter_map(|record| record.event.as_ref()).collect()
+    }
+
+    #[tokio::test]
+    async fn reads_ios_messages_with_their_chat() {
+        let content = sqlite("sms", r#"
+            CREATE TABLE handle (ROWID INTEGER PRIMARY KEY, id TEXT);
+            CREATE TABLE message (ROWID INTEGER PRIMARY KEY, text TEXT, date INTEGER, is_from_me INTEGER, service TEXT, handle_id INTEGER);
+            CREATE TABLE chat_message_join (chat_id INTEGER, message_id INTEGER);
+            CREATE TABLE chat_handle_join (chat_id INTEGER, handle_id INTEGER);
+            CREATE TABLE attachment (ROWID INTEGER PRIMARY KEY, transfer_name TEXT);
+            CREATE TABLE message_attachment_join (message_id INTEGER, attachment_id INTEGER);
+            INSERT INTO handle VALUES (1, '+12025550143'), (2, 'bob@example.com');
+            INSERT INTO message VALUES (1, 'hi', 727019100000000000, 0, 'iMessage', 1), (2, 'photo', 727019160, 1, 'SMS', 1);
+            INSERT INTO chat_message_join VALUES (1, 1), (1, 2);
+            INSERT INTO chat_handle_join VALUES (1, 1), (1, 2);
+            INSERT INTO attachment VALUES (1, 'IMG_0001.HEIC');
+            INSERT INTO message_attachment_join VALUES (2, 1);
+        "#).await;
+        assert!(is_sqlite(&content));
+
+        let database = read(&content, &Extractors::new()).await.unwrap();
+        assert_eq!(database.schema, DeviceSchema::IosSms);
+        let events = events(&database);
+        assert_eq!(events.len(), 2);
+
+        // Nanoseconds since 2001 in current versions, seconds in older ones
+        assert_eq!(events[0].start_time.as_deref(), Some("2024-01-15T13:45:00+00:00"));
+        assert_eq!(events[1].start_time.as_deref(), Some("2024-01-15T13:46:00+00:00"));
+        assert_eq!(events[0].event_type, EventType::Message);
+        assert_eq!(events[0].originating.as_deref(), Some("+12025550143"));
+        assert_eq!(events[0].recipients, ["bob@example.com"]);
+        assert_eq!(events[1].event_type, EventType::Sms);
+        assert_eq!(events[1].direction, Some(Direction::Outgoing));
+        assert_eq!(events[1].originating, None);
+        assert_eq!(events[1].recipients, ["+12025550143", "bob@example.com"]);
+        assert_eq!(events[1].attachments, ["IMG_0001.HEIC"]);
+    }
+
+    #[tokio::test]
+    async fn reads_android_sms_and_calls() {
+        let content = sqlite("mmssms", r#"
+            CREATE TABLE sms (_id INTEGER PRIMARY KEY, address TEXT, date INTEGER, type INTEGER, body TEXT);
+            INSERT INTO sms VALUES (1, '(202) 555-0143', 1705326300000, 1, 'hi'), (2, '2025550143', 1705326360000, 2, 'ok'), (3, NULL, 1705326400000, 3, 'draft');
+        "#).await;
+        let database = read(&content, &Extractors::new()).await.unwrap();
+        assert_eq!(database.schema, DeviceSchema::AndroidSms);
+        assert_eq!(database.records.len(), 3);
+        let events = events(&database);
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].originating.as_deref(), Some("+12025550143"));
+        assert_eq!(events[1].recipients, ["+12025550143"]);
+        assert_eq!(database.records[2].text, "2024-01-15T13:46:40+00:00,draft,draft");
+        assert_eq!(database.records[2].sheet_name.as_deref(), Some("sms"));
+
+        let content = sqlite("calllog", r#"
+            CREATE TABLE calls (_id INTEGER PRIMARY KEY, number TEXT, date INTEGER, duration INTEGER, type INTEGER, name TEXT);
+            INSERT INTO calls VALUES (7, '3125550178', 1705326300000, 65, 3, 'Carol');
+        "#).await;
+        let database = read(&content, &Extractors::new()).await.unwrap();
+        assert_eq!(database.schema, DeviceSchema::AndroidCalls);
+        let record = &database.records[0];
+        assert_eq!(record.line_number, 7);
+        assert_eq!(record.text, "2024-01-15T13:45:00+00:00,incoming,missed,3125550178,Carol,65");
+        assert_eq!(record.event.as_ref().unwrap().duration_seconds, Some(65));
+        let contact = record.contact.as_ref().unwrap();
+        assert_eq!((contact.name.as_str(), contact.values[0].value.as_str()), ("Carol", "+13125550178"));
+    }
+
+    #[tokio::test]
+    async fn other_tables_are_read_as_their_text() {
+        let content = sqlite("other", r#"
+            CREATE TABLE notes (id INTEGER PRIMARY KEY, body TEXT, updated INTEGER);
+            INSERT INTO notes VALUES (1, 'call 202-555-0143', 20240115), (2, NULL, 5);
+            CREATE TABLE calls (_id INTEGER PRIMARY KEY, phone TEXT);
+            INSERT INTO calls VALUES (1, '3125550178');
+        "#).await;
+        let database = read(&content, &Extractors::new()).await.unwrap();
+        // A calls table without the columns calllog.db has is read like any other
+        assert_eq!(database.schema, DeviceSchema::AndroidCalls);
+        let texts: Vec<(&str, &str)> = database.records.iter().map(|r| (r.sheet_name.as_deref().unwrap(), r.text.as_str())).collect();
+        assert_eq!(texts, [("calls", "3125550178"), ("notes", "call 202-555-0143")]);
+        assert!(database.records.iter().all(|record| record.event.is_none()));
+
+        assert!(read(b"SQLite format 3\0truncated", &Extractors::new()).await.is_err());
+    }
+}
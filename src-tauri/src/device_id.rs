@@ -0,0 +1,238 @@
+// IMSI, IMEI/IMEISV and ICCID decoding and check-digit validation
+use crate::models::{ItemAttributes, ItemType};
+
+pub struct MobileCountry {
+    pub mcc: &'static str,
+    pub region: &'static str,
+    pub mnc_len: usize,
+}
+
+macro_rules! mcc {
+    ($mcc:expr, $region:expr, $mnc_len:expr) => {
+        MobileCountry { mcc: $mcc, region: $region, mnc_len: $mnc_len }
+    };
+}
+
+pub static MOBILE_COUNTRIES: &[MobileCountry] = &[
+    mcc!("202", "GR", 2), mcc!("204", "NL", 2), mcc!("206", "BE", 2), mcc!("208", "FR", 2),
+    mcc!("214", "ES", 2), mcc!("216", "HU", 2), mcc!("222", "IT", 2), mcc!("226", "RO", 2),
+    mcc!("228", "CH", 2), mcc!("232", "AT", 2), mcc!("234", "GB", 2), mcc!("235", "GB", 2),
+    mcc!("238", "DK", 2), mcc!("240", "SE", 2), mcc!("242", "NO", 2), mcc!("244", "FI", 2),
+    mcc!("246", "LT", 2), mcc!("247", "LV", 2), mcc!("248", "EE", 2), mcc!("250", "RU", 2),
+    mcc!("255", "UA", 2), mcc!("260", "PL", 2), mcc!("262", "DE", 2), mcc!("268", "PT", 2),
+    mcc!("272", "IE", 2), mcc!("274", "IS", 2), mcc!("286", "TR", 2), mcc!("302", "CA", 3),
+    mcc!("310", "US", 3), mcc!("311", "US", 3), mcc!("312", "US", 3), mcc!("313", "US", 3),
+    mcc!("314", "US", 3), mcc!("315", "US", 3), mcc!("316", "US", 3), mcc!("330", "PR", 3),
+    mcc!("334", "MX", 3), mcc!("338", "JM", 3), mcc!("370", "DO", 2), mcc!("372", "HT", 2),
+    mcc!("404", "IN", 2), mcc!("405", "IN", 2), mcc!("410", "PK", 2), mcc!("412", "AF", 2),
+    mcc!("413", "LK", 2), mcc!("414", "MM", 2), mcc!("415", "LB", 2), mcc!("416", "JO", 2),
+    mcc!("417", "SY", 2), mcc!("418", "IQ", 2), mcc!("419", "KW", 2), mcc!("420", "SA", 2),
+    mcc!("422", "OM", 2), mcc!("424", "AE", 2), mcc!("425", "IL", 2), mcc!("426", "BH", 2),
+    mcc!("427", "QA", 2), mcc!("429", "NP", 2), mcc!("432", "IR", 2), mcc!("440", "JP", 2),
+    mcc!("441", "JP", 2), mcc!("450", "KR", 2), mcc!("452", "VN", 2), mcc!("454", "HK", 2),
+    mcc!("455", "MO", 2), mcc!("456", "KH", 2), mcc!("460", "CN", 2), mcc!("466", "TW", 2),
+    mcc!("470", "BD", 2), mcc!("502", "MY", 2), mcc!("505", "AU", 2), mcc!("510", "ID", 2),
+    mcc!("515", "PH", 2), mcc!("520", "TH", 2), mcc!("525", "SG", 2), mcc!("530", "NZ", 2),
+    mcc!("602", "EG", 2), mcc!("603", "DZ", 2), mcc!("604", "MA", 2), mcc!("605", "TN", 2),
+    mcc!("606", "LY", 2), mcc!("620", "GH", 2), mcc!("621", "NG", 2), mcc!("639", "KE", 2),
+    mcc!("640", "TZ", 2), mcc!("641", "UG", 2), mcc!("655", "ZA", 2), mcc!("704", "GT", 2),
+    mcc!("706", "SV", 2), mcc!("708", "HN", 3), mcc!("710", "NI", 2), mcc!("712", "CR", 2),
+    mcc!("714", "PA", 3), mcc!("716", "PE", 2), mcc!("722", "AR", 3), mcc!("724", "BR", 2),
+    mcc!("730", "CL", 2), mcc!("732", "CO", 3), mcc!("734", "VE", 2), mcc!("736", "BO", 2),
+    mcc!("740", "EC", 2), mcc!("744", "PY", 2), mcc!("748", "UY", 2), mcc!("901", "International", 2),
+];
+
+// GSMA reporting-body prefixes that begin allocated TACs
+const TAC_REPORTING_BODIES: &[&str] = &[
+    "01", "10", "30", "33", "35", "44", "45", "49", "50", "51", "52", "53", "54", "86", "91", "98", "99",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DeviceIdentifier {
+    pub item_type: ItemType,
+    pub value: String,
+    pub attributes: ItemAttributes,
+}
+
+pub fn mobile_country(mcc: &str) -> Option<&'static MobileCountry> {
+    MOBILE_COUNTRIES.iter().find(|c| c.mcc == mcc)
+}
+
+fn digit_values(digits: &str) -> impl DoubleEndedIterator<Item = u32> + '_ {
+    digits.chars().filter_map(|c| c.to_digit(10))
+}
+
+// Check digit that makes `payload` + digit pass the Luhn test
+pub fn luhn_check_digit(payload: &str) -> u32 {
+    let sum: u32 = digit_values(payload)
+        .rev()
+        .enumerate()
+        .map(|(i, d)| {
+            if i % 2 == 0 {
+                let doubled = d * 2;
+                if doubled > 9 { doubled - 9 } else { doubled }
+            } else {
+                d
+            }
+        })
+        .sum();
+    (10 - sum % 10) % 10
+}
+
+pub fn luhn_valid(digits: &str) -> bool {
+    let (payload, check) = match digits.len() {
+        0 | 1 => return false,
+        len => digits.split_at(len - 1),
+    };
+    check.parse::<u32>().ok() == Some(luhn_check_digit(payload))
+}
+
+fn attributes(pairs: &[(&str, &str)]) -> ItemAttributes {
+    pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+}
+
+pub fn parse_imsi(digits: &str) -> Option<DeviceIdentifier> {
+    if !(14..=15).contains(&digits.len()) {
+        return None;
+    }
+
+    let country = mobile_country(&digits[..3])?;
+    let mnc = &digits[3..3 + country.mnc_len];
+
+    Some(DeviceIdentifier {
+        item_type: ItemType::Imsi,
+        value: digits.to_string(),
+        attributes: attributes(&[("mcc", country.mcc), ("mnc", mnc), ("country", country.region)]),
+    })
+}
+
+// 15-digit IMEIs must pass Luhn; a 14-digit body gets its check digit computed
+pub fn parse_imei(digits: &str) -> Option<DeviceIdentifier> {
+    let imei = match digits.len() {
+        14 => format!("{}{}", digits, luhn_check_digit(digits)),
+        15 if luhn_valid(digits) => digits.to_string(),
+        _ => return None,
+    };
+
+    Some(DeviceIdentifier {
+        item_type: ItemType::Imei,
+        attributes: attributes(&[("tac", &imei[..8]), ("format", "IMEI")]),
+        value: imei,
+    })
+}
+
+// IMEISV carries a 2-digit software version instead of a check digit; it is stored
+// under the equivalent IMEI so both forms of the same handset match.
+pub fn parse_imeisv(digits: &str) -> Option<DeviceIdentifier> {
+    if digits.len() != 16 {
+        return None;
+    }
+
+    let body = &digits[..14];
+    let imei = format!("{}{}", body, luhn_check_digit(body));
+
+    Some(DeviceIdentifier {
+        item_type: ItemType::Imei,
+        attributes: attributes(&[("tac", &imei[..8]), ("svn", &digits[14..]), ("format", "IMEISV")]),
+        value: imei,
+    })
+}
+
+pub fn parse_iccid(digits: &str) -> Option<DeviceIdentifier> {
+    if !(18..=22).contains(&digits.len()) || !digits.starts_with("89") || !luhn_valid(digits) {
+        return None;
+    }
+
+    // E.118 allows the country code to be zero-padded, e.g. "8901..." for North America
+    let country_digits = digits[2..].strip_prefix('0').unwrap_or(&digits[2..]);
+    let issuer_country = crate::phone::COUNTRIES
+        .iter()
+        .rev()
+        .filter(|c| country_digits.starts_with(c.calling_code))
+        .max_by_key(|c| c.calling_code.len())
+        .map(|c| c.region);
+
+    let mut attrs = attributes(&[("issuer_prefix", &digits[..7])]);
+    if let Some(region) = issuer_country {
+        attrs.insert("country".to_string(), region.to_string());
+    }
+
+    Some(DeviceIdentifier {
+        item_type: ItemType::Iccid,
+        value: digits.to_string(),
+        attributes: attrs,
+    })
+}
+
+// Best guess for an unlabelled 15-digit value: IMEIs always pass Luhn and start with
+// a reporting-body prefix. Any run of digits opening with a country code would pass for
+// an IMSI, so those are only taken where a label or column names them.
+pub fn classify(digits: &str) -> Option<DeviceIdentifier> {
+    if digits.len() != 15 || !TAC_REPORTING_BODIES.contains(&&digits[..2]) {
+        return None;
+    }
+
+    parse_imei(digits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn luhn_check_digits() {
+        assert_eq!(luhn_check_digit("7992739871"), 3);
+        assert!(luhn_valid("79927398713"));
+        assert!(!luhn_valid("79927398710"));
+        assert!(!luhn_valid("7"));
+    }
+
+    #[test]
+    fn imeis_pass_luhn_or_get_their_check_digit() {
+        let imei = parse_imei("490154203237518").unwrap();
+        assert_eq!(imei.item_type, ItemType::Imei);
+        assert_eq!(imei.attributes["tac"], "49015420");
+        assert_eq!(parse_imei("49015420323751").unwrap().value, "490154203237518");
+        assert_eq!(parse_imei("490154203237519"), None);
+        assert_eq!(parse_imei("4901542032375"), None);
+    }
+
+    #[test]
+    fn imeisvs_are_stored_as_their_imei() {
+        let imeisv = parse_imeisv("4901542032375101").unwrap();
+        assert_eq!(imeisv.value, "490154203237518");
+        assert_eq!(imeisv.attributes["svn"], "01");
+        assert_eq!(parse_imeisv("490154203237518"), None);
+    }
+
+    #[test]
+    fn iccids_start_with_89_and_pass_luhn() {
+        let iccid = parse_iccid("89012600000000000003").unwrap();
+        assert_eq!(iccid.attributes["country"], "US");
+        assert_eq!(parse_iccid("89441000303120000003").unwrap().attributes["country"], "GB");
+        assert_eq!(parse_iccid("89012600000000000004"), None);
+        assert_eq!(parse_iccid("79012600000000000003"), None);
+        assert_eq!(parse_iccid("8901260000000003"), None);
+    }
+
+    #[test]
+    fn imsis_need_a_known_country() {
+        let imsi = parse_imsi("310150123456789").unwrap();
+        assert_eq!(imsi.attributes["mcc"], "310");
+        assert_eq!(imsi.attributes["mnc"], "150");
+        assert_eq!(imsi.attributes["country"], "US");
+        assert_eq!(parse_imsi("234150123456789").unwrap().attributes["mnc"], "15");
+        assert_eq!(parse_imsi("999150123456789"), None);
+        assert_eq!(parse_imsi("3101501234567"), None);
+    }
+
+    #[test]
+    fn unlabelled_values_are_only_taken_as_imeis() {
+        assert_eq!(classify("490154203237518").map(|id| id.item_type), Some(ItemType::Imei));
+        // Passes Luhn and opens with a country code, but no TAC starts with 31
+        assert_eq!(classify("310150123456785"), None);
+        assert_eq!(classify("310150123456789"), None);
+        assert_eq!(classify("212345678901235"), None);
+        assert_eq!(classify("49015420323751"), None);
+    }
+}
@@ -0,0 +1,901 @@
+// Extraction reports exported by mobile-forensics suites: the report XML on its own, or as
+// report.xml inside a .ufdr zip next to the files extracted from the handset. The report
+// is a list of typed models (contacts, calls, SMS, chats and their messages, emails, ...)
+// made of named fields and nested models, preceded by metadata sections describing the
+// device. Models are read one at a time and become one record each; chat messages are
+// records of their own, read with the chat they belong to. Each record is numbered by
+// the line of the report its model starts on.
+use std::fs::File;
+use std::io::{BufRead, BufReader, Cursor, Read, Seek};
+use std::path::Path;
+use chrono::{DateTime, NaiveDateTime};
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use tokio::sync::mpsc;
+use crate::archive::ZIP_MAGIC;
+use crate::cdr;
+use crate::chat;
+use crate::contacts;
+use crate::device_id;
+use crate::error::AppError;
+use crate::extractors::Extractors;
+use crate::models::{CdrEvent, Contact, Direction, EventType, ItemAttributes, ItemType, SourceRecord};
+
+// The report inside a .ufdr
+pub const REPORT_ENTRY: &str = "report.xml";
+// Models read ahead of the ingestion writing them
+const READ_AHEAD: usize = 64;
+// A report names its root and markers near the top; only this much of a file is searched
+const HEAD_BYTES: usize = 64 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    Xml,
+    Ufdr,
+}
+
+impl ReportFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReportFormat::Xml => "report_xml",
+            ReportFormat::Ufdr => "ufdr",
+        }
+    }
+}
+
+// A report's root is <project>, followed by its metadata sections or decoded data
+pub fn is_report(head: &[u8]) -> bool {
+    let text = String::from_utf8_lossy(&head[..head.len().min(HEAD_BYTES)]);
+    let text = text.trim_start_matches('\u{FEFF}');
+
+    let mut rest = text;
+    let root = loop {
+        let Some(start) = rest.find('<') else { return false };
+        rest = &rest[start..];
+        let skip = if rest.starts_with("<!--") { rest.find("-->").map(|end| end + 3) } else if rest.starts_with("<?") || rest.starts_with("<!") { rest.find('>').map(|end| end + 1) } else { break rest };
+        let Some(skip) = skip else { return false };
+        rest = &rest[skip..];
+    };
+
+    let name = root[1..].split(|c: char| c.is_whitespace() || c == '>' || c == '/').next().unwrap_or("");
+    let name = name.rsplit(':').next().unwrap_or(name);
+    name == "project" && ["pa.cellebrite.com/report", "<metadata", "<decodedData"].iter().any(|marker| text.contains(marker))
+}
+
+// A zip holding the report at its root
+pub fn is_ufdr<R: Read + Seek>(reader: R) -> bool {
+    zip::ZipArchive::new(reader).ok().and_then(|archive| report_entry(&archive)).is_some()
+}
+
+// Only the head of a file and the entry names of a zip are looked at
+pub fn detect(content: &[u8]) -> Option<ReportFormat> {
+    if is_report(content) {
+        Some(ReportFormat::Xml)
+    } else if content.starts_with(ZIP_MAGIC) && is_ufdr(Cursor::new(content)) {
+        Some(ReportFormat::Ufdr)
+    } else {
+        None
+    }
+}
+
+fn report_entry<R: Read + Seek>(archive: &zip::ZipArchive<R>) -> Option<usize> {
+    let name = archive.file_names().find(|name| name.eq_ignore_ascii_case(REPORT_ENTRY))?;
+    archive.index_for_name(name)
+}
+
+fn zip_error(e: zip::result::ZipError) -> AppError {
+    AppError::Archive(e.to_string())
+}
+
+pub struct DeviceReport {
+    pub records: Vec<SourceRecord>,
+    pub metadata: ItemAttributes,
+}
+
+// A report already in memory, e.g. one inside an archive
+pub fn read(content: &[u8], extractors: &Extractors) -> Result<DeviceReport, AppError> {
+    let format = detect(content).ok_or(AppError::InvalidFormat)?;
+    let mut records = ReportRecords::new(format);
+
+    let read = match format {
+        ReportFormat::Xml => records.read_all(ReportReader::new(content), extractors)?,
+        ReportFormat::Ufdr => {
+            let mut archive = zip::ZipArchive::new(Cursor::new(content)).map_err(zip_error)?;
+            let index = report_entry(&archive).ok_or(AppError::InvalidFormat)?;
+            let entry = archive.by_index(index).map_err(zip_error)?;
+            records.read_all(ReportReader::new(BufReader::new(entry)), extractors)?
+        }
+    };
+
+    Ok(DeviceReport { records: read, metadata: records.metadata() })
+}
+
+// A report read from disk on its own thread, handing over one entry at a time, so neither
+// the XML nor the zip it is in is ever held in memory. Dropping the stream stops the thread.
+pub struct ReportStream {
+    format: ReportFormat,
+    entries: mpsc::Receiver<Result<(ReportEntry, u64), AppError>>,
+    bytes_read: u64,
+}
+
+impl ReportStream {
+    pub fn open(path: &Path, format: ReportFormat) -> Self {
+        let (sender, entries) = mpsc::channel(READ_AHEAD);
+        let path = path.to_path_buf();
+
+        std::thread::spawn(move || {
+            let read = || -> Result<(), AppError> {
+                let file = File::open(&path)?;
+                match format {
+                    ReportFormat::Xml => send_entries(ReportReader::new(BufReader::new(file)), &sender),
+                    ReportFormat::Ufdr => {
+                        let mut archive = zip::ZipArchive::new(file).map_err(zip_error)?;
+                        let index = report_entry(&archive).ok_or(AppError::InvalidFormat)?;
+                        let entry = archive.by_index(index).map_err(zip_error)?;
+                        send_entries(ReportReader::new(BufReader::new(entry)), &sender)
+                    }
+                }
+            };
+            if let Err(e) = read() {
+                let _ = sender.blocking_send(Err(e));
+            }
+        });
+
+        Self { format, entries, bytes_read: 0 }
+    }
+
+    pub fn format(&self) -> ReportFormat {
+        self.format
+    }
+
+    pub async fn next(&mut self) -> Result<Option<ReportEntry>, AppError> {
+        match self.entries.recv().await {
+            Some(Ok((entry, bytes_read))) => {
+                self.bytes_read = bytes_read;
+                Ok(Some(entry))
+            }
+            Some(Err(e)) => Err(e),
+            None => Ok(None),
+        }
+    }
+
+    // Of the report XML, which for a .ufdr is the uncompressed report.xml
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes_read
+    }
+}
+
+fn send_entries<R: BufRead>(mut reader: ReportReader<R>, sender: &mpsc::Sender<Result<(ReportEntry, u64), AppError>>) -> Result<(), AppError> {
+    while let Some(entry) = reader.next_entry()? {
+        if sender.blocking_send(Ok((entry, reader.bytes_read()))).is_err() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+// A <model> with its <field> values and the models nested in its modelField and
+// multiModelField elements, under those elements' names
+#[derive(Debug, Clone, Default)]
+pub struct Model {
+    kind: String,
+    line_number: i64,
+    deleted: bool,
+    fields: Vec<Field>,
+    children: Vec<(String, Model)>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Field {
+    name: String,
+    // The value's type, e.g. String, TimeStamp, Int32
+    kind: String,
+    value: String,
+}
+
+impl Model {
+    fn field(&self, name: &str) -> Option<&str> {
+        self.fields.iter().find(|f| f.name == name && !f.value.is_empty()).map(|f| f.value.as_str())
+    }
+
+    fn children<'a>(&'a self, container: &'a str) -> impl Iterator<Item = &'a Model> + 'a {
+        self.children.iter().filter(move |(name, _)| name == container).map(|(_, model)| model)
+    }
+
+    // Text and times, here and in nested models; numbers are left out so ids and counts
+    // are not read as phone numbers
+    fn text_values(&self, values: &mut Vec<String>) {
+        for field in &self.fields {
+            let value = account(&field.value);
+            if matches!(field.kind.as_str(), "" | "String" | "TimeStamp") && !values.contains(&value) {
+                values.push(value);
+            }
+        }
+        for (_, child) in &self.children {
+            child.text_values(values);
+        }
+    }
+}
+
+pub enum ReportEntry {
+    // A <metadata> section's items, as (name, value)
+    Metadata { section: String, line_number: i64, items: Vec<(String, String)> },
+    // A model of the decoded data; a chat message comes with its chat, messages excluded
+    Model { model: Model, chat: Option<Model> },
+}
+
+// Elements open around the reader's position
+enum Frame {
+    Metadata { section: String, line_number: i64, items: Vec<(String, String)> },
+    Item { name: String, value: String },
+    Model(Model),
+    // modelField or multiModelField
+    Container(String),
+    Field(Field),
+    Value { kind: String, value: String },
+    Other,
+}
+
+// Counts the lines quick-xml has consumed, so each model gets the line it starts on
+struct LineCounter<R> {
+    inner: R,
+    lines: i64,
+    bytes: u64,
+}
+
+impl<R: BufRead> Read for LineCounter<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let available = self.fill_buf()?;
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<R: BufRead> BufRead for LineCounter<R> {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        // Already buffered, so this does no I/O
+        if let Ok(buffer) = self.inner.fill_buf() {
+            self.lines += buffer[..amt.min(buffer.len())].iter().filter(|&&b| b == b'\n').count() as i64;
+        }
+        self.bytes += amt as u64;
+        self.inner.consume(amt);
+    }
+}
+
+pub struct ReportReader<R: BufRead> {
+    reader: Reader<LineCounter<R>>,
+    buffer: Vec<u8>,
+    open: Vec<Frame>,
+}
+
+impl<R: BufRead> ReportReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            reader: Reader::from_reader(LineCounter { inner, lines: 0, bytes: 0 }),
+            buffer: Vec::new(),
+            open: Vec::new(),
+        }
+    }
+
+    pub fn bytes_read(&self) -> u64 {
+        self.reader.get_ref().bytes
+    }
+
+    pub fn next_entry(&mut self) -> Result<Option<ReportEntry>, AppError> {
+        loop {
+            // Whitespace between elements is its own event, so this is where the tag starts
+            let line_number = self.reader.get_ref().lines + 1;
+            self.buffer.clear();
+            let event = self.reader.read_event_into(&mut self.buffer).map_err(|e| {
+                AppError::Parse(format!("Report XML at line {}: {}", line_number, e))
+            })?;
+
+            let entry = match event {
+                Event::Start(start) => {
+                    let frame = frame(&start, line_number, &self.open);
+                    self.open.push(frame);
+                    None
+                }
+                Event::Empty(start) => {
+                    let frame = frame(&start, line_number, &self.open);
+                    self.close(frame)
+                }
+                Event::End(_) => match self.open.pop() {
+                    Some(frame) => self.close(frame),
+                    None => None,
+                },
+                Event::Text(text) => {
+                    let text = text.unescape().map(|t| t.into_owned()).unwrap_or_else(|_| String::from_utf8_lossy(&text).into_owned());
+                    self.append(&text);
+                    None
+                }
+                Event::CData(data) => {
+                    let text = String::from_utf8_lossy(&data.into_inner()).into_owned();
+                    self.append(&text);
+                    None
+                }
+                Event::Eof => return Ok(None),
+                _ => None,
+            };
+
+            if entry.is_some() {
+                return Ok(entry);
+            }
+        }
+    }
+
+    fn append(&mut self, text: &str) {
+        if let Some(Frame::Item { value, .. } | Frame::Value { value, .. }) = self.open.last_mut() {
+            value.push_str(text);
+        }
+    }
+
+    fn close(&mut self, frame: Frame) -> Option<ReportEntry> {
+        match frame {
+            Frame::Metadata { section, line_number, items } => Some(ReportEntry::Metadata { section, line_number, items }),
+            Frame::Item { name, value } => {
+                if let Some(Frame::Metadata { items, .. }) = self.open.last_mut() {
+                    let value = value.trim();
+                    if !value.is_empty() {
+                        items.push((name, value.to_string()));
+                    }
+                }
+                None
+            }
+            Frame::Value { kind, value } => {
+                // A multi-valued field lists its values one after another
+                if let Some(Frame::Field(field)) = self.open.last_mut() {
+                    let value = value.trim();
+                    if !value.is_empty() {
+                        if !field.value.is_empty() {
+                            field.value.push(';');
+                        }
+                        field.value.push_str(value);
+                    }
+                    if !kind.is_empty() {
+                        field.kind = kind;
+                    }
+                }
+                None
+            }
+            Frame::Field(field) => {
+                if let Some(Frame::Model(model)) = self.open.last_mut() {
+                    if !field.value.is_empty() {
+                        model.fields.push(field);
+                    }
+                }
+                None
+            }
+            Frame::Model(model) => {
+                if let [.., Frame::Model(parent), Frame::Container(name)] = &mut self.open[..] {
+                    // Messages are records of their own; other nested models stay with their parent
+                    if name == "Messages" {
+                        return Some(ReportEntry::Model { model, chat: Some(parent.clone()) });
+                    }
+                    parent.children.push((name.clone(), model));
+                    return None;
+                }
+                // Models directly under <modelType> are the report's entries
+                (!self.open.iter().any(|frame| matches!(frame, Frame::Model(_)))).then_some(ReportEntry::Model { model, chat: None })
+            }
+            Frame::Container(_) | Frame::Other => None,
+        }
+    }
+}
+
+fn frame(start: &BytesStart, line_number: i64, open: &[Frame]) -> Frame {
+    let attribute = |name: &str| {
+        start.attributes().flatten()
+            .find(|a| a.key.local_name().as_ref() == name.as_bytes())
+            .and_then(|a| a.unescape_value().ok().map(|v| v.into_owned()))
+    };
+
+    match start.local_name().as_ref() {
+        b"metadata" => Frame::Metadata { section: attribute("section").unwrap_or_default(), line_number, items: Vec::new() },
+        b"item" if matches!(open.last(), Some(Frame::Metadata { .. })) => Frame::Item { name: attribute("name").unwrap_or_default(), value: String::new() },
+        b"model" => Frame::Model(Model {
+            kind: attribute("type").unwrap_or_default(),
+            line_number,
+            deleted: attribute("deleted_state").is_some_and(|state| state.eq_ignore_ascii_case("deleted")),
+            ..Default::default()
+        }),
+        b"modelField" | b"multiModelField" => Frame::Container(attribute("name").unwrap_or_default()),
+        b"field" => Frame::Field(Field { name: attribute("name").unwrap_or_default(), kind: attribute("type").unwrap_or_default(), value: String::new() }),
+        b"value" => Frame::Value { kind: attribute("type").unwrap_or_default(), value: String::new() },
+        _ => Frame::Other,
+    }
+}
+
+// What the report says about the handset, taken from its metadata and device-info models
+#[derive(Debug, Default)]
+struct DeviceInfo {
+    imei: Option<String>,
+    imsi: Option<String>,
+    iccid: Option<String>,
+    msisdn: Option<String>,
+    // A serial number or UDID, for handsets without an IMEI
+    identifier: Option<String>,
+}
+
+impl DeviceInfo {
+    // Returns an IMEI, IMSI or ICCID labelled the way the extractors read one, so it
+    // becomes an item however the report spaced it or whatever the report named it
+    fn note(&mut self, name: &str, value: &str, extractors: &Extractors) -> Option<String> {
+        let key: String = name.chars().filter(char::is_ascii_alphanumeric).collect::<String>().to_ascii_lowercase();
+        let digits: String = value.chars().filter(char::is_ascii_digit).collect();
+
+        let (label, id, slot) = if key.contains("imei") {
+            ("IMEI", device_id::parse_imei(&digits).or_else(|| device_id::parse_imeisv(&digits)), &mut self.imei)
+        } else if key.contains("imsi") {
+            ("IMSI", device_id::parse_imsi(&digits), &mut self.imsi)
+        } else if key.contains("iccid") {
+            ("ICCID", device_id::parse_iccid(&digits), &mut self.iccid)
+        } else {
+            if (key.contains("msisdn") || key.ends_with("phonenumber")) && self.msisdn.is_none() {
+                self.msisdn = extractors.phone_in(value).map(|phone| phone.e164);
+            } else if ["uniqueid", "udid", "serial", "serialnumber"].iter().any(|id| key.ends_with(id)) && self.identifier.is_none() {
+                self.identifier = Some(value.trim().to_string());
+            }
+            return None;
+        };
+
+        let id = id?;
+        slot.get_or_insert_with(|| id.value.clone());
+        Some(if name.eq_ignore_ascii_case(label) {
+            format!("{}: {}", label, id.value)
+        } else {
+            format!("{} ({}): {}", name, label, id.value)
+        })
+    }
+
+    // The handset the report was extracted from
+    fn source(&self) -> Option<&String> {
+        self.imei.as_ref().or(self.identifier.as_ref()).or(self.msisdn.as_ref())
+    }
+}
+
+// Turns report entries into records, keeping track of the device they came from
+pub struct ReportRecords {
+    format: ReportFormat,
+    device: DeviceInfo,
+    models: usize,
+}
+
+impl ReportRecords {
+    pub fn new(format: ReportFormat) -> Self {
+        Self { format, device: DeviceInfo::default(), models: 0 }
+    }
+
+    // Recorded as the file's metadata once the report is read
+    pub fn metadata(&self) -> ItemAttributes {
+        let mut metadata = ItemAttributes::new();
+        metadata.insert("format".to_string(), self.format.as_str().to_string());
+        metadata.insert("models".to_string(), self.models.to_string());
+        let fields = [
+            ("device", self.device.source()),
+            ("imei", self.device.imei.as_ref()),
+            ("imsi", self.device.imsi.as_ref()),
+            ("iccid", self.device.iccid.as_ref()),
+            ("msisdn", self.device.msisdn.as_ref()),
+        ];
+        for (key, value) in fields {
+            if let Some(value) = value {
+                metadata.insert(key.to_string(), value.clone());
+            }
+        }
+        metadata
+    }
+
+    fn read_all<R: BufRead>(&mut self, mut reader: ReportReader<R>, extractors: &Extractors) -> Result<Vec<SourceRecord>, AppError> {
+        let mut records = Vec::new();
+        while let Some(entry) = reader.next_entry()? {
+            records.extend(self.record(entry, extractors));
+        }
+        Ok(records)
+    }
+
+    pub fn record(&mut self, entry: ReportEntry, extractors: &Extractors) -> Option<SourceRecord> {
+        match entry {
+            ReportEntry::Metadata { section, line_number, items } => {
+                let mut text = vec![section];
+                for (name, value) in &items {
+                    let labelled = self.device.note(name, value, extractors);
+                    text.push(labelled.unwrap_or_else(|| format!("{}: {}", name, value)));
+                }
+                (text.len() > 1).then(|| self.report_record(line_number, text, None))
+            }
+            ReportEntry::Model { model, chat } => {
+                self.models += 1;
+                match model.kind.as_str() {
+                    "Contact" => self.contact(&model, extractors),
+                    "Call" => Some(self.communication(&model, None, EventType::Call, extractors)),
+                    "SMS" | "MMS" => Some(self.communication(&model, None, EventType::Sms, extractors)),
+                    "InstantMessage" => Some(self.communication(&model, chat.as_ref(), EventType::Message, extractors)),
+                    "Email" => Some(self.communication(&model, None, EventType::Email, extractors)),
+                    "DeviceInfoEntry" => {
+                        let labelled = match (model.field("Name"), model.field("Value")) {
+                            (Some(name), Some(value)) => self.device.note(name, value, extractors),
+                            _ => None,
+                        };
+                        match labelled {
+                            Some(labelled) => {
+                                let mut text = Self::heading(&model);
+                                text.push(labelled);
+                                Some(self.report_record(model.line_number, text, None))
+                            }
+                            None => self.generic(&model),
+                        }
+                    }
+                    _ => self.generic(&model),
+                }
+            }
+        }
+    }
+
+    fn report_record(&self, line_number: i64, text: Vec<String>, event: Option<CdrEvent>) -> SourceRecord {
+        SourceRecord {
+            line_number,
+            byte_offset: None,
+            // Lines of a .ufdr are those of the report inside it
+            sheet_name: (self.format == ReportFormat::Ufdr).then(|| REPORT_ENTRY.to_string()),
+            page_number: None,
+            text: text.into_iter().filter(|t| !t.trim().is_empty()).collect::<Vec<_>>().join(","),
+            event,
+            contact: None,
+        }
+    }
+
+    fn heading(model: &Model) -> Vec<String> {
+        let mut heading = vec![model.kind.clone()];
+        if model.deleted {
+            heading.push("deleted".to_string());
+        }
+        heading
+    }
+
+    fn generic(&self, model: &Model) -> Option<SourceRecord> {
+        let mut values = Vec::new();
+        model.text_values(&mut values);
+        if values.is_empty() {
+            return None;
+        }
+        let mut text = Self::heading(model);
+        text.extend(values);
+        Some(self.report_record(model.line_number, text, None))
+    }
+
+    // Numbers and addresses in the contact's entries, whichever model type each is given
+    fn contact(&self, model: &Model, extractors: &Extractors) -> Option<SourceRecord> {
+        let name = ["Name", "FirstName", "Company"].iter().find_map(|field| model.field(field)).map(str::to_string);
+        let mut values = Vec::new();
+        let mut details: Vec<String> = name.iter().cloned().collect();
+
+        for (_, entry) in &model.children {
+            let Some(value) = entry.field("Value") else { continue };
+            let domain = entry.field("Domain").unwrap_or(&entry.kind).to_ascii_lowercase();
+            let item_type = if domain.contains("phone") {
+                ItemType::Phone
+            } else if domain.contains("email") {
+                ItemType::Email
+            } else {
+                details.push(value.to_string());
+                continue;
+            };
+            let label = entry.field("Category").map(|c| c.to_lowercase());
+            details.push(match &label {
+                Some(label) => format!("{} ({})", value, label),
+                None => value.to_string(),
+            });
+            contacts::push_value(&mut values, contacts::identifier(value, item_type, extractors), item_type, label);
+        }
+
+        if details.is_empty() {
+            return None;
+        }
+
+        let mut text = Self::heading(model);
+        text.extend(details);
+        let mut record = self.report_record(model.line_number, text, None);
+        if let Some(name) = name.filter(|_| !values.is_empty()) {
+            record.contact = Some(Contact { name, values });
+        }
+        Some(record)
+    }
+
+    // Calls, SMS, chat messages and emails: who to whom, when, with what
+    fn communication(&self, model: &Model, chat: Option<&Model>, event_type: EventType, extractors: &Extractors) -> SourceRecord {
+        let parties: Vec<&Model> = model.children("Parties").collect();
+        let role = |party: &&Model, name: &str| party.field("Role").is_some_and(|r| r.eq_ignore_ascii_case(name));
+        let from: Option<&Model> = model.children("From").next().or_else(|| parties.iter().copied().find(|p| role(p, "From")));
+        let mut to: Vec<(&str, &Model)> = Vec::new();
+        for field in ["To", "Cc", "Bcc"] {
+            to.extend(model.children(field).map(|party| (field, party)));
+            to.extend(parties.iter().copied().filter(|p| role(p, field)).map(|party| (field, party)));
+        }
+
+        let sender = from.and_then(|party| party_id(party, extractors));
+        // A chat message names only its sender; it went to everyone else in the chat
+        if to.is_empty() {
+            if let Some(chat) = chat {
+                let others = chat.children("Participants").filter(|party| party_id(party, extractors) != sender);
+                to.extend(others.map(|party| ("To", party)));
+            }
+        }
+        let mut recipients: Vec<String> = Vec::new();
+        for id in to.iter().filter_map(|(_, party)| party_id(party, extractors)) {
+            if !recipients.contains(&id) {
+                recipients.push(id);
+            }
+        }
+
+        let owner = |party: &Model| party.field("IsPhoneOwner").is_some_and(|v| v.eq_ignore_ascii_case("true"));
+        let status = model.field("Direction").or(model.field("Folder")).or(model.field("Type")).map(str::to_ascii_lowercase);
+        let direction = match status.as_deref() {
+            Some("outgoing" | "sent" | "outbox") => Some(Direction::Outgoing),
+            Some("incoming" | "missed" | "rejected" | "inbox") => Some(Direction::Incoming),
+            _ if from.is_some_and(owner) => Some(Direction::Outgoing),
+            _ if to.iter().any(|(_, party)| owner(party)) => Some(Direction::Incoming),
+            _ => None,
+        };
+        let owner_id = from.filter(|p| owner(p)).or(to.iter().map(|(_, p)| *p).find(|p| owner(p))).and_then(|p| party_id(p, extractors));
+
+        let start_time = ["TimeStamp", "StartTime", "DateSent", "DateDelivered"].iter().find_map(|f| model.field(f)).and_then(timestamp);
+        let duration = model.field("Duration").and_then(duration_seconds);
+        let attachments: Vec<String> = model.children("Attachments").chain(model.children("Attachment"))
+            .filter_map(|attachment| attachment.field("Filename").or(attachment.field("Name")))
+            .map(str::to_string)
+            .collect();
+        let source = model.field("Source").or(chat.and_then(|chat| chat.field("Source")));
+
+        let mut event = CdrEvent {
+            carrier: None,
+            event_type,
+            direction,
+            originating: sender,
+            terminating: (recipients.len() == 1).then(|| recipients[0].clone()),
+            dialed: None,
+            subscriber: self.device.msisdn.clone().or(owner_id),
+            start_time: start_time.clone(),
+            duration_seconds: duration,
+            cell_site: None,
+            sector: None,
+            // The handset the report came from
+            imei: self.device.imei.clone(),
+            imsi: self.device.imsi.clone(),
+            recipients,
+            attachments: attachments.clone(),
+            roles: Vec::new(),
+        };
+        if event_type == EventType::Email {
+            let addressed = from.map(|party| ("from", party)).into_iter().chain(to.iter().map(|(field, party)| (*field, *party)));
+            for (field, party) in addressed {
+                if let Some(id) = party_id(party, extractors).filter(|id| !event.roles.iter().any(|(v, _)| v == id)) {
+                    event.roles.push((id, field.to_ascii_lowercase()));
+                }
+            }
+        }
+        event.add_address_roles();
+
+        let mut text = Self::heading(model);
+        text.extend(start_time);
+        text.extend(status);
+        text.extend(source.map(str::to_string));
+        text.extend(from.map(describe));
+        text.push(to.iter().map(|(_, party)| describe(party)).collect::<Vec<_>>().join(";"));
+        text.extend(["Subject", "Body"].iter().filter_map(|f| model.field(f)).map(str::to_string));
+        text.push(attachments.join(";"));
+        text.extend(duration.map(|seconds| seconds.to_string()));
+        self.report_record(model.line_number, text, Some(event))
+    }
+}
+
+// WhatsApp accounts are the number at s.whatsapp.net, which would otherwise read as an
+// email address
+fn account(identifier: &str) -> String {
+    match identifier.strip_suffix("@s.whatsapp.net") {
+        Some(number) if !number.is_empty() && number.chars().all(|c| c.is_ascii_digit()) => format!("+{}", number),
+        _ => identifier.to_string(),
+    }
+}
+
+// Party models name the number or account in Identifier and the contact's name in Name
+fn party_id(party: &Model, extractors: &Extractors) -> Option<String> {
+    let identifier = party.field("Identifier").map(account);
+    identifier.as_deref().or(party.field("Name")).and_then(|id| chat::party(id, extractors))
+}
+
+fn describe(party: &Model) -> String {
+    let identifier = party.field("Identifier").map(account);
+    match (party.field("Name"), identifier) {
+        (Some(name), Some(id)) if name != id => format!("{} ({})", name, id),
+        (name, id) => id.or(name.map(str::to_string)).unwrap_or_default(),
+    }
+}
+
+// Reports write times as ISO 8601, with the zone where the device recorded one; a time
+// without it is kept as local time
+fn timestamp(value: &str) -> Option<String> {
+    let value = value.trim();
+    DateTime::parse_from_rfc3339(value)
+        .map(|datetime| datetime.to_rfc3339())
+        .or_else(|_| NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S%.f").map(|datetime| cdr::local_time(&datetime)))
+        .ok()
+}
+
+// "01:02:03", "1.01:02:03" with days, or plain seconds
+fn duration_seconds(value: &str) -> Option<i64> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<f64>() {
+        return Some(seconds.round() as i64);
+    }
+
+    let (days, time) = match value.split_once('.') {
+        Some((days, time)) if time.contains(':') && !days.contains(':') => (days.parse::<i64>().ok()?, time),
+        _ => (0, value),
+    };
+    let mut seconds = 0.0;
+    for part in time.split(':') {
+        seconds = seconds * 60.0 + part.parse::<f64>().ok()?;
+    }
+    Some(days * 86_400 + seconds.round() as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    const REPORT: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<project id="1" name="Handset" xmlns="http://pa.cellebrite.com/report/2.0">
+  <metadata section="Device Info">
+    <item name="IMEI"><![CDATA[490154203237518]]></item>
+  </metadata>
+</project>
+"#;
+
+    fn zip_of(name: &str, content: &[u8]) -> Vec<u8> {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        writer.start_file(name, zip::write::SimpleFileOptions::default()).unwrap();
+        writer.write_all(content).unwrap();
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn detects_reports_and_ufdr_containers() {
+        assert_eq!(detect(REPORT.as_bytes()), Some(ReportFormat::Xml));
+        assert_eq!(detect(&zip_of("report.xml", REPORT.as_bytes())), Some(ReportFormat::Ufdr));
+        assert_eq!(detect(&zip_of("files/report.xml", REPORT.as_bytes())), None);
+        assert_eq!(detect(b"<html><body>project</body></html>"), None);
+    }
+
+    #[test]
+    fn device_identifiers_become_items_however_they_are_written() {
+        let report = r#"<project xmlns="http://pa.cellebrite.com/report/2.0">
+  <metadata section="Device Info">
+    <item name="DeviceInfoDetectedPhoneIMEI"><![CDATA[35 209900 176148 1]]></item>
+    <item name="IMSI"><![CDATA[310150123456789]]></item>
+    <item name="DeviceInfoUniqueID"><![CDATA[00008030-001A2C3E0E]]></item>
+  </metadata>
+  <decodedData>
+    <modelType type="DeviceInfoEntry">
+      <model type="DeviceInfoEntry" id="d1">
+        <field name="Name" type="String"><value type="String"><![CDATA[ICCID]]></value></field>
+        <field name="Value" type="String"><value type="String"><![CDATA[8901-2600-0000-0000-0003]]></value></field>
+      </model>
+    </modelType>
+  </decodedData>
+</project>
+"#;
+        let extractors = Extractors::new();
+        let report = read(report.as_bytes(), &extractors).unwrap();
+
+        let items: Vec<(ItemType, String)> = report.records
+            .iter()
+            .flat_map(|record| extractors.extract_all(&record.text))
+            .map(|hit| (hit.item_type, hit.value))
+            .collect();
+        assert!(items.contains(&(ItemType::Imei, "352099001761481".to_string())));
+        assert!(items.contains(&(ItemType::Imsi, "310150123456789".to_string())));
+        assert!(items.contains(&(ItemType::Iccid, "89012600000000000003".to_string())));
+
+        assert_eq!(report.metadata["device"], "352099001761481");
+        assert_eq!(report.metadata["iccid"], "89012600000000000003");
+        assert!(report.records[0].text.contains("DeviceInfoUniqueID: 00008030-001A2C3E0E"));
+    }
+
+    #[test]
+    fn only_the_head_of_a_file_is_searched() {
+        let mut late = " ".repeat(HEAD_BYTES).into_bytes();
+        late.extend_from_slice(REPORT.as_bytes());
+        assert_eq!(detect(&late), None);
+
+        let mut padded = REPORT.replace("</project>", "").into_bytes();
+        padded.extend(std::iter::repeat_n(b' ', 4 * HEAD_BYTES));
+        assert_eq!(detect(&padded), Some(ReportFormat::Xml));
+    }
+
+    #[test]
+    fn reads_contacts_calls_and_chat_messages() {
+        let report = r#"<project xmlns="http://pa.cellebrite.com/report/2.0">
+  <metadata section="Device Info"><item name="MSISDN"><![CDATA[+1 (415) 555-0100]]></item></metadata>
+  <decodedData>
+    <modelType type="Contact">
+      <model type="Contact" id="c1" deleted_state="Deleted">
+        <field name="Name"><value>Alice Smith</value></field>
+        <multiModelField name="Entries">
+          <model type="PhoneNumber"><field name="Category"><value>Mobile</value></field><field name="Value"><value>(415) 555-0111</value></field></model>
+          <model type="UserID"><field name="Value"><value>alice.s</value></field></model>
+        </multiModelField>
+      </model>
+    </modelType>
+    <modelType type="Call">
+      <model type="Call" id="k1">
+        <field name="Direction"><value>Outgoing</value></field>
+        <field name="TimeStamp"><value>2023-11-14T22:21:40.000-05:00</value></field>
+        <field name="Duration"><value>00:01:02</value></field>
+        <multiModelField name="Parties">
+          <model type="Party"><field name="Identifier"><value>(415) 555-0111</value></field><field name="Name"><value>Alice Smith</value></field><field name="Role"><value>To</value></field></model>
+        </multiModelField>
+      </model>
+    </modelType>
+    <modelType type="Chat">
+      <model type="Chat" id="ch1">
+        <field name="Source"><value>WhatsApp</value></field>
+        <multiModelField name="Participants">
+          <model type="Party"><field name="Identifier"><value>14155550100@s.whatsapp.net</value></field><field name="IsPhoneOwner"><value>True</value></field></model>
+          <model type="Party"><field name="Identifier"><value>14155550114@s.whatsapp.net</value></field></model>
+        </multiModelField>
+        <multiModelField name="Messages">
+          <model type="InstantMessage" id="m1">
+            <field name="TimeStamp"><value>2023-11-17T10:00:00</value></field>
+            <field name="Body"><value>hello</value></field>
+            <modelField name="From"><model type="Party"><field name="Identifier"><value>14155550114@s.whatsapp.net</value></field></model></modelField>
+          </model>
+        </multiModelField>
+      </model>
+    </modelType>
+  </decodedData>
+</project>
+"#;
+        let report = read(report.as_bytes(), &Extractors::new()).unwrap();
+        let contact = report.records.iter().find_map(|record| record.contact.as_ref()).unwrap();
+        assert_eq!(contact.name, "Alice Smith");
+        assert_eq!(contact.values.len(), 1);
+        assert_eq!(contact.values[0].label.as_deref(), Some("mobile"));
+        assert!(report.records.iter().any(|record| record.text.starts_with("Contact,deleted,Alice Smith")));
+
+        let events: Vec<&CdrEvent> = report.records.iter().filter_map(|record| record.event.as_ref()).collect();
+        assert_eq!(events.len(), 2);
+        let call = events[0];
+        assert_eq!(call.direction, Some(Direction::Outgoing));
+        assert_eq!(call.terminating.as_deref(), Some("+14155550111"));
+        assert_eq!(call.subscriber.as_deref(), Some("+14155550100"));
+        assert_eq!(call.start_time.as_deref(), Some("2023-11-14T22:21:40-05:00"));
+        assert_eq!(call.duration_seconds, Some(62));
+
+        // A chat message went to everyone else in the chat
+        let message = events[1];
+        assert_eq!(message.event_type, EventType::Message);
+        assert_eq!(message.originating.as_deref(), Some("+14155550114"));
+        assert_eq!(message.recipients, ["+14155550100"]);
+        assert_eq!(message.start_time.as_deref(), Some("2023-11-17T10:00:00"));
+    }
+
+    #[test]
+    fn durations_and_times_in_report_formats() {
+        assert_eq!(duration_seconds("00:01:02"), Some(62));
+        assert_eq!(duration_seconds("1.00:00:05"), Some(86405));
+        assert_eq!(duration_seconds("42.6"), Some(43));
+        assert_eq!(duration_seconds("soon"), None);
+        assert_eq!(timestamp("2023-11-14T22:21:40.000-05:00").as_deref(), Some("2023-11-14T22:21:40-05:00"));
+        assert_eq!(timestamp("2023-11-14T22:21:40.5").as_deref(), Some("2023-11-14T22:21:40.500"));
+        assert_eq!(account("14155550114@s.whatsapp.net"), "+14155550114");
+        assert_eq!(account("group@g.us"), "group@g.us");
+    }
+}
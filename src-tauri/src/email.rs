@@ -0,0 +1,901 @@
+// Email containers: mbox files, single RFC 5322 messages (.eml) and Outlook .msg files.
+// Each message is read as a record of its headers, which carries an email event, and
+// records of its decoded text parts; named attachments are handed back to be processed
+// as files of their own.
+use std::borrow::Cow;
+use std::io::{BufRead, Cursor, Read};
+use std::sync::LazyLock;
+use base64::alphabet;
+use base64::engine::general_purpose::{GeneralPurpose, GeneralPurposeConfig};
+use base64::engine::DecodePaddingMode;
+use base64::Engine;
+use chrono::{DateTime, TimeZone};
+use encoding_rs::Encoding;
+use regex::{Captures, Regex};
+use crate::archive::ArchiveEntry;
+use crate::cdr;
+use crate::encoding::TextEncoding;
+use crate::error::AppError;
+use crate::extractors::Extractors;
+use crate::html;
+use crate::models::{CdrEvent, EventType, ItemType, SourceRecord};
+
+// Header lines looked at to recognize a message without an .eml name; long relay chains
+// put many folded Received lines before From
+const SNIFF_HEADER_LINES: usize = 200;
+// Multiparts nested deeper than this are not opened
+const MAX_PART_DEPTH: usize = 16;
+
+// Transfer encodings are often unpadded or wrapped at odd lengths
+const BASE64: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::STANDARD,
+    GeneralPurposeConfig::new()
+        .with_decode_padding_mode(DecodePaddingMode::Indifferent)
+        .with_decode_allow_trailing_bits(true),
+);
+
+// RFC 2047 encoded words, and the whitespace between two of them on one line
+static ENCODED_WORD: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"=\?([^?\s]+)\?([BbQq])\?([^?\s]*)\?=").unwrap());
+static ENCODED_WORD_GAP: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\?=[ \t]+=\?").unwrap());
+
+// Zone comments mailers append to dates, e.g. "-0800 (PST)"
+static DATE_COMMENT: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\([^)]*\)").unwrap());
+
+// Header fields whose addresses are recorded with a role, in the order roles are given
+const ADDRESS_FIELDS: &[(&str, &str)] = &[
+    ("from", "from"),
+    ("sender", "from"),
+    ("to", "to"),
+    ("cc", "cc"),
+    ("bcc", "bcc"),
+    ("reply-to", "reply-to"),
+];
+
+// MAPI property ids of an Outlook message
+const PR_SUBJECT: u16 = 0x0037;
+const PR_CLIENT_SUBMIT_TIME: u16 = 0x0039;
+const PR_TRANSPORT_MESSAGE_HEADERS: u16 = 0x007D;
+const PR_RECIPIENT_TYPE: u16 = 0x0C15;
+const PR_SENDER_NAME: u16 = 0x0C1A;
+const PR_SENDER_EMAIL_ADDRESS: u16 = 0x0C1F;
+const PR_BODY: u16 = 0x1000;
+const PR_HTML: u16 = 0x1013;
+const PR_DISPLAY_NAME: u16 = 0x3001;
+const PR_EMAIL_ADDRESS: u16 = 0x3003;
+const PR_ATTACH_DATA: u16 = 0x3701;
+const PR_ATTACH_FILENAME: u16 = 0x3704;
+const PR_ATTACH_LONG_FILENAME: u16 = 0x3707;
+const PR_SMTP_ADDRESS: u16 = 0x39FE;
+const PR_SENDER_SMTP_ADDRESS: u16 = 0x5D01;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MailFormat {
+    Mbox,
+    Eml,
+    Msg,
+}
+
+impl MailFormat {
+    // Recorded as the file's "format" metadata
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MailFormat::Mbox => "mbox",
+            MailFormat::Eml => "eml",
+            MailFormat::Msg => "msg",
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct MailContents {
+    pub messages: usize,
+    pub records: Vec<SourceRecord>,
+    pub attachments: Vec<ArchiveEntry>,
+}
+
+impl MailContents {
+    fn extend(&mut self, other: MailContents) {
+        self.messages += other.messages;
+        self.records.extend(other.records);
+        self.attachments.extend(other.attachments);
+    }
+}
+
+// Container format from the file's leading bytes; an .eml name is trusted without them
+pub fn detect(extension: &str, content: &[u8]) -> Option<MailFormat> {
+    if let Ok(file) = cfb::CompoundFile::open(Cursor::new(content)) {
+        return file.is_stream("/__properties_version1.0").then_some(MailFormat::Msg);
+    }
+
+    let first_line = content.split(|b| *b == b'\n').next().unwrap_or_default();
+    if first_line.starts_with(b"From ") {
+        let rest = &content[(first_line.len() + 1).min(content.len())..];
+        return looks_like_headers(rest).then_some(MailFormat::Mbox);
+    }
+
+    (extension == "eml" || looks_like_headers(content)).then_some(MailFormat::Eml)
+}
+
+// Every message of the container, attachments of all of them together
+pub fn read(format: MailFormat, content: &[u8], extractors: &Extractors) -> Result<MailContents, AppError> {
+    let mut contents = MailContents::default();
+    match format {
+        MailFormat::Eml => contents.extend(read_message(content, 1, 0, extractors)),
+        MailFormat::Mbox => {
+            let mut messages = MboxMessages::new(content);
+            while let Some(message) = messages.next_message()? {
+                contents.extend(read_message(&message.content, message.line_number, message.byte_offset, extractors));
+            }
+        }
+        MailFormat::Msg => contents.extend(read_msg(content, extractors)?),
+    }
+    Ok(contents)
+}
+
+// One message of an mbox, from its "From " separator line on
+pub struct MboxMessage {
+    pub line_number: i64,
+    pub byte_offset: i64,
+    pub content: Vec<u8>,
+}
+
+// Splits an mbox into messages as it is read, so only one message is held at a time. A
+// "From " line starts a message at the top of the file or after a blank line.
+pub struct MboxMessages<R> {
+    reader: R,
+    // Separator already read that starts the next message
+    next: Option<MboxMessage>,
+    previous_blank: bool,
+    line_number: i64,
+    bytes_read: u64,
+}
+
+impl<R: BufRead> MboxMessages<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            next: None,
+            previous_blank: true,
+            line_number: 1,
+            bytes_read: 0,
+        }
+    }
+
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes_read
+    }
+
+    pub fn next_message(&mut self) -> Result<Option<MboxMessage>, AppError> {
+        let mut message = self.next.take();
+
+        loop {
+            let mut line = Vec::new();
+            let read = self.reader.read_until(b'\n', &mut line)?;
+            if read == 0 {
+                return Ok(message.filter(|m| !m.content.iter().all(u8::is_ascii_whitespace)));
+            }
+
+            let line_number = self.line_number;
+            let byte_offset = self.bytes_read as i64;
+            self.line_number += 1;
+            self.bytes_read += read as u64;
+
+            let separator = self.previous_blank && line.starts_with(b"From ");
+            self.previous_blank = trim_line(&line).is_empty();
+
+            match &mut message {
+                Some(_) if separator => {
+                    self.next = Some(MboxMessage { line_number, byte_offset, content: line });
+                    return Ok(message);
+                }
+                Some(message) => message.content.extend_from_slice(&line),
+                // Anything before the first separator is read like a message of its own
+                None => message = Some(MboxMessage { line_number, byte_offset, content: line }),
+            }
+        }
+    }
+}
+
+fn trim_line(line: &[u8]) -> &[u8] {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    line.strip_suffix(b"\r").unwrap_or(line)
+}
+
+// (offset, line without its break) of each line of `bytes`
+fn byte_lines(bytes: &[u8]) -> impl Iterator<Item = (usize, &[u8])> {
+    bytes.split(|b| *b == b'\n').scan(0, |offset, line| {
+        let start = *offset;
+        *offset += line.len() + 1;
+        Some((start, trim_line(line)))
+    })
+}
+
+fn is_header_name(name: &[u8]) -> bool {
+    !name.is_empty() && name.iter().all(|b| b.is_ascii_graphic() && *b != b':')
+}
+
+// A header block with From and a field every message has, each line a field or its continuation
+fn looks_like_headers(content: &[u8]) -> bool {
+    let mut names = Vec::new();
+
+    for (i, (_, line)) in byte_lines(content).take(SNIFF_HEADER_LINES).enumerate() {
+        if line.is_empty() {
+            break;
+        }
+        if line.starts_with(b" ") || line.starts_with(b"\t") {
+            if i == 0 {
+                return false;
+            }
+            continue;
+        }
+        match line.iter().position(|b| *b == b':').map(|colon| &line[..colon]) {
+            Some(name) if is_header_name(name) => names.push(String::from_utf8_lossy(name).to_ascii_lowercase()),
+            _ => return false,
+        }
+    }
+
+    names.iter().any(|n| n == "from")
+        && names.iter().any(|n| matches!(n.as_str(), "date" | "received" | "message-id" | "subject"))
+}
+
+// Header block and body, split at the first empty line; the body's line within `bytes`
+// (counted from 0) and offset come with it
+fn split_headers(bytes: &[u8]) -> (&[u8], &[u8], i64, usize) {
+    for (i, (offset, line)) in byte_lines(bytes).enumerate() {
+        if line.is_empty() {
+            let body_start = (offset + bytes[offset..].iter().position(|b| *b == b'\n').map(|n| n + 1).unwrap_or(0)).min(bytes.len());
+            return (&bytes[..offset], &bytes[body_start..], i as i64 + 1, body_start);
+        }
+    }
+    (bytes, &[], 0, bytes.len())
+}
+
+// Unfolded (lowercase name, value) pairs; lines that are neither, such as an mbox
+// separator, are skipped
+fn parse_headers(text: &str) -> Vec<(String, String)> {
+    let mut headers: Vec<(String, String)> = Vec::new();
+
+    for line in text.lines() {
+        if line.starts_with([' ', '\t']) {
+            if let Some((_, value)) = headers.last_mut() {
+                value.push(' ');
+                value.push_str(line.trim());
+            }
+            continue;
+        }
+        if let Some((name, value)) = line.split_once(':').filter(|(name, _)| is_header_name(name.as_bytes())) {
+            headers.push((name.to_ascii_lowercase(), value.trim().to_string()));
+        }
+    }
+
+    headers
+}
+
+fn header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers.iter().find(|(n, _)| n == name).map(|(_, value)| value.as_str())
+}
+
+// RFC 2047 encoded words, e.g. "=?UTF-8?B?...?=" in a subject or attachment name
+fn decode_words(text: &str) -> Cow<'_, str> {
+    if !text.contains("=?") {
+        return Cow::Borrowed(text);
+    }
+
+    // Whitespace between adjacent encoded words on one line is not part of the text
+    let joined = ENCODED_WORD_GAP.replace_all(text, "?==?");
+    let decoded = ENCODED_WORD
+        .replace_all(&joined, |caps: &Captures| {
+            let bytes = match &caps[2] {
+                "B" | "b" => BASE64.decode(caps[3].trim_end_matches('=')).ok(),
+                _ => Some(decode_quoted_printable(caps[3].replace('_', " ").as_bytes())),
+            };
+            // A language may follow the charset, as in "utf-8*en"
+            let charset = caps[1].split('*').next().unwrap_or_default();
+            match (bytes, Encoding::for_label(charset.as_bytes())) {
+                (Some(bytes), Some(encoding)) => encoding.decode_without_bom_handling(&bytes).0.into_owned(),
+                _ => caps[0].to_string(),
+            }
+        })
+        .into_owned();
+
+    if decoded == text {
+        Cow::Borrowed(text)
+    } else {
+        Cow::Owned(decoded)
+    }
+}
+
+pub fn decode_quoted_printable(bytes: &[u8]) -> Vec<u8> {
+    let hex = |b: u8| (b as char).to_digit(16).map(|d| d as u8);
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] != b'=' {
+            out.push(bytes[i]);
+            i += 1;
+            continue;
+        }
+
+        let rest = &bytes[i + 1..];
+        // "=" at the end of a line is a soft break, trailing spaces before it included
+        let spaces = rest.iter().take_while(|b| matches!(b, b' ' | b'\t')).count();
+        if rest[spaces..].starts_with(b"\r\n") {
+            i += 1 + spaces + 2;
+        } else if rest[spaces..].starts_with(b"\n") {
+            i += 1 + spaces + 1;
+        } else if let (Some(high), Some(low)) = (rest.first().copied().and_then(hex), rest.get(1).copied().and_then(hex)) {
+            out.push(high << 4 | low);
+            i += 3;
+        } else {
+            out.push(b'=');
+            i += 1;
+        }
+    }
+
+    out
+}
+
+fn decode_transfer<'a>(body: &'a [u8], transfer_encoding: Option<&str>) -> Cow<'a, [u8]> {
+    match transfer_encoding.map(|e| e.trim().to_ascii_lowercase()).as_deref() {
+        Some("base64") => {
+            let mut clean: Vec<u8> = body.iter().copied().filter(|b| b.is_ascii_alphanumeric() || matches!(b, b'+' | b'/')).collect();
+            // A stray last character cannot complete a byte
+            if clean.len() % 4 == 1 {
+                clean.pop();
+            }
+            Cow::Owned(BASE64.decode(&clean).unwrap_or_default())
+        }
+        Some("quoted-printable") => Cow::Owned(decode_quoted_printable(body)),
+        _ => Cow::Borrowed(body),
+    }
+}
+
+// A part's declared charset, or a guess like any text file's when it has none
+fn decode_text(bytes: &[u8], charset: Option<&str>) -> String {
+    match charset.and_then(|charset| Encoding::for_label(charset.trim().as_bytes())) {
+        Some(encoding) => encoding.decode_with_bom_removal(bytes).0.into_owned(),
+        None => TextEncoding::detect(bytes).decode(bytes).into_owned(),
+    }
+}
+
+// "text/plain; charset=utf-8; name=\"a.txt\"" as the lowercase value and its parameters
+struct HeaderValue {
+    value: String,
+    params: Vec<(String, String)>,
+}
+
+impl HeaderValue {
+    fn parse(text: &str) -> Self {
+        let mut pieces = Vec::new();
+        let mut piece = String::new();
+        let mut quoted = false;
+        let mut chars = text.chars();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '"' => quoted = !quoted,
+                '\\' if quoted => piece.extend(chars.next()),
+                ';' if !quoted => pieces.push(std::mem::take(&mut piece)),
+                _ => piece.push(c),
+            }
+        }
+        pieces.push(piece);
+
+        let value = pieces[0].trim().to_ascii_lowercase();
+        let params = pieces[1..]
+            .iter()
+            .filter_map(|p| p.split_once('='))
+            .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_string()))
+            .collect();
+
+        Self { value, params }
+    }
+
+    // Plain, encoded-word or RFC 2231 ("name*=utf-8''a%20b", "name*0=...") parameters
+    fn param(&self, name: &str) -> Option<String> {
+        if let Some((_, value)) = self.params.iter().find(|(n, _)| n == name) {
+            return Some(decode_words(value).into_owned());
+        }
+
+        let extended = format!("{}*", name);
+        let mut pieces: Vec<(usize, bool, &str)> = self.params.iter().filter_map(|(n, value)| {
+            let rest = n.strip_prefix(&extended)?;
+            if rest.is_empty() {
+                return Some((0, true, value.as_str()));
+            }
+            let (index, encoded) = match rest.strip_suffix('*') {
+                Some(index) => (index, true),
+                None => (rest, false),
+            };
+            Some((index.parse().ok()?, encoded, value.as_str()))
+        }).collect();
+        if pieces.is_empty() {
+            return None;
+        }
+        pieces.sort_by_key(|(index, _, _)| *index);
+
+        // The first encoded piece names the charset: charset'language'text
+        let mut charset = None;
+        let mut bytes = Vec::new();
+        for (_, encoded, value) in pieces {
+            if !encoded {
+                bytes.extend_from_slice(value.as_bytes());
+                continue;
+            }
+            let parts: Vec<&str> = value.splitn(3, '\'').collect();
+            let text = match parts[..] {
+                [set, _, text] if charset.is_none() => {
+                    charset = Some(set.to_string());
+                    text
+                }
+                _ => value,
+            };
+            bytes.extend(percent_decode(text));
+        }
+
+        Some(decode_text(&bytes, charset.as_deref().filter(|c| !c.is_empty())))
+    }
+}
+
+fn percent_decode(text: &str) -> Vec<u8> {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match (bytes[i], bytes.get(i + 1..i + 3).and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok())) {
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                out.push(byte);
+                i += 1;
+            }
+        }
+    }
+    out
+}
+
+// Where the part being read sits in the file
+#[derive(Clone, Copy)]
+struct Position {
+    line_number: i64,
+    // None once the text no longer matches the file byte for byte
+    byte_offset: Option<i64>,
+}
+
+// A message's headers as one record carrying its event, then its text parts a line at a time
+pub fn read_message(raw: &[u8], line_number: i64, byte_offset: i64, extractors: &Extractors) -> MailContents {
+    let (header_bytes, body, body_line, body_offset) = split_headers(raw);
+    let header_text = TextEncoding::detect(header_bytes).decode(header_bytes);
+    let headers = parse_headers(&header_text);
+
+    let mut contents = MailContents { messages: 1, ..Default::default() };
+    let mut attachment_names = Vec::new();
+    let body_position = Position {
+        line_number: line_number + body_line,
+        byte_offset: Some(byte_offset + body_offset as i64),
+    };
+    read_part(&headers, body, body_position, 0, &mut contents, &mut attachment_names);
+
+    let decoded = decode_words(&header_text);
+    let exact = matches!(decoded, Cow::Borrowed(_)) && std::str::from_utf8(header_bytes).is_ok();
+    let header_record = SourceRecord {
+        line_number,
+        byte_offset: exact.then_some(byte_offset),
+        sheet_name: None,
+        page_number: None,
+        text: decoded.trim_end().to_string(),
+        event: email_event(&headers, attachment_names, extractors),
+        contact: None,
+    };
+    contents.records.insert(0, header_record);
+
+    contents
+}
+
+// Multiparts are opened part by part; text parts become records and anything else with a
+// name (or a forwarded message) becomes an attachment
+fn read_part(
+    headers: &[(String, String)],
+    body: &[u8],
+    position: Position,
+    depth: usize,
+    contents: &mut MailContents,
+    attachment_names: &mut Vec<String>,
+) {
+    let content_type = HeaderValue::parse(header(headers, "content-type").unwrap_or("text/plain"));
+    let disposition = header(headers, "content-disposition").map(HeaderValue::parse);
+    let transfer_encoding = header(headers, "content-transfer-encoding");
+
+    if content_type.value.starts_with("multipart/") {
+        if let Some(boundary) = content_type.param("boundary").filter(|_| depth < MAX_PART_DEPTH) {
+            for (part, part_line, part_offset) in split_multipart(body, &boundary) {
+                let (part_headers, part_body, body_line, body_offset) = split_headers(part);
+                let part_headers = parse_headers(&TextEncoding::detect(part_headers).decode(part_headers));
+                let part_position = Position {
+                    line_number: position.line_number + part_line + body_line,
+                    byte_offset: position.byte_offset.map(|offset| offset + (part_offset + body_offset) as i64),
+                };
+                read_part(&part_headers, part_body, part_position, depth + 1, contents, attachment_names);
+            }
+            return;
+        }
+    }
+
+    let name = disposition
+        .as_ref()
+        .and_then(|d| d.param("filename"))
+        .or_else(|| content_type.param("name"))
+        .map(|name| name.rsplit(['/', '\\']).next().unwrap_or_default().trim().to_string())
+        .filter(|name| !name.is_empty())
+        .or_else(|| (content_type.value == "message/rfc822").then(|| "message.eml".to_string()));
+    let is_attachment = disposition.as_ref().is_some_and(|d| d.value == "attachment");
+    let is_text = content_type.value == "text/plain" || content_type.value == "text/html";
+
+    if let Some(name) = name.filter(|_| is_attachment || !is_text) {
+        attachment_names.push(name.clone());
+        contents.attachments.push(ArchiveEntry {
+            path: name,
+            content: decode_transfer(body, transfer_encoding).into_owned(),
+        });
+        return;
+    }
+    if !is_text && !content_type.value.starts_with("text/") {
+        return;
+    }
+
+    let bytes = decode_transfer(body, transfer_encoding);
+    let text = decode_text(&bytes, content_type.param("charset").as_deref());
+    let html = content_type.value == "text/html";
+    let text = if html { html::to_text(&text) } else { text };
+
+    // Line numbers of decoded text count from the part's first line; byte offsets are
+    // only kept where the text is the file's own
+    let exact = matches!(bytes, Cow::Borrowed(_)) && !html && std::str::from_utf8(body).is_ok();
+    let mut offset = 0;
+    for (i, line) in text.split('\n').enumerate() {
+        let line_offset = offset;
+        offset += line.len() + 1;
+        let line = line.trim_end_matches('\r');
+        if line.trim().is_empty() {
+            continue;
+        }
+        contents.records.push(SourceRecord {
+            line_number: position.line_number + i as i64,
+            byte_offset: position.byte_offset.filter(|_| exact).map(|start| start + line_offset as i64),
+            sheet_name: None,
+            page_number: None,
+            text: line.to_string(),
+            event: None,
+            contact: None,
+        });
+    }
+}
+
+// Parts between "--boundary" lines, each with its first line and offset within `body`
+fn split_multipart<'a>(body: &'a [u8], boundary: &str) -> Vec<(&'a [u8], i64, usize)> {
+    let delimiter = format!("--{}", boundary);
+    let mut parts = Vec::new();
+    // Start, first line of the part being collected
+    let mut current: Option<(usize, i64)> = None;
+
+    for (i, (offset, line)) in byte_lines(body).enumerate() {
+        let line = line.trim_ascii_end();
+        if !line.starts_with(delimiter.as_bytes()) {
+            continue;
+        }
+        let rest = &line[delimiter.len()..];
+        if !rest.is_empty() && rest != b"--" {
+            continue;
+        }
+
+        if let Some((start, first_line)) = current.take() {
+            // The line break before a delimiter belongs to the delimiter
+            let end = offset.saturating_sub(1).max(start);
+            let end = if end > start && body[end - 1] == b'\r' { end - 1 } else { end };
+            parts.push((&body[start..end], first_line, start));
+        }
+        if rest == b"--" {
+            break;
+        }
+        let next = (offset + line.len() + 1).min(body.len());
+        let next = if body.get(offset + line.len()) == Some(&b'\r') { (next + 1).min(body.len()) } else { next };
+        current = Some((next, i as i64 + 1));
+    }
+
+    parts
+}
+
+// Addresses in the order a header lists them
+fn addresses(value: &str, extractors: &Extractors) -> Vec<String> {
+    let mut hits: Vec<_> = extractors.extract_all(&decode_words(value)).into_iter().filter(|hit| hit.item_type == ItemType::Email).collect();
+    hits.sort_by_key(|hit| hit.offset);
+    hits.into_iter().map(|hit| hit.value).collect()
+}
+
+fn email_event(headers: &[(String, String)], attachments: Vec<String>, extractors: &Extractors) -> Option<CdrEvent> {
+    let mut roles: Vec<(String, String)> = Vec::new();
+    let mut add_role = |value: String, role: &str| {
+        if !roles.iter().any(|(v, _)| *v == value) {
+            roles.push((value, role.to_string()));
+        }
+    };
+
+    let mut from = Vec::new();
+    let mut recipients: Vec<String> = Vec::new();
+    for (field, role) in ADDRESS_FIELDS {
+        for (_, value) in headers.iter().filter(|(name, _)| name == field) {
+            for address in addresses(value, extractors) {
+                match *role {
+                    "from" if !from.contains(&address) => from.push(address.clone()),
+                    "to" | "cc" | "bcc" if !recipients.contains(&address) => recipients.push(address.clone()),
+                    _ => {}
+                }
+                add_role(address, role);
+            }
+        }
+    }
+
+    // Relays record the address they accepted the message from, e.g. "from host ([203.0.113.5])"
+    for (_, value) in headers.iter().filter(|(name, _)| name == "received") {
+        let mut hits: Vec<_> = extractors.extract_all(value).into_iter().filter(|hit| hit.item_type == ItemType::Ip).collect();
+        hits.sort_by_key(|hit| hit.offset);
+        for hit in hits {
+            add_role(hit.value, "received");
+        }
+    }
+
+    if from.is_empty() && recipients.is_empty() {
+        return None;
+    }
+
+    Some(CdrEvent {
+        carrier: None,
+        event_type: EventType::Email,
+        direction: None,
+        originating: from.into_iter().next(),
+        terminating: (recipients.len() == 1).then(|| recipients[0].clone()),
+        dialed: None,
+        subscriber: None,
+        start_time: header(headers, "date").and_then(parse_date),
+        duration_seconds: None,
+        cell_site: None,
+        sector: None,
+        imei: None,
+        imsi: None,
+        recipients,
+        attachments,
+        roles,
+    })
+}
+
+// RFC 5322 dates, with the zone comments mailers append, e.g. "-0800 (PST)"
+fn parse_date(value: &str) -> Option<String> {
+    let value = DATE_COMMENT.replace_all(value, "");
+    let value = value.trim();
+    DateTime::parse_from_rfc2822(value)
+        .ok()
+        .map(|datetime| datetime.to_rfc3339())
+        .or_else(|| cdr::parse_timestamp(value, None))
+}
+
+type MsgFile<'a> = cfb::CompoundFile<Cursor<&'a [u8]>>;
+
+// Property streams are named by tag: id and type, e.g. "__substg1.0_0037001F" for a
+// Unicode subject
+fn msg_stream(file: &mut MsgFile, storage: &str, id: u16, kind: u16) -> Option<Vec<u8>> {
+    let path = format!("{}/__substg1.0_{:04X}{:04X}", storage, id, kind);
+    let mut stream = file.open_stream(&path).ok()?;
+    let mut bytes = Vec::new();
+    stream.read_to_end(&mut bytes).ok()?;
+    Some(bytes)
+}
+
+// Unicode (PT_UNICODE) or 8-bit (PT_STRING8) strings
+fn msg_string(file: &mut MsgFile, storage: &str, id: u16) -> Option<String> {
+    let text = match msg_stream(file, storage, id, 0x001F) {
+        Some(bytes) => TextEncoding::Utf16Le.decode(&bytes).into_owned(),
+        None => {
+            let bytes = msg_stream(file, storage, id, 0x001E)?;
+            TextEncoding::detect(&bytes).decode(&bytes).into_owned()
+        }
+    };
+    let text = text.trim_end_matches('\0').trim().to_string();
+    (!text.is_empty()).then_some(text)
+}
+
+// Fixed-size properties sit in "__properties_version1.0" as 16-byte entries after a header
+// of 32 bytes for the message and 8 for recipients and attachments
+fn msg_fixed(file: &mut MsgFile, storage: &str, header_len: usize, id: u16) -> Option<[u8; 8]> {
+    let mut stream = file.open_stream(format!("{}/__properties_version1.0", storage)).ok()?;
+    let mut bytes = Vec::new();
+    stream.read_to_end(&mut bytes).ok()?;
+
+    bytes.get(header_len..)?.chunks_exact(16).find_map(|entry| {
+        let tag = u32::from_le_bytes(entry[..4].try_into().ok()?);
+        ((tag >> 16) as u16 == id).then(|| entry[8..16].try_into().ok()).flatten()
+    })
+}
+
+fn msg_storages(file: &MsgFile, prefix: &str) -> Vec<String> {
+    let mut names: Vec<String> = file
+        .read_root_storage()
+        .filter(|entry| entry.is_storage() && entry.name().starts_with(prefix))
+        .map(|entry| format!("/{}", entry.name()))
+        .collect();
+    names.sort();
+    names
+}
+
+// An Outlook message is read as if it were the message it was sent as: the internet
+// headers it kept when received, or headers made up from its properties when it never
+// went over SMTP, followed by its body. Line numbers refer to that text.
+fn read_msg(content: &[u8], extractors: &Extractors) -> Result<MailContents, AppError> {
+    let mut file = cfb::CompoundFile::open(Cursor::new(content))
+        .map_err(|e| AppError::Parse(format!("Outlook message: {}", e)))?;
+
+    let mut headers = match msg_string(&mut file, "", PR_TRANSPORT_MESSAGE_HEADERS) {
+        Some(transport) => transport.replace("\r\n", "\n").trim_end().to_string(),
+        None => {
+            let mut lines = Vec::new();
+            // Exchange senders may only have an X.500 address, kept for what it is
+            let sender = msg_string(&mut file, "", PR_SENDER_SMTP_ADDRESS).or_else(|| msg_string(&mut file, "", PR_SENDER_EMAIL_ADDRESS));
+            let sender_name = msg_string(&mut file, "", PR_SENDER_NAME);
+            if sender.is_some() || sender_name.is_some() {
+                lines.push(format!("From: {} <{}>", sender_name.unwrap_or_default(), sender.unwrap_or_default()));
+            }
+
+            let mut fields: [(&str, Vec<String>); 3] = [("To", Vec::new()), ("Cc", Vec::new()), ("Bcc", Vec::new())];
+            for storage in msg_storages(&file, "__recip_version1.0_") {
+                let address = msg_string(&mut file, &storage, PR_SMTP_ADDRESS).or_else(|| msg_string(&mut file, &storage, PR_EMAIL_ADDRESS));
+                let name = msg_string(&mut file, &storage, PR_DISPLAY_NAME);
+                let kind = msg_fixed(&mut file, &storage, 8, PR_RECIPIENT_TYPE).map(|v| u32::from_le_bytes([v[0], v[1], v[2], v[3]]));
+                let field = match kind {
+                    Some(2) => 1,
+                    Some(3) => 2,
+                    _ => 0,
+                };
+                fields[field].1.push(format!("{} <{}>", name.unwrap_or_default(), address.unwrap_or_default()));
+            }
+            for (name, recipients) in fields {
+                if !recipients.is_empty() {
+                    lines.push(format!("{}: {}", name, recipients.join(", ")));
+                }
+            }
+
+            if let Some(subject) = msg_string(&mut file, "", PR_SUBJECT) {
+                lines.push(format!("Subject: {}", subject));
+            }
+            // FILETIME: 100 ns intervals since 1601
+            let submitted = msg_fixed(&mut file, "", 32, PR_CLIENT_SUBMIT_TIME)
+                .map(i64::from_le_bytes)
+                .and_then(|ticks| chrono::Utc.timestamp_opt(ticks / 10_000_000 - 11_644_473_600, 0).single());
+            if let Some(submitted) = submitted {
+                lines.push(format!("Date: {}", submitted.to_rfc2822()));
+            }
+            lines.join("\n")
+        }
+    };
+
+    let body = match msg_string(&mut file, "", PR_BODY) {
+        Some(body) => body.replace("\r\n", "\n"),
+        None => msg_stream(&mut file, "", PR_HTML, 0x0102)
+            .map(|html| html::to_text(&TextEncoding::detect(&html).decode(&html)))
+            .unwrap_or_default(),
+    };
+
+    let mut attachments = Vec::new();
+    for storage in msg_storages(&file, "__attach_version1.0_") {
+        let name = msg_string(&mut file, &storage, PR_ATTACH_LONG_FILENAME)
+            .or_else(|| msg_string(&mut file, &storage, PR_ATTACH_FILENAME))
+            .or_else(|| msg_string(&mut file, &storage, PR_DISPLAY_NAME));
+        // Embedded messages keep their data as a storage rather than a stream
+        if let (Some(name), Some(data)) = (name, msg_stream(&mut file, &storage, PR_ATTACH_DATA, 0x0102)) {
+            attachments.push(ArchiveEntry { path: name, content: data });
+        }
+    }
+
+    // The message as text, without byte positions since the file is binary
+    headers.push_str("\n\n");
+    headers.push_str(&body);
+    let mut contents = read_message(headers.as_bytes(), 1, 0, extractors);
+    for record in &mut contents.records {
+        record.byte_offset = None;
+    }
+
+    // Attachments come from the message's own storages, whatever the made-up text says
+    let names: Vec<String> = attachments.iter().map(|a| a.path.clone()).collect();
+    if let Some(event) = contents.records.first_mut().and_then(|record| record.event.as_mut()) {
+        event.attachments = names;
+    }
+    contents.attachments = attachments;
+
+    Ok(contents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MESSAGE: &str = "From: \"Dave\" <dave@example.net>\r\n\
+        To: owner@example.com\r\n\
+        Cc: =?UTF-8?Q?Alice_M=C3=BCller?= <alice@example.com>\r\n\
+        Subject: plans\r\n\
+        Date: Sat, 18 Nov 2023 12:00:00 -0800 (PST)\r\n\
+        Received: from mail.example.net ([203.0.113.5]) by mx.example.com\r\n\
+        Content-Type: multipart/mixed; boundary=\"b1\"\r\n\
+        \r\n\
+        --b1\r\n\
+        Content-Type: text/plain; charset=utf-8\r\n\
+        Content-Transfer-Encoding: quoted-printable\r\n\
+        \r\n\
+        Call me at 202-555-0143 =\r\n\
+        tonight\r\n\
+        --b1\r\n\
+        Content-Type: image/jpeg; name=\"photo.jpg\"\r\n\
+        Content-Transfer-Encoding: base64\r\n\
+        \r\n\
+        aGVsbG8=\r\n\
+        --b1--\r\n";
+
+    #[test]
+    fn detects_mail_containers() {
+        assert_eq!(detect("txt", MESSAGE.as_bytes()), Some(MailFormat::Eml));
+        assert_eq!(detect("eml", b"anything"), Some(MailFormat::Eml));
+        let mbox = format!("From dave@example.net Sat Nov 18 12:00:00 2023\n{}", MESSAGE);
+        assert_eq!(detect("", mbox.as_bytes()), Some(MailFormat::Mbox));
+        assert_eq!(detect("txt", b"From: the desk of the sheriff\nNotes: none\n"), None);
+        assert_eq!(detect("txt", b"Conversation: Bob\n\nFrom: Bob\nSent: today\n"), None);
+    }
+
+    #[test]
+    fn reads_a_message_into_an_event_records_and_attachments() {
+        let contents = read(MailFormat::Eml, MESSAGE.as_bytes(), &Extractors::new()).unwrap();
+        assert_eq!(contents.messages, 1);
+
+        let event = contents.records[0].event.as_ref().unwrap();
+        assert_eq!(event.originating.as_deref(), Some("dave@example.net"));
+        assert_eq!(event.recipients, ["owner@example.com", "alice@example.com"]);
+        assert_eq!(event.start_time.as_deref(), Some("2023-11-18T12:00:00-08:00"));
+        assert_eq!(event.attachments, ["photo.jpg"]);
+        assert!(event.roles.contains(&("203.0.113.5".to_string(), "received".to_string())));
+        assert!(contents.records[0].text.contains("Alice Müller"));
+
+        let body = &contents.records[1];
+        assert_eq!(body.text, "Call me at 202-555-0143 tonight");
+        assert_eq!(body.line_number, 13);
+        // Quoted-printable text is not the file's own bytes
+        assert_eq!(body.byte_offset, None);
+
+        assert_eq!(contents.attachments[0].path, "photo.jpg");
+        assert_eq!(contents.attachments[0].content, b"hello");
+    }
+
+    #[test]
+    fn splits_an_mbox_at_separator_lines() {
+        let mbox = "From a@example.com Mon Jan 15 13:45:00 2024\n\
+            From: a@example.com\nDate: Mon, 15 Jan 2024 13:45:00 +0000\n\nHello\nFrom the start\n\n\
+            From b@example.com Mon Jan 15 14:00:00 2024\n\
+            From: b@example.com\nSubject: second\n\nbody\n";
+        let mut messages = MboxMessages::new(mbox.as_bytes());
+        let first = messages.next_message().unwrap().unwrap();
+        let second = messages.next_message().unwrap().unwrap();
+        assert!(messages.next_message().unwrap().is_none());
+        // Only a "From " line after a blank line starts a message
+        assert_eq!((first.line_number, second.line_number), (1, 8));
+        assert!(String::from_utf8_lossy(&first.content).contains("From the start"));
+        assert_eq!(messages.bytes_read(), mbox.len() as u64);
+
+        let contents = read(MailFormat::Mbox, mbox.as_bytes(), &Extractors::new()).unwrap();
+        assert_eq!(contents.messages, 2);
+    }
+
+    #[test]
+    fn dates_fall_back_to_local_time() {
+        assert_eq!(parse_date("Mon, 15 Jan 2024 13:45:00 +0100").as_deref(), Some("2024-01-15T13:45:00+01:00"));
+        assert_eq!(parse_date("2024-01-15 13:45:00").as_deref(), Some("2024-01-15T13:45:00"));
+        assert_eq!(parse_date("sometime"), None);
+    }
+}
@@ -0,0 +1,212 @@
+// Character-encoding detection and decoding for text returns
+use std::borrow::Cow;
+use std::str::FromStr;
+use encoding_rs::{CoderResult, Decoder, Encoding, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252};
+use crate::error::AppError;
+
+// Bytes looked at when guessing an encoding without a BOM
+const SNIFF_BYTES: usize = 8192;
+
+// Windows-1252 leaves these unassigned; seeing one means the file is not 1252
+const CP1252_UNDEFINED: &[u8] = &[0x81, 0x8D, 0x8F, 0x90, 0x9D];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextEncoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Windows1252,
+    Latin1,
+}
+
+impl TextEncoding {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TextEncoding::Utf8 => "UTF-8",
+            TextEncoding::Utf16Le => "UTF-16LE",
+            TextEncoding::Utf16Be => "UTF-16BE",
+            TextEncoding::Windows1252 => "windows-1252",
+            TextEncoding::Latin1 => "ISO-8859-1",
+        }
+    }
+
+    // A BOM decides; otherwise NULs on one side of every byte pair mean UTF-16 of mostly
+    // ASCII text, valid UTF-8 means UTF-8, and single-byte text is Windows-1252 when it
+    // uses the 0x80-0x9F range 1252 assigns (smart quotes, dashes) and Latin-1 otherwise
+    pub fn detect(content: &[u8]) -> Self {
+        match Encoding::for_bom(content) {
+            Some((encoding, _)) if encoding == UTF_16LE => return TextEncoding::Utf16Le,
+            Some((encoding, _)) if encoding == UTF_16BE => return TextEncoding::Utf16Be,
+            Some(_) => return TextEncoding::Utf8,
+            None => {}
+        }
+
+        let sample = &content[..content.len().min(SNIFF_BYTES)];
+        let pairs = sample.len() / 2;
+        if pairs > 0 {
+            let even_nuls = sample.iter().step_by(2).filter(|b| **b == 0).count();
+            let odd_nuls = sample.iter().skip(1).step_by(2).filter(|b| **b == 0).count();
+            if odd_nuls * 10 >= pairs * 3 && even_nuls * 10 < pairs {
+                return TextEncoding::Utf16Le;
+            }
+            if even_nuls * 10 >= pairs * 3 && odd_nuls * 10 < pairs {
+                return TextEncoding::Utf16Be;
+            }
+        }
+
+        match std::str::from_utf8(sample) {
+            Ok(_) => return TextEncoding::Utf8,
+            // The sample may end inside a multi-byte character
+            Err(e) if e.error_len().is_none() => return TextEncoding::Utf8,
+            Err(_) => {}
+        }
+
+        let c1 = |b: &u8| (0x80..=0x9F).contains(b);
+        if content.iter().any(|b| CP1252_UNDEFINED.contains(b)) || !content.iter().any(c1) {
+            TextEncoding::Latin1
+        } else {
+            TextEncoding::Windows1252
+        }
+    }
+
+    // Length of the BOM `decode` drops from the start of `content`
+    pub fn bom_len(&self, content: &[u8]) -> usize {
+        let bom: &[u8] = match self {
+            TextEncoding::Utf8 => b"\xEF\xBB\xBF",
+            TextEncoding::Utf16Le => b"\xFF\xFE",
+            TextEncoding::Utf16Be => b"\xFE\xFF",
+            TextEncoding::Windows1252 | TextEncoding::Latin1 => return 0,
+        };
+        if content.starts_with(bom) { bom.len() } else { 0 }
+    }
+
+    // Bytes decoded `text` took in the file, so an offset into the text can be given as a
+    // file position; a U+FFFD standing in for a malformed sequence counts as well-formed
+    pub fn encoded_len(&self, text: &str) -> usize {
+        match self {
+            TextEncoding::Utf8 => text.len(),
+            TextEncoding::Utf16Le | TextEncoding::Utf16Be => text.encode_utf16().count() * 2,
+            TextEncoding::Windows1252 | TextEncoding::Latin1 => text.chars().count(),
+        }
+    }
+
+    // A leading BOM is dropped; undecodable sequences become U+FFFD
+    pub fn decode<'a>(&self, content: &'a [u8]) -> Cow<'a, str> {
+        match self {
+            TextEncoding::Utf8 => UTF_8.decode_with_bom_removal(content).0,
+            TextEncoding::Utf16Le => UTF_16LE.decode_with_bom_removal(content).0,
+            TextEncoding::Utf16Be => UTF_16BE.decode_with_bom_removal(content).0,
+            TextEncoding::Windows1252 => WINDOWS_1252.decode_without_bom_handling(content).0,
+            // encoding_rs treats the Latin-1 label as 1252, as browsers do; 0x80-0x9F must stay C1
+            TextEncoding::Latin1 => Cow::Owned(content.iter().map(|b| *b as char).collect()),
+        }
+    }
+
+    pub fn stream_decoder(&self) -> StreamDecoder {
+        let decoder = match self {
+            TextEncoding::Utf8 => Some(UTF_8.new_decoder_with_bom_removal()),
+            TextEncoding::Utf16Le => Some(UTF_16LE.new_decoder_with_bom_removal()),
+            TextEncoding::Utf16Be => Some(UTF_16BE.new_decoder_with_bom_removal()),
+            TextEncoding::Windows1252 => Some(WINDOWS_1252.new_decoder_without_bom_handling()),
+            TextEncoding::Latin1 => None,
+        };
+        StreamDecoder { decoder }
+    }
+}
+
+// Decodes a file read in chunks, as `decode` would decode it whole; a character split
+// between two chunks is completed by the next one
+pub struct StreamDecoder {
+    // None for Latin-1, which is decoded byte by byte
+    decoder: Option<Decoder>,
+}
+
+impl StreamDecoder {
+    // Appends the decoded chunk to `out`; `last` flushes a trailing incomplete character
+    pub fn decode(&mut self, chunk: &[u8], last: bool, out: &mut String) {
+        let Some(decoder) = &mut self.decoder else {
+            out.extend(chunk.iter().map(|b| *b as char));
+            return;
+        };
+
+        if let Some(needed) = decoder.max_utf8_buffer_length(chunk.len()) {
+            out.reserve(needed);
+        }
+        let mut rest = chunk;
+        loop {
+            let (result, read, _) = decoder.decode_to_string(rest, out, last);
+            rest = &rest[read..];
+            if result == CoderResult::InputEmpty {
+                break;
+            }
+            out.reserve(rest.len() * 3 + 16);
+        }
+    }
+}
+
+impl FromStr for TextEncoding {
+    type Err = AppError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().replace('_', "-").as_str() {
+            "utf-8" | "utf8" => Ok(TextEncoding::Utf8),
+            "utf-16le" | "utf-16" | "utf16le" | "utf16" | "unicode" => Ok(TextEncoding::Utf16Le),
+            "utf-16be" | "utf16be" => Ok(TextEncoding::Utf16Be),
+            "windows-1252" | "cp1252" | "win1252" | "ansi" => Ok(TextEncoding::Windows1252),
+            "iso-8859-1" | "latin-1" | "latin1" | "iso8859-1" => Ok(TextEncoding::Latin1),
+            _ => Err(AppError::Parse(format!("Unknown text encoding: {}", s))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utf16le(text: &str) -> Vec<u8> {
+        text.encode_utf16().flat_map(u16::to_le_bytes).collect()
+    }
+
+    #[test]
+    fn detects_encodings_with_and_without_a_bom() {
+        let mut with_bom = vec![0xFF, 0xFE];
+        with_bom.extend(utf16le("Calling Number"));
+        assert_eq!(TextEncoding::detect(&with_bom), TextEncoding::Utf16Le);
+        assert_eq!(TextEncoding::detect(&utf16le("Calling Number")), TextEncoding::Utf16Le);
+        let be: Vec<u8> = "Calling".encode_utf16().flat_map(u16::to_be_bytes).collect();
+        assert_eq!(TextEncoding::detect(&be), TextEncoding::Utf16Be);
+        assert_eq!(TextEncoding::detect("Café 202-555-0143".as_bytes()), TextEncoding::Utf8);
+        assert_eq!(TextEncoding::detect(b"\x93quoted\x94 caf\xe9"), TextEncoding::Windows1252);
+        assert_eq!(TextEncoding::detect(b"caf\xe9 \x81"), TextEncoding::Latin1);
+        assert_eq!(TextEncoding::detect(b"caf\xe9 au lait"), TextEncoding::Latin1);
+        // A head cut off inside a character is still UTF-8
+        assert_eq!(TextEncoding::detect(b"caf\xc3"), TextEncoding::Utf8);
+    }
+
+    #[test]
+    fn decodes_whole_or_in_chunks_alike() {
+        let mut content = vec![0xEF, 0xBB, 0xBF];
+        content.extend("Zoë – 202-555-0143".as_bytes());
+        assert_eq!(TextEncoding::Utf8.decode(&content), "Zoë – 202-555-0143");
+
+        // Every split point, including inside a multi-byte character
+        for split in 0..content.len() {
+            let mut decoder = TextEncoding::Utf8.stream_decoder();
+            let mut out = String::new();
+            decoder.decode(&content[..split], false, &mut out);
+            decoder.decode(&content[split..], true, &mut out);
+            assert_eq!(out, "Zoë – 202-555-0143");
+        }
+
+        assert_eq!(TextEncoding::Windows1252.decode(b"\x93hi\x94"), "\u{201C}hi\u{201D}");
+        assert_eq!(TextEncoding::Latin1.decode(b"\x93hi\xe9"), "\u{93}hi\u{e9}");
+    }
+
+    #[test]
+    fn parses_encoding_names() {
+        assert_eq!("UTF_16".parse::<TextEncoding>().unwrap(), TextEncoding::Utf16Le);
+        assert_eq!(" cp1252 ".parse::<TextEncoding>().unwrap(), TextEncoding::Windows1252);
+        assert_eq!("Latin-1".parse::<TextEncoding>().unwrap(), TextEncoding::Latin1);
+        assert!("ebcdic".parse::<TextEncoding>().is_err());
+    }
+}
@@ -11,9 +11,18 @@ pub enum AppError {
     #[error("CSV error: {0}")]
     Csv(#[from] csv::Error),
     
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+    
     #[error("Excel error: {0}")]
     Excel(String),
     
+    #[error("PDF error: {0}")]
+    Pdf(String),
+    
+    #[error("Archive error: {0}")]
+    Archive(String),
+    
     #[error("Parse error: {0}")]
     Parse(String),
     
@@ -23,6 +32,30 @@ pub enum AppError {
     #[error("File not found")]
     FileNotFound,
     
+    #[error("Source not found: {0}")]
+    SourceNotFound(i32),
+    
+    #[error("Backup error: {0}")]
+    Backup(String),
+    
+    #[error("Not a CAST case database")]
+    UnrecognizedDatabase,
+    
+    #[error("Case database is schema version {found}, newer than the {supported} this version supports; update the application to open it")]
+    SchemaTooNew { found: i64, supported: i64 },
+    
+    #[error("Case database is schema version {found}, older than the {current} this version writes; run `cast-cli upgrade` on it first")]
+    SchemaOutdated { found: i64, current: i64 },
+    
+    #[error("Invalid CAST_JSON case file: {field}: {message}")]
+    InvalidCaseFile { field: String, message: String },
+    
+    #[error("Device database error: {0}")]
+    DeviceDatabase(String),
+    
+    #[error("Ingestion cancelled")]
+    Cancelled,
+    
     #[error("General error: {0}")]
     General(String),
 }
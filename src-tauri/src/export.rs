@@ -0,0 +1,107 @@
+use crate::error::AppError;
+use crate::models::{CrossReferenceItem, ExtractedItem, Source, TowerIntersectionItem};
+
+// Items carry their source's id; the sheet names the source as the case does
+pub fn write_items_csv(data_type: &str, path: &str, data: &[ExtractedItem], sources: &[Source]) -> Result<(), AppError> {
+    let mut wtr = csv::Writer::from_path(path)?;
+    
+    match data_type {
+        "phones" | "emails" | "ips" | "imsis" | "imeis" | "iccids" => {
+            wtr.write_record(["Value", "Source", "Count", "Aliases"])?;
+            for item in data {
+                let source = match sources.iter().find(|source| source.id == item.source) {
+                    Some(source) => source.name.clone(),
+                    None => format!("Source {}", item.source),
+                };
+                wtr.write_record([
+                    &item.value,
+                    &source,
+                    &item.count.to_string(),
+                    &item.aliases.join("; "),
+                ])?;
+            }
+        }
+        _ => {}
+    }
+    
+    wtr.flush()?;
+    Ok(())
+}
+
+pub fn write_cross_reference_csv(path: &str, data: &[CrossReferenceItem]) -> Result<(), AppError> {
+    let mut wtr = csv::Writer::from_path(path)?;
+    
+    wtr.write_record(["Value", "Type", "Sources", "Source Count", "Total Count", "Aliases"])?;
+    for item in data {
+        let names = item.sources.iter()
+            .map(|source| source.name.as_str())
+            .collect::<Vec<_>>()
+            .join("; ");
+        wtr.write_record([
+            &item.value,
+            item.item_type.as_str(),
+            &names,
+            &item.sources.len().to_string(),
+            &item.count.to_string(),
+            &item.aliases.join("; "),
+        ])?;
+    }
+    
+    wtr.flush()?;
+    Ok(())
+}
+
+// One row per record placing an identifier at a site, so the sheet can be filtered by site
+pub fn write_tower_intersection_csv(path: &str, data: &[TowerIntersectionItem]) -> Result<(), AppError> {
+    let mut wtr = csv::Writer::from_path(path)?;
+    
+    wtr.write_record(["Value", "Type", "Site Count", "Site", "Source", "File", "Sheet", "Line", "Start Time", "Role", "Row"])?;
+    for item in data {
+        for site in &item.sites {
+            for record in &site.records {
+                wtr.write_record([
+                    &item.value,
+                    item.item_type.as_str(),
+                    &item.site_count.to_string(),
+                    &site.label,
+                    &site.source.name,
+                    &record.file_name,
+                    record.sheet_name.as_deref().unwrap_or(""),
+                    &record.line_number.to_string(),
+                    record.start_time.as_deref().unwrap_or(""),
+                    record.role.as_deref().unwrap_or(""),
+                    &record.row_data,
+                ])?;
+            }
+        }
+    }
+    
+    wtr.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::ItemType;
+
+    #[test]
+    fn item_sheets_name_their_sources() {
+        let item = |value: &str, source| ExtractedItem {
+            value: value.to_string(),
+            source,
+            count: 2,
+            item_type: ItemType::Phone,
+            attributes: Default::default(),
+            aliases: vec!["Alice".to_string()],
+        };
+        let sources = [Source { id: 1, name: "AT&T return".to_string(), provider: None, warrant_number: None, created_at: None }];
+        let path = std::env::temp_dir().join(format!("cast-items-{}.csv", std::process::id()));
+        let path = path.to_string_lossy();
+
+        write_items_csv("phones", &path, &[item("+12025550143", 1), item("+13125550178", 7)], &sources).unwrap();
+        let written = std::fs::read_to_string(&*path).unwrap();
+        std::fs::remove_file(&*path).unwrap();
+        assert_eq!(written, "Value,Source,Count,Aliases\n+12025550143,AT&T return,2,Alice\n+13125550178,Source 7,2,Alice\n");
+    }
+}
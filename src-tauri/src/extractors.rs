@@ -1,72 +1,325 @@
 use regex::Regex;
 use std::collections::HashSet;
+use crate::device_id::{self, DeviceIdentifier};
+use crate::error::AppError;
+use crate::models::{ItemAttributes, ItemType};
+use crate::phone::{self, Country, PhoneNumber};
+
+// One identifier found in scanned text
+#[derive(Debug, Clone)]
+pub struct Hit {
+    pub item_type: ItemType,
+    pub value: String,
+    pub attributes: ItemAttributes,
+    // Byte offset of the match within the scanned text
+    pub offset: usize,
+}
 
 pub struct Extractors {
+    country: &'static Country,
     phone_regex: Vec<Regex>,
+    phone_span_regex: Regex,
+    phone_bare_regex: Regex,
+    phone_label_regex: Regex,
     email_regex: Regex,
     ipv4_regex: Regex,
     ipv6_regex: Regex,
+    device_label_regex: Regex,
+    device_bare_regex: Regex,
+    iccid_regex: Regex,
+}
+
+impl Default for Extractors {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Extractors {
     pub fn new() -> Self {
+        Self::for_country(phone::country_by_region(phone::DEFAULT_REGION).expect("default region is listed"))
+    }
+    
+    // Numbers without a country code are interpreted as dialled from `region`
+    pub fn with_region(region: &str) -> Result<Self, AppError> {
+        phone::country_by_region(region)
+            .map(Self::for_country)
+            .ok_or_else(|| AppError::Parse(format!("Unknown region: {}", region)))
+    }
+    
+    fn for_country(country: &'static Country) -> Self {
         Self {
+            country,
             phone_regex: vec![
                 Regex::new(r"\b1?[-.\s]?\(?([0-9]{3})\)?[-.\s]?([0-9]{3})[-.\s]?([0-9]{4})\b").unwrap(),
                 Regex::new(r"\b1?([0-9]{3})([0-9]{3})([0-9]{4})\b").unwrap(),
                 Regex::new(r"\b([0-9]{3})[-.\s]([0-9]{3})[-.\s]([0-9]{4})\b").unwrap(),
             ],
+            phone_span_regex: Regex::new(r"(\+)?\b(\d(?:[\s().-]{0,2}\d)*)").unwrap(),
+            phone_bare_regex: Regex::new(r"\b\d{11,15}\b").unwrap(),
+            phone_label_regex: Regex::new(r"(?i)\b(?:phone|telephone|tel|mobile|cell|msisdn|mdn|fax)\b[^0-9\n]{0,12}?(\d{11,15})\b").unwrap(),
             email_regex: Regex::new(r"\b[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Z|a-z]{2,}\b").unwrap(),
             ipv4_regex: Regex::new(r"\b(?:(?:25[0-5]|2[0-4][0-9]|[01]?[0-9][0-9]?)\.){3}(?:25[0-5]|2[0-4][0-9]|[01]?[0-9][0-9]?)\b").unwrap(),
             ipv6_regex: Regex::new(r"\b(?:[A-Fa-f0-9]{1,4}:){7}[A-Fa-f0-9]{1,4}\b").unwrap(),
+            device_label_regex: Regex::new(r"(?i)\b(imeisv|imei|imsi|iccid)\b[^0-9\n]{0,12}?(\d(?:-?\d){13,21})\b").unwrap(),
+            device_bare_regex: Regex::new(r"\b\d{15}\b").unwrap(),
+            iccid_regex: Regex::new(r"\b89\d{16,20}\b").unwrap(),
         }
     }
     
-    pub fn extract_phones(&self, text: &str) -> HashSet<String> {
-        let mut phones = HashSet::new();
+    // Every identifier in `text`, with the byte offset where each one starts
+    pub fn extract_all(&self, text: &str) -> Vec<Hit> {
+        let mut seen = HashSet::new();
+        let mut hits = Vec::new();
         
-        for regex in &self.phone_regex {
-            for capture in regex.captures_iter(text) {
-                let mut phone = String::new();
-                
-                for i in 1..=capture.len() - 1 {
-                    if let Some(group) = capture.get(i) {
-                        phone.push_str(group.as_str());
+        let phones = self.phone_matches(text, false).into_iter().map(|(phone, offset)| Hit {
+            item_type: ItemType::Phone,
+            value: phone.e164,
+            attributes: ItemAttributes::from([("country".to_string(), phone.region.to_string())]),
+            offset,
+        });
+        let devices = self.device_matches(text).into_iter().map(|(id, offset)| Hit {
+            item_type: id.item_type,
+            value: id.value,
+            attributes: id.attributes,
+            offset,
+        });
+        let emails = self.email_matches(text).into_iter().map(|(email, offset)| Hit {
+            item_type: ItemType::Email,
+            value: email,
+            attributes: ItemAttributes::new(),
+            offset,
+        });
+        let ips = self.ip_matches(text).into_iter().map(|(ip, offset)| Hit {
+            item_type: ItemType::Ip,
+            value: ip,
+            attributes: ItemAttributes::new(),
+            offset,
+        });
+        
+        for hit in phones.chain(devices).chain(emails).chain(ips) {
+            if seen.insert((hit.item_type, hit.value.clone(), hit.offset)) {
+                hits.push(hit);
+            }
+        }
+        
+        hits.sort_by_key(|hit| hit.offset);
+        hits
+    }
+    
+    pub fn extract_phones(&self, text: &str) -> HashSet<PhoneNumber> {
+        self.phone_matches(text, false).into_iter().map(|(phone, _)| phone).collect()
+    }
+    
+    pub fn extract_device_ids(&self, text: &str) -> HashSet<DeviceIdentifier> {
+        self.device_matches(text).into_iter().map(|(id, _)| id).collect()
+    }
+    
+    // The first phone number in a single field, e.g. a CDR party column
+    pub fn phone_in(&self, text: &str) -> Option<PhoneNumber> {
+        self.phone_matches(text, true)
+            .into_iter()
+            .min_by_key(|(_, offset)| *offset)
+            .map(|(phone, _)| phone)
+    }
+    
+    pub fn extract_emails(&self, text: &str) -> HashSet<String> {
+        self.email_matches(text).into_iter().map(|(email, _)| email).collect()
+    }
+    
+    pub fn extract_ips(&self, text: &str) -> HashSet<String> {
+        self.ip_matches(text).into_iter().map(|(ip, _)| ip).collect()
+    }
+    
+    // `phone_field` is set when the whole text is known to hold a number, such as a party
+    // column, which lets a bare digit run be read with its calling code
+    fn phone_matches(&self, text: &str, phone_field: bool) -> Vec<(PhoneNumber, usize)> {
+        // (number, start, end) - the patterns overlap, so the same hit can be found more than once
+        let mut phones = Vec::new();
+        let mut nanp = Vec::new();
+        
+        // Dotted quads and labelled device identifiers would otherwise read as digit groups;
+        // they are blanked to the same length so offsets still line up with `text`
+        let blank = |caps: &regex::Captures| " ".repeat(caps[0].len());
+        let text = self.ipv4_regex.replace_all(text, blank);
+        let text = self.device_label_regex.replace_all(&text, blank);
+        
+        if self.country.calling_code == "1" {
+            for regex in &self.phone_regex {
+                for capture in regex.captures_iter(&text) {
+                    let mut phone = String::new();
+                    
+                    for i in 1..=capture.len() - 1 {
+                        if let Some(group) = capture.get(i) {
+                            phone.push_str(group.as_str());
+                        }
+                    }
+                    
+                    if phone.len() == 10 || (phone.len() == 11 && phone.starts_with('1')) {
+                        // Offsets point at the first digit, or at a "+" written before it
+                        let whole = capture.get(0).unwrap();
+                        let mut start = whole.start() + whole.as_str().find(|c: char| c.is_ascii_digit()).unwrap_or(0);
+                        if text[..start].ends_with('+') {
+                            start -= 1;
+                        }
+                        nanp.push((
+                            PhoneNumber {
+                                e164: format!("+1{}", self.normalize_phone(&phone)),
+                                region: self.country.region,
+                            },
+                            start,
+                            whole.end(),
+                        ));
                     }
                 }
+            }
+        }
+        
+        for capture in self.phone_span_regex.captures_iter(&text) {
+            let has_plus = capture.get(1).is_some();
+            let span = capture.get(2).unwrap();
+            let groups = digit_groups(span.as_str());
+            let digits: Vec<&str> = groups.iter().map(|(_, g)| *g).collect();
+            
+            let mut i = 0;
+            while i < digits.len() {
+                let rest = &digits[i..];
+                let parsed = if has_plus && i == 0 {
+                    phone::parse_international(rest, self.country)
+                } else {
+                    phone::parse_with_idd(rest, self.country).or_else(|| {
+                        // NANP national numbers are covered by the patterns above
+                        if self.country.calling_code == "1" {
+                            None
+                        } else {
+                            phone::parse_national(rest, self.country)
+                        }
+                    })
+                };
                 
-                if phone.len() == 10 || (phone.len() == 11 && phone.starts_with('1')) {
-                    phones.insert(self.normalize_phone(&phone));
+                match parsed {
+                    Some((number, used)) => {
+                        let start = if has_plus && i == 0 {
+                            capture.get(0).unwrap().start()
+                        } else {
+                            span.start() + groups[i].0
+                        };
+                        let (last_offset, last_group) = groups[i + used - 1];
+                        phones.push((number, start, span.start() + last_offset + last_group.len()));
+                        i += used;
+                    }
+                    None => i += 1,
                 }
             }
         }
         
-        phones
+        // In free text a run of 11-15 digits without "+" or "00" is as likely an account or
+        // reference number, so it is only read as a phone number after a phone label
+        let labelled: HashSet<usize> = self.phone_label_regex
+            .captures_iter(&text)
+            .map(|capture| capture.get(1).unwrap().start())
+            .collect();
+        
+        // 15-digit runs shaped like an IMEI or IMSI are not numbers, even where an unlabelled
+        // IMSI is not taken as one
+        for m in self.phone_bare_regex.find_iter(&text) {
+            if !phone_field && !labelled.contains(&m.start()) {
+                continue;
+            }
+            let device_shaped = m.len() == 15 && (device_id::classify(m.as_str()).is_some() || device_id::parse_imsi(m.as_str()).is_some());
+            if device_shaped {
+                continue;
+            }
+            if let Some(number) = phone::parse_bare_international(m.as_str(), self.country) {
+                phones.push((number, m.start(), m.end()));
+            }
+        }
+        
+        // A NANP pattern also matches the tail of an international number, as "207 946 0958"
+        // in "+44 207 946 0958"; it gives way to the number the whole span was read as
+        nanp.retain(|(number, start, end)| {
+            !phones.iter().any(|(other, o_start, o_end)| other != number && start < o_end && o_start < end)
+        });
+        phones.extend(nanp);
+        
+        phones.sort_by_key(|(_, start, end)| (*start, std::cmp::Reverse(*end)));
+        
+        let mut kept: Vec<(PhoneNumber, usize, usize)> = Vec::new();
+        for (number, start, end) in phones {
+            let duplicate = kept.iter().any(|(k, k_start, k_end)| *k == number && start < *k_end && *k_start < end);
+            if !duplicate {
+                kept.push((number, start, end));
+            }
+        }
+        
+        kept.into_iter().map(|(number, start, _)| (number, start)).collect()
     }
     
-    pub fn extract_emails(&self, text: &str) -> HashSet<String> {
+    fn device_matches(&self, text: &str) -> Vec<(DeviceIdentifier, usize)> {
+        let mut ids = Vec::new();
+        let mut labelled = HashSet::new();
+        
+        // An explicit IMSI/IMEI/ICCID label decides the type, so only the check digit is tested
+        for capture in self.device_label_regex.captures_iter(text) {
+            let number = capture.get(2).unwrap();
+            let digits: String = number.as_str().chars().filter(|c| c.is_ascii_digit()).collect();
+            let parsed = match (capture[1].to_lowercase().as_str(), digits.len()) {
+                ("imsi", _) => device_id::parse_imsi(&digits),
+                ("imei", 16) | ("imeisv", _) => device_id::parse_imeisv(&digits),
+                ("imei", _) => device_id::parse_imei(&digits),
+                _ => device_id::parse_iccid(&digits),
+            };
+            
+            if let Some(id) = parsed {
+                labelled.insert(digits);
+                ids.push((id, number.start()));
+            }
+        }
+        
+        for m in self.device_bare_regex.find_iter(text) {
+            if labelled.contains(m.as_str()) {
+                continue;
+            }
+            if let Some(id) = device_id::classify(m.as_str()) {
+                ids.push((id, m.start()));
+            }
+        }
+        
+        for m in self.iccid_regex.find_iter(text) {
+            if labelled.contains(m.as_str()) {
+                continue;
+            }
+            if let Some(id) = device_id::parse_iccid(m.as_str()) {
+                ids.push((id, m.start()));
+            }
+        }
+        
+        ids
+    }
+    
+    fn email_matches(&self, text: &str) -> Vec<(String, usize)> {
         self.email_regex
             .find_iter(text)
-            .map(|m| m.as_str().to_lowercase())
+            .map(|m| (m.as_str().to_lowercase(), m.start()))
             .collect()
     }
     
-    pub fn extract_ips(&self, text: &str) -> HashSet<String> {
-        let mut ips = HashSet::new();
+    fn ip_matches(&self, text: &str) -> Vec<(String, usize)> {
+        let mut ips = Vec::new();
         
         for m in self.ipv4_regex.find_iter(text) {
-            ips.insert(m.as_str().to_string());
+            ips.push((m.as_str().to_string(), m.start()));
         }
         
         for m in self.ipv6_regex.find_iter(text) {
-            ips.insert(m.as_str().to_string());
+            ips.push((m.as_str().to_string(), m.start()));
         }
         
         ips
     }
     
     fn normalize_phone(&self, phone: &str) -> String {
-        let digits: String = phone.chars().filter(|c| c.is_digit(10)).collect();
+        let digits: String = phone.chars().filter(|c| c.is_ascii_digit()).collect();
         
         if digits.len() == 11 && digits.starts_with('1') {
             digits[1..].to_string()
@@ -74,4 +327,73 @@ impl Extractors {
             digits
         }
     }
-}
\ No newline at end of file
+}
+// Runs of ASCII digits in `text`, with their byte offsets
+fn digit_groups(text: &str) -> Vec<(usize, &str)> {
+    let mut groups = Vec::new();
+    let mut start = None;
+    
+    for (i, b) in text.bytes().enumerate() {
+        match (b.is_ascii_digit(), start) {
+            (true, None) => start = Some(i),
+            (false, Some(s)) => {
+                groups.push((s, &text[s..i]));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    
+    if let Some(s) = start {
+        groups.push((s, &text[s..]));
+    }
+    
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(text: &str, item_type: ItemType) -> Vec<String> {
+        Extractors::new()
+            .extract_all(text)
+            .into_iter()
+            .filter(|hit| hit.item_type == item_type)
+            .map(|hit| hit.value)
+            .collect()
+    }
+
+    #[test]
+    fn imsis_are_only_taken_with_a_label() {
+        assert_eq!(values("IMSI: 310150123456789", ItemType::Imsi), ["310150123456789"]);
+        assert!(values("ref 310150123456789", ItemType::Imsi).is_empty());
+        assert!(values("ref 310150123456789", ItemType::Phone).is_empty());
+        assert_eq!(values("handset 490154203237518", ItemType::Imei), ["490154203237518"]);
+    }
+
+    #[test]
+    fn international_numbers_are_not_also_read_as_nanp() {
+        assert_eq!(values("Call +44 207 946 0958 today", ItemType::Phone), ["+442079460958"]);
+        assert_eq!(values("dial 011 44 20 7946 0958", ItemType::Phone), ["+442079460958"]);
+        assert_eq!(values("+1 (202) 555-0143 or 312.555.0178", ItemType::Phone), ["+12025550143", "+13125550178"]);
+    }
+
+    #[test]
+    fn phone_offsets_point_at_the_number() {
+        let text = "from +44 20 7946 0958 to 202-555-0143";
+        let extractors = Extractors::new();
+        let offsets: Vec<usize> = extractors.extract_all(text).iter().filter(|hit| hit.item_type == ItemType::Phone).map(|hit| hit.offset).collect();
+        assert_eq!(offsets, [5, 25]);
+        assert_eq!(extractors.phone_in(text).map(|phone| phone.e164).as_deref(), Some("+442079460958"));
+    }
+
+    #[test]
+    fn bare_runs_need_a_phone_label_or_field() {
+        assert!(values("Account 4912345678901", ItemType::Phone).is_empty());
+        assert!(values("Account Number: 4912345678901", ItemType::Phone).is_empty());
+        assert_eq!(values("Mobile: 4912345678901", ItemType::Phone), ["+4912345678901"]);
+        assert_eq!(values("Account 004912345678901", ItemType::Phone), ["+4912345678901"]);
+        assert_eq!(Extractors::new().phone_in("4912345678901").map(|phone| phone.e164).as_deref(), Some("+4912345678901"));
+    }
+}
@@ -1,24 +1,112 @@
-use std::io::{BufRead, BufReader, Cursor};
-use csv::ReaderBuilder;
-use calamine::{Reader, Xlsx};
-use encoding_rs::UTF_8;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, Cursor, Read};
+use std::path::Path;
+use calamine::{Ods, Reader, Sheets, Xls, Xlsb, Xlsx};
+use crate::archive::{self, ArchiveEntry, ArchiveFormat, UnpackBudget, ZIP_MAGIC};
+use crate::cdr::{self, CdrReader};
+use crate::chat;
+use crate::contacts::{self, ContactColumns};
+use crate::email::{self, MailFormat, MboxMessages};
+use crate::encoding::TextEncoding;
 use crate::error::AppError;
 use crate::extractors::Extractors;
-use crate::database::Database;
+use crate::html;
+use crate::job::IngestJob;
+use crate::database::{Database, IngestBatch};
+use crate::device_db;
+use crate::device_report::{self, ReportFormat, ReportRecords, ReportStream};
+use crate::models::{CommEvent, IngestStats, ItemAttributes, ItemType, SourceRecord};
+use crate::stream::{TextLines, CHUNK_SIZE};
 
-const CHUNK_SIZE: usize = 1024 * 1024;
+// Streamed text beyond this is left in the original file rather than copied into the case
+const MAX_INLINE_CONTENT: usize = 64 * 1024 * 1024;
+
+// Compound File Binary header shared by .xls, .doc and .msg
+const OLE2_MAGIC: &[u8] = &[0xD0, 0xCF, 0x11, 0xE0, 0xA1, 0xB1, 0x1A, 0xE1];
+const ODS_MIMETYPE: &[u8] = b"application/vnd.oasis.opendocument.spreadsheet";
+const PDF_MAGIC: &[u8] = b"%PDF-";
+
+// (name, content, container file id, nesting depth) of a file waiting to be processed
+type PendingFile<'a> = (String, Cow<'a, [u8]>, Option<i64>, usize);
+
+// A file written by process_contents, with the attachments found in it
+struct ProcessedFile {
+    file_id: i64,
+    items: Vec<String>,
+    attachments: Vec<ArchiveEntry>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WorkbookFormat {
+    Xls,
+    Xlsx,
+    Xlsb,
+    Ods,
+}
+
+// Where a streamed file's extracted text is kept
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentStorage {
+    // In files.content like any other file, unless it exceeds MAX_INLINE_CONTENT
+    Inline,
+    // Only the original path, in files.content_path
+    Path,
+}
 
 pub struct FileProcessor {
     extractors: Extractors,
+    // Overrides detection for every text file processed
+    encoding: Option<TextEncoding>,
+    job: Option<IngestJob>,
+}
+
+impl Default for FileProcessor {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl FileProcessor {
     pub fn new() -> Self {
         Self {
             extractors: Extractors::new(),
+            encoding: None,
+            job: None,
         }
     }
     
+    pub fn with_region(region: &str) -> Result<Self, AppError> {
+        Ok(Self {
+            extractors: Extractors::with_region(region)?,
+            encoding: None,
+            job: None,
+        })
+    }
+    
+    pub fn with_encoding(mut self, encoding: Option<TextEncoding>) -> Self {
+        self.encoding = encoding;
+        self
+    }
+    
+    // Reports progress to the job and stops, rolling back the delivery, once it is cancelled
+    pub fn with_job(mut self, job: IngestJob) -> Self {
+        self.job = Some(job);
+        self
+    }
+    
+    fn checkpoint(&self, file_name: &str, stats: &IngestStats, force: bool) -> Result<(), AppError> {
+        match &self.job {
+            Some(job) => job.checkpoint(file_name, stats, force),
+            None => Ok(()),
+        }
+    }
+    
+    // Archives are unpacked in memory and their members processed in stored order, each
+    // recorded as a child file of its archive, as email attachments are of their message;
+    // nesting is followed to archive::MAX_DEPTH, and the whole delivery may unpack to
+    // archive::MAX_UNPACKED_BYTES
     pub async fn process_file(
         &self,
         file_name: &str,
@@ -26,80 +114,446 @@ impl FileProcessor {
         source_id: i32,
         db: &Database,
     ) -> Result<Vec<String>, AppError> {
-        let extension = file_name
-            .split('.')
-            .last()
-            .unwrap_or("")
-            .to_lowercase();
-            
-        let content = match extension.as_str() {
-            "csv" => self.process_csv(file_content)?,
-            "xlsx" | "xls" => self.process_excel(file_content)?,
-            "txt" => self.process_text(file_content)?,
-            "html" => self.process_html(file_content)?,
-            _ => self.process_text(file_content)?,
+        let mut stats = IngestStats { bytes_read: file_content.len() as u64, ..Default::default() };
+        let mut batch = db.begin_batch(source_id).await?;
+        let items = self.process_delivery(file_name, file_content, &mut batch, &mut stats).await?;
+        self.checkpoint(file_name, &stats, true)?;
+        batch.commit().await?;
+        Ok(items)
+    }
+    
+    // Reads the file from disk a chunk at a time. CSV and text files are decoded and
+    // extracted as they stream, as are mbox files a message at a time, so memory stays
+    // bounded whatever their size; archives, workbooks, PDF, HTML, single emails and chat
+    // exports need the whole file and go through process_file. Like process_file, it
+    // writes everything or, on error, nothing.
+    pub async fn process_path(
+        &self,
+        path: &Path,
+        source_id: i32,
+        storage: ContentStorage,
+        db: &Database,
+    ) -> Result<IngestStats, AppError> {
+        let file_name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_else(|| path.to_string_lossy().to_string());
+        let extension = extension_of(&file_name);
+        let mut stats = IngestStats::default();
+        
+        let mut file = File::open(path)?;
+        let mut head = Vec::new();
+        (&mut file).take(CHUNK_SIZE as u64).read_to_end(&mut head)?;
+        
+        let mut batch = db.begin_batch(source_id).await?;
+        let encoding = self.encoding.unwrap_or_else(|| TextEncoding::detect(&head));
+        
+        // Chat exports are read a message at a time and address books a card at a time,
+        // which needs the whole file
+        let whole_text = extension != "csv" && {
+            let text = encoding.decode(&head);
+            chat::detect(&text).is_some() || contacts::is_vcard(&text)
         };
+        let report = if device_report::is_report(&head) {
+            Some(ReportFormat::Xml)
+        } else if head.starts_with(ZIP_MAGIC) && device_report::is_ufdr(File::open(path)?) {
+            Some(ReportFormat::Ufdr)
+        } else {
+            None
+        };
+        if let Some(format) = report {
+            let stream = ReportStream::open(path, format);
+            self.process_report(path, &file_name, stream, storage, &mut batch, &mut stats).await?;
+            batch.commit().await?;
+            return Ok(stats);
+        }
         
-        let file_id = db.insert_file(file_name, source_id, &content.join("\n")).await?;
+        let mail = email::detect(&extension, &head);
+        if mail == Some(MailFormat::Mbox) {
+            let reader = BufReader::new(Cursor::new(head).chain(file));
+            self.process_mbox(path, &file_name, reader, storage, &mut batch, &mut stats).await?;
+            batch.commit().await?;
+            return Ok(stats);
+        }
+        if !streams_as_text(&head, &extension) || whole_text || mail.is_some() {
+            let mut content = head;
+            file.read_to_end(&mut content)?;
+            stats.bytes_read = content.len() as u64;
+            self.process_delivery(&file_name, &content, &mut batch, &mut stats).await?;
+            self.checkpoint(&file_name, &stats, true)?;
+            batch.commit().await?;
+            return Ok(stats);
+        }
         
-        let mut extracted_items = Vec::new();
+        let file_id = batch.insert_file(&file_name, "", None).await?;
+        let metadata: ItemAttributes = [("encoding".to_string(), encoding.as_str().to_string())].into_iter().collect();
+        batch.set_file_metadata(file_id, &metadata).await?;
+        stats.files += 1;
+        self.checkpoint(&file_name, &stats, true)?;
+        
+        let mut lines = TextLines::new(Cursor::new(head).chain(file), encoding);
+        let mut csv = (extension == "csv").then(|| CsvRecords::new(&self.extractors));
+        let mut content = (storage == ContentStorage::Inline).then(String::new);
         
-        for chunk in content.chunks(100) {
-            let chunk_text = chunk.join(" ");
+        loop {
+            let record = match &mut csv {
+                Some(csv) => csv.next(&mut lines)?,
+                None => lines.next_record()?,
+            };
+            let Some(record) = record else { break };
             
-            for phone in self.extractors.extract_phones(&chunk_text) {
-                db.insert_extracted_item(&phone, "phone", source_id, file_id).await?;
-                extracted_items.push(phone);
-            }
+            append_content(&mut content, &record.text);
             
-            for email in self.extractors.extract_emails(&chunk_text) {
-                db.insert_extracted_item(&email, "email", source_id, file_id).await?;
-                extracted_items.push(email);
-            }
+            stats.lines += 1;
+            stats.items += self.ingest_record(&record, encoding, file_id, &mut batch).await?.len() as u64;
+            stats.bytes_read = lines.bytes_read();
+            self.checkpoint(&file_name, &stats, false)?;
+        }
+        
+        match content {
+            Some(content) => batch.set_file_content(file_id, &content).await?,
+            None => batch.set_file_content_path(file_id, &path.to_string_lossy()).await?,
+        }
+        self.checkpoint(&file_name, &stats, true)?;
+        batch.commit().await?;
+        
+        Ok(stats)
+    }
+    
+    // Messages are read one at a time, each message's attachments processed before the
+    // next is read; the mbox file itself is written like a streamed text file
+    async fn process_mbox(
+        &self,
+        path: &Path,
+        file_name: &str,
+        reader: impl std::io::BufRead,
+        storage: ContentStorage,
+        batch: &mut IngestBatch,
+        stats: &mut IngestStats,
+    ) -> Result<(), AppError> {
+        let file_id = batch.insert_file(file_name, "", None).await?;
+        stats.files += 1;
+        self.checkpoint(file_name, stats, true)?;
+        
+        let mut budget = UnpackBudget::new();
+        let mut messages = MboxMessages::new(reader);
+        let mut count = 0;
+        let mut content = (storage == ContentStorage::Inline).then(String::new);
+        
+        while let Some(message) = messages.next_message()? {
+            let contents = email::read_message(&message.content, message.line_number, message.byte_offset, &self.extractors);
+            count += contents.messages;
             
-            for ip in self.extractors.extract_ips(&chunk_text) {
-                db.insert_extracted_item(&ip, "ip", source_id, file_id).await?;
-                extracted_items.push(ip);
+            for record in &contents.records {
+                append_content(&mut content, &record.text);
+                
+                stats.lines += 1;
+                stats.items += self.ingest_record(record, TextEncoding::Utf8, file_id, batch).await?.len() as u64;
+                stats.bytes_read = messages.bytes_read();
+                self.checkpoint(file_name, stats, false)?;
             }
+            
+            let attachments = child_files(contents.attachments, file_id, 1).collect();
+            self.process_entries(attachments, &mut budget, batch, stats).await?;
+        }
+        
+        let metadata: ItemAttributes = [
+            ("format".to_string(), MailFormat::Mbox.as_str().to_string()),
+            ("messages".to_string(), count.to_string()),
+        ].into_iter().collect();
+        batch.set_file_metadata(file_id, &metadata).await?;
+        match content {
+            Some(content) => batch.set_file_content(file_id, &content).await?,
+            None => batch.set_file_content_path(file_id, &path.to_string_lossy()).await?,
+        }
+        self.checkpoint(file_name, stats, true)?;
+        
+        Ok(())
+    }
+    
+    // Models are read one at a time on the stream's thread; the report file itself is
+    // written like a streamed text file, its device identifiers as its metadata
+    async fn process_report(
+        &self,
+        path: &Path,
+        file_name: &str,
+        mut stream: ReportStream,
+        storage: ContentStorage,
+        batch: &mut IngestBatch,
+        stats: &mut IngestStats,
+    ) -> Result<(), AppError> {
+        let file_id = batch.insert_file(file_name, "", None).await?;
+        stats.files += 1;
+        self.checkpoint(file_name, stats, true)?;
+        
+        let mut records = ReportRecords::new(stream.format());
+        let mut content = (storage == ContentStorage::Inline).then(String::new);
+        
+        while let Some(entry) = stream.next().await? {
+            let Some(record) = records.record(entry, &self.extractors) else { continue };
+            append_content(&mut content, &record.text);
+            
+            stats.lines += 1;
+            stats.items += self.ingest_record(&record, TextEncoding::Utf8, file_id, batch).await?.len() as u64;
+            stats.bytes_read = stream.bytes_read();
+            self.checkpoint(file_name, stats, false)?;
+        }
+        
+        batch.set_file_metadata(file_id, &records.metadata()).await?;
+        match content {
+            Some(content) => batch.set_file_content(file_id, &content).await?,
+            None => batch.set_file_content_path(file_id, &path.to_string_lossy()).await?,
+        }
+        self.checkpoint(file_name, stats, true)?;
+        
+        Ok(())
+    }
+    
+    async fn process_delivery(
+        &self,
+        file_name: &str,
+        file_content: &[u8],
+        batch: &mut IngestBatch,
+        stats: &mut IngestStats,
+    ) -> Result<Vec<String>, AppError> {
+        let pending = vec![(file_name.to_string(), Cow::Borrowed(file_content), None, 0)];
+        self.process_entries(pending, &mut UnpackBudget::new(), batch, stats).await
+    }
+    
+    // Depth first, so a container's members follow it
+    async fn process_entries(
+        &self,
+        mut pending: Vec<PendingFile<'_>>,
+        budget: &mut UnpackBudget,
+        batch: &mut IngestBatch,
+        stats: &mut IngestStats,
+    ) -> Result<Vec<String>, AppError> {
+        let mut extracted_items = Vec::new();
+        
+        while let Some((name, content, parent_id, depth)) = pending.pop() {
+            self.checkpoint(&name, stats, true)?;
+            // A .ufdr is a zip but is read as the report it holds
+            let format = archive::detect_archive(&content)
+                .filter(|_| detect_workbook(&content).is_none())
+                .filter(|&format| format != ArchiveFormat::Zip || !device_report::is_ufdr(Cursor::new(&content[..])));
+            let Some(format) = format else {
+                let processed = self.process_contents(&name, &content, parent_id, batch, stats).await?;
+                extracted_items.extend(processed.items);
+                if !processed.attachments.is_empty() {
+                    check_depth(&name, depth)?;
+                    pending.extend(child_files(processed.attachments, processed.file_id, depth + 1));
+                }
+                continue;
+            };
+            
+            check_depth(&name, depth)?;
+            
+            let entries = archive::unpack(&name, &content, format, budget)?;
+            let file_id = batch.insert_file(&name, "", parent_id).await?;
+            let metadata: ItemAttributes = [
+                ("format".to_string(), format.as_str().to_string()),
+                ("entries".to_string(), entries.len().to_string()),
+            ].into_iter().collect();
+            batch.set_file_metadata(file_id, &metadata).await?;
+            stats.files += 1;
+            
+            pending.extend(child_files(entries, file_id, depth + 1));
         }
         
         Ok(extracted_items)
     }
     
-    fn process_csv(&self, content: &[u8]) -> Result<Vec<String>, AppError> {
-        let (text, _, _) = UTF_8.decode(content);
-        let mut reader = ReaderBuilder::new()
-            .has_headers(true)
-            .flexible(true)
-            .from_reader(Cursor::new(text.as_bytes()));
+    async fn process_contents(
+        &self,
+        file_name: &str,
+        file_content: &[u8],
+        parent_id: Option<i64>,
+        batch: &mut IngestBatch,
+        stats: &mut IngestStats,
+    ) -> Result<ProcessedFile, AppError> {
+        let extension = extension_of(file_name);
+        
+        let is_pdf = looks_like_pdf(file_content);
+        let is_sqlite = device_db::is_sqlite(file_content);
+        let is_report = device_report::detect(file_content).is_some();
+        let mut metadata = ItemAttributes::new();
+        let mut attachments = Vec::new();
+        let mut text_encoding = TextEncoding::Utf8;
+        
+        // Workbooks are recognized by content; carriers often send HTML tables or
+        // delimited text under an .xls name, which are then read as what they are
+        let records = match (email::detect(&extension, file_content), detect_workbook(file_content)) {
+            _ if is_pdf => self.process_pdf(file_content)?,
+            _ if is_sqlite => {
+                let device = device_db::read(file_content, &self.extractors).await?;
+                metadata.insert("format".to_string(), device.schema.as_str().to_string());
+                metadata.insert("tables".to_string(), device.tables.to_string());
+                device.records
+            }
+            _ if is_report => {
+                let report = device_report::read(file_content, &self.extractors)?;
+                metadata.extend(report.metadata);
+                report.records
+            }
+            (Some(format), _) => {
+                let contents = email::read(format, file_content, &self.extractors)?;
+                metadata.insert("format".to_string(), format.as_str().to_string());
+                metadata.insert("messages".to_string(), contents.messages.to_string());
+                attachments = contents.attachments;
+                contents.records
+            }
+            (None, Some(format)) => self.process_excel(file_content, format)?,
+            (None, None) => {
+                // Returns come as UTF-16 from Windows exports and as Windows-1252 from older systems
+                let encoding = self.encoding.unwrap_or_else(|| TextEncoding::detect(file_content));
+                metadata.insert("encoding".to_string(), encoding.as_str().to_string());
+                let text = encoding.decode(file_content);
+                text_encoding = encoding;
+                
+                let mut records = match extension.as_str() {
+                    "csv" => self.process_csv(&text)?,
+                    "txt" => self.process_text(&text, &mut metadata)?,
+                    "html" | "htm" => self.process_html(&text, &mut metadata)?,
+                    "xlsx" | "xls" | "xlsb" | "ods" if looks_like_html(&text) => self.process_html(&text, &mut metadata)?,
+                    _ => self.process_text(&text, &mut metadata)?,
+                };
+                file_offsets(&mut records, &text, encoding, encoding.bom_len(file_content));
+                records
+            }
+        };
+        
+        let text = records.iter().map(|r| r.text.as_str()).collect::<Vec<_>>().join("\n");
+        let file_id = batch.insert_file(file_name, &text, parent_id).await?;
+        
+        if is_pdf {
+            let pages = records.iter().filter_map(|r| r.page_number).max().unwrap_or(0);
+            metadata.insert("format".to_string(), "pdf".to_string());
+            metadata.insert("export_type".to_string(), pdf_export_type(&text).to_string());
+            metadata.insert("pages".to_string(), pages.to_string());
+        }
+        
+        if !metadata.is_empty() {
+            batch.set_file_metadata(file_id, &metadata).await?;
+        }
+        stats.files += 1;
+        
+        let mut extracted_items = Vec::new();
+        
+        for record in &records {
+            let items = self.ingest_record(record, text_encoding, file_id, batch).await?;
+            stats.lines += 1;
+            stats.items += items.len() as u64;
+            extracted_items.extend(items);
+            self.checkpoint(file_name, stats, false)?;
+        }
+        
+        Ok(ProcessedFile { file_id, items: extracted_items, attachments })
+    }
+    
+    // Each record is scanned on its own so every hit keeps its line, column and row.
+    // `encoding` is the file's, to give hits their byte offset in it. Returns the values
+    // found, each once.
+    async fn ingest_record(
+        &self,
+        record: &SourceRecord,
+        encoding: TextEncoding,
+        file_id: i64,
+        batch: &mut IngestBatch,
+    ) -> Result<Vec<String>, AppError> {
+        let mut extracted_items = Vec::new();
+        
+        if let Some(event) = &record.event {
+            batch.insert_event(&CommEvent::from_cdr(event, batch.source_id(), file_id, record)).await?;
+        }
+        
+        if let Some(contact) = &record.contact {
+            batch.insert_contact(contact, file_id, record.line_number).await?;
+        }
+        
+        let mut item_ids: HashMap<(ItemType, String), i64> = HashMap::new();
+        
+        let mut hits = self.extractors.extract_all(&record.text);
+        if let Some(event) = &record.event {
+            hits.extend(cdr::column_hits(event, &record.text, &hits));
+            hits.sort_by_key(|hit| hit.offset);
+        }
+        
+        for hit in hits {
+            let key = (hit.item_type, hit.value.clone());
+            let item_id = match item_ids.get(&key) {
+                Some(id) => *id,
+                None => {
+                    let attributes = (!hit.attributes.is_empty()).then_some(&hit.attributes);
+                    let id = batch.insert_extracted_item(&hit.value, hit.item_type, file_id, attributes).await?;
+                    item_ids.insert(key, id);
+                    extracted_items.push(hit.value.clone());
+                    id
+                }
+            };
             
+            // Quoted CSV fields can span lines, so position the hit on its own physical line
+            let before = &record.text[..hit.offset];
+            let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+            let line_number = record.line_number + before.matches('\n').count() as i64;
+            let column_number = before[line_start..].chars().count() as i64 + 1;
+            let byte_offset = record.byte_offset.map(|start| start + encoding.encoded_len(before) as i64);
+            let role = match (&record.event, &record.contact) {
+                (Some(event), _) => event.role_of(hit.item_type, &hit.value),
+                (None, Some(contact)) => contact.values
+                    .iter()
+                    .any(|v| v.item_type == hit.item_type && v.value == hit.value)
+                    .then_some("contact"),
+                (None, None) => None,
+            };
+            batch.insert_occurrence(item_id, file_id, record, line_number, column_number, byte_offset, role).await?;
+        }
+        
+        Ok(extracted_items)
+    }
+    
+    fn process_csv(&self, text: &str) -> Result<Vec<SourceRecord>, AppError> {
+        let mut lines = TextLines::new(text.as_bytes(), TextEncoding::Utf8);
+        let mut csv = CsvRecords::new(&self.extractors);
         let mut records = Vec::new();
         
-        for result in reader.records() {
-            if let Ok(record) = result {
-                let row = record.iter().collect::<Vec<_>>().join(" ");
-                records.push(row);
-            }
+        while let Some(record) = csv.next(&mut lines)? {
+            records.push(record);
         }
         
         Ok(records)
     }
     
-    fn process_excel(&self, content: &[u8]) -> Result<Vec<String>, AppError> {
+    fn process_excel(&self, content: &[u8], format: WorkbookFormat) -> Result<Vec<SourceRecord>, AppError> {
         let cursor = Cursor::new(content);
-        let mut workbook: Xlsx<_> = Xlsx::new(cursor)
-            .map_err(|e| AppError::Excel(e.to_string()))?;
+        let excel_error = |e: &dyn std::fmt::Display| AppError::Excel(format!("{:?} workbook: {}", format, e));
+        let mut workbook = match format {
+            WorkbookFormat::Xls => Sheets::Xls(Xls::new(cursor).map_err(|e| excel_error(&e))?),
+            WorkbookFormat::Xlsx => Sheets::Xlsx(Xlsx::new(cursor).map_err(|e| excel_error(&e))?),
+            WorkbookFormat::Xlsb => Sheets::Xlsb(Xlsb::new(cursor).map_err(|e| excel_error(&e))?),
+            WorkbookFormat::Ods => Sheets::Ods(Ods::new(cursor).map_err(|e| excel_error(&e))?),
+        };
             
         let mut records = Vec::new();
         
         for sheet_name in workbook.sheet_names().to_owned() {
             if let Ok(range) = workbook.worksheet_range(&sheet_name) {
-                for row in range.rows() {
-                    let row_text = row.iter()
-                        .map(|cell| cell.to_string())
-                        .collect::<Vec<_>>()
-                        .join(" ");
-                    records.push(row_text);
+                let first_row = range.start().map(|(row, _)| row as i64).unwrap_or(0);
+                let mut cdr = CdrReader::new(&self.extractors);
+                
+                for (i, row) in range.rows().enumerate() {
+                    let cells = row.iter().map(|cell| cell.to_string()).collect::<Vec<_>>();
+                    let event = cdr.read_row(&cells.iter().map(String::as_str).collect::<Vec<_>>());
+                    
+                    // Cells are comma-joined so neighbouring numeric cells never read as one value
+                    records.push(SourceRecord {
+                        line_number: first_row + i as i64 + 1,
+                        byte_offset: None,
+                        sheet_name: Some(sheet_name.clone()),
+                        page_number: None,
+                        text: cells.join(","),
+                        event,
+                        contact: None,
+                    });
                 }
             }
         }
@@ -107,43 +561,306 @@ impl FileProcessor {
         Ok(records)
     }
     
-    fn process_text(&self, content: &[u8]) -> Result<Vec<String>, AppError> {
-        let (text, _, _) = UTF_8.decode(content);
-        let reader = BufReader::new(Cursor::new(text.as_bytes()));
+    // Text is laid out per page, so lines are numbered within their page
+    fn process_pdf(&self, content: &[u8]) -> Result<Vec<SourceRecord>, AppError> {
+        // pdf-extract panics on some malformed fonts; a bad PDF must fail on its own
+        let pages = std::panic::catch_unwind(|| pdf_extract::extract_text_from_mem_by_pages(content))
+            .map_err(|_| AppError::Pdf("unreadable PDF".to_string()))?
+            .map_err(|e| match e {
+                pdf_extract::OutputError::PdfError(e) => AppError::Pdf(e.to_string()),
+                e => AppError::Pdf(e.to_string()),
+            })?;
         
-        let records: Vec<String> = reader
-            .lines()
-            .filter_map(|line| line.ok())
-            .filter(|line| !line.trim().is_empty())
+        let records = pages.iter()
+            .enumerate()
+            .flat_map(|(i, page)| {
+                lines_with_offsets(page).into_iter().map(move |record| SourceRecord {
+                    byte_offset: None,
+                    page_number: Some(i as i64 + 1),
+                    text: record.text.trim().to_string(),
+                    ..record
+                })
+            })
             .collect();
             
         Ok(records)
     }
     
-    fn process_html(&self, content: &[u8]) -> Result<Vec<String>, AppError> {
-        let (text, _, _) = UTF_8.decode(content);
+    // Address books are read a card at a time, messaging app exports a message at a time,
+    // other text a line at a time
+    fn process_text(&self, text: &str, metadata: &mut ItemAttributes) -> Result<Vec<SourceRecord>, AppError> {
+        if contacts::is_vcard(text) {
+            metadata.insert("format".to_string(), "vcard".to_string());
+            return Ok(contacts::read_vcards(text, &self.extractors));
+        }
         
-        let text = text.replace("<br>", "\n")
-            .replace("<br/>", "\n")
-            .replace("<br />", "\n")
-            .replace("</p>", "\n")
-            .replace("</div>", "\n");
-            
-        let text = regex::Regex::new(r"<[^>]+>")
-            .unwrap()
-            .replace_all(&text, " ");
-            
-        let text = regex::Regex::new(r"&[a-zA-Z]+;")
-            .unwrap()
-            .replace_all(&text, " ");
-            
-        let records: Vec<String> = text
-            .lines()
-            .map(|line| line.trim())
-            .filter(|line| !line.is_empty())
-            .map(|line| line.to_string())
+        match chat::read(text, &self.extractors) {
+            Some(export) => {
+                metadata.insert("format".to_string(), export.format.as_str().to_string());
+                Ok(export.records)
+            }
+            None => Ok(lines_with_offsets(text)),
+        }
+    }
+    
+    fn process_html(&self, text: &str, metadata: &mut ItemAttributes) -> Result<Vec<SourceRecord>, AppError> {
+        let text = html::to_text(text);
+        
+        // Line numbers refer to the rendered text; tags leave no meaningful byte positions
+        let records = self.process_text(&text, metadata)?
+            .into_iter()
+            .map(|record| SourceRecord {
+                byte_offset: None,
+                ..record
+            })
             .collect();
             
         Ok(records)
     }
-}
\ No newline at end of file
+}
+
+// Carrier exports are recognized by their header row and each row after it mapped to an
+// event; contact lists by a first row naming name and number columns, each row after it
+// then read as a contact
+struct CsvRecords<'a> {
+    extractors: &'a Extractors,
+    cdr: CdrReader<'a>,
+    contacts: Option<ContactColumns>,
+    header_read: bool,
+}
+
+impl<'a> CsvRecords<'a> {
+    fn new(extractors: &'a Extractors) -> Self {
+        Self {
+            extractors,
+            cdr: CdrReader::new(extractors),
+            contacts: None,
+            header_read: false,
+        }
+    }
+    
+    fn next<R: Read>(&mut self, lines: &mut TextLines<R>) -> Result<Option<SourceRecord>, AppError> {
+        while let Some(row) = lines.next_csv_row()? {
+            let fields = row.fields.iter().map(String::as_str).collect::<Vec<_>>();
+            let event = self.cdr.read_row(&fields);
+            if !std::mem::replace(&mut self.header_read, true) {
+                self.contacts = ContactColumns::detect(&fields).filter(|_| !self.cdr.recognized());
+                continue;
+            }
+            if !row.record.text.trim().is_empty() {
+                let contact = self.contacts.as_ref().and_then(|columns| columns.read_row(&fields, self.extractors));
+                return Ok(Some(SourceRecord { event, contact, ..row.record }));
+            }
+        }
+        Ok(None)
+    }
+}
+
+// Non-blank lines of `text`, numbered from 1 with their starting byte offsets
+fn lines_with_offsets(text: &str) -> Vec<SourceRecord> {
+    let mut lines = TextLines::new(text.as_bytes(), TextEncoding::Utf8);
+    let mut records = Vec::new();
+    
+    // Reading from memory cannot fail
+    while let Ok(Some(record)) = lines.next_record() {
+        records.push(record);
+    }
+    
+    records
+}
+
+// Turns offsets into `text`, the decoded content of a file, into offsets into the file
+fn file_offsets(records: &mut [SourceRecord], text: &str, encoding: TextEncoding, bom: usize) {
+    // Records come in file order, so each offset is measured on from the previous one
+    let (mut text_offset, mut file_offset) = (0, bom);
+    for record in records {
+        let Some(offset) = record.byte_offset.map(|offset| offset as usize) else { continue };
+        if offset < text_offset {
+            (text_offset, file_offset) = (0, bom);
+        }
+        let Some(skipped) = text.get(text_offset..offset) else {
+            record.byte_offset = None;
+            continue;
+        };
+        file_offset += encoding.encoded_len(skipped);
+        text_offset = offset;
+        record.byte_offset = Some(file_offset as i64);
+    }
+}
+
+// Members of an archive or attachments of a message, queued so they are processed in order
+fn child_files<'a>(entries: Vec<ArchiveEntry>, parent_id: i64, depth: usize) -> impl Iterator<Item = PendingFile<'a>> {
+    entries.into_iter().rev().map(move |entry| (entry.path, Cow::Owned(entry.content), Some(parent_id), depth))
+}
+
+fn check_depth(name: &str, depth: usize) -> Result<(), AppError> {
+    if depth >= archive::MAX_DEPTH {
+        return Err(AppError::Archive(format!("{}: archives nested more than {} deep", name, archive::MAX_DEPTH)));
+    }
+    Ok(())
+}
+
+fn extension_of(file_name: &str) -> String {
+    file_name
+        .rsplit('.')
+        .next()
+        .unwrap_or("")
+        .to_lowercase()
+}
+
+// Appends a record's text to a streamed file's content until it outgrows MAX_INLINE_CONTENT,
+// after which only the file's path is kept
+fn append_content(content: &mut Option<String>, record: &str) {
+    let Some(text) = content else { return };
+    if text.len() + record.len() >= MAX_INLINE_CONTENT {
+        *content = None;
+        return;
+    }
+    if !text.is_empty() {
+        text.push('\n');
+    }
+    text.push_str(record);
+}
+
+// Whether process_path can stream the file as CSV or text, judged from its first chunk
+fn streams_as_text(head: &[u8], extension: &str) -> bool {
+    let binary = looks_like_pdf(head)
+        || archive::detect_archive(head).is_some()
+        || head.starts_with(OLE2_MAGIC)
+        || device_db::is_sqlite(head);
+    !binary && !matches!(extension, "html" | "htm" | "xlsx" | "xls" | "xlsb" | "ods")
+}
+
+// Workbook format from the file's leading bytes, whatever its extension says
+pub fn detect_workbook(content: &[u8]) -> Option<WorkbookFormat> {
+    if content.starts_with(OLE2_MAGIC) {
+        // BIFF8 keeps its sheets in a "Workbook" stream, BIFF5 in "Book"; Word or
+        // Outlook files have neither
+        let file = cfb::CompoundFile::open(Cursor::new(content)).ok()?;
+        let is_xls = file.is_stream("/Workbook") || file.is_stream("/Book");
+        return is_xls.then_some(WorkbookFormat::Xls);
+    }
+    
+    if !content.starts_with(ZIP_MAGIC) {
+        return None;
+    }
+    
+    let mut archive = zip::ZipArchive::new(Cursor::new(content)).ok()?;
+    
+    // OpenDocument names its media type in a leading "mimetype" entry
+    if let Ok(mut entry) = archive.by_name("mimetype") {
+        let mut mimetype = Vec::new();
+        entry.read_to_end(&mut mimetype).ok()?;
+        return mimetype.starts_with(ODS_MIMETYPE).then_some(WorkbookFormat::Ods);
+    }
+    
+    if archive.by_name("xl/workbook.bin").is_ok() {
+        Some(WorkbookFormat::Xlsb)
+    } else if archive.by_name("xl/workbook.xml").is_ok() {
+        Some(WorkbookFormat::Xlsx)
+    } else {
+        None
+    }
+}
+
+fn looks_like_html(text: &str) -> bool {
+    text.trim_start_matches('\u{FEFF}').trim_start().starts_with('<')
+}
+
+// The header may follow a few bytes of junk; readers accept it within the first 1 KiB
+fn looks_like_pdf(content: &[u8]) -> bool {
+    content[..content.len().min(1024)]
+        .windows(PDF_MAGIC.len())
+        .any(|w| w == PDF_MAGIC)
+}
+
+// Which service produced a law-enforcement return, by the names it prints. Instagram
+// and WhatsApp returns also name Meta, so they are checked before Facebook.
+pub fn pdf_export_type(text: &str) -> &'static str {
+    let text = text.to_lowercase();
+    let has = |word: &str| {
+        text.match_indices(word).any(|(i, _)| {
+            let before = text[..i].chars().next_back();
+            let after = text[i + word.len()..].chars().next();
+            !before.is_some_and(char::is_alphanumeric) && !after.is_some_and(char::is_alphanumeric)
+        })
+    };
+    
+    if has("snapchat") || has("snap inc") {
+        "Snapchat_Export"
+    } else if has("instagram") {
+        "Instagram_Export"
+    } else if has("whatsapp") {
+        "WhatsApp_Export"
+    } else if has("facebook") || has("meta") {
+        "Facebook_Export"
+    } else if has("twitter") || has("x.com") {
+        "Twitter_Export"
+    } else if has("telegram") {
+        "Telegram_Export"
+    } else if has("user id:") && has("timestamp") {
+        "Social_Media_Export"
+    } else if has("forensic") || has("investigation") {
+        "Forensic_Report"
+    } else {
+        "General_Document"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    // A BIFF8 workbook with one "Calls" sheet, written as Excel 97 lays it out
+    const BIFF8_XLS: &[u8] = include_bytes!("../tests/fixtures/calls.xls");
+
+    fn compound_file(stream: &str) -> Vec<u8> {
+        let mut file = cfb::CompoundFile::create(Cursor::new(Vec::new())).unwrap();
+        file.create_stream(stream).unwrap().write_all(b"data").unwrap();
+        file.flush().unwrap();
+        file.into_inner().into_inner()
+    }
+
+    #[test]
+    fn detects_biff8_workbook_stream() {
+        assert_eq!(detect_workbook(BIFF8_XLS), Some(WorkbookFormat::Xls));
+        assert_eq!(detect_workbook(&compound_file("/Book")), Some(WorkbookFormat::Xls));
+    }
+
+    #[test]
+    fn other_compound_files_are_not_workbooks() {
+        assert_eq!(detect_workbook(&compound_file("/WordDocument")), None);
+        assert_eq!(detect_workbook(&compound_file("/__properties_version1.0")), None);
+        assert_eq!(detect_workbook(b"Caller,Called\n"), None);
+    }
+
+    #[test]
+    fn reads_biff8_rows_with_sheet_names() {
+        let records = FileProcessor::new().process_excel(BIFF8_XLS, WorkbookFormat::Xls).unwrap();
+
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].text, "Caller,Called");
+        assert_eq!(records[1].text, "+12025550143,3125550178");
+        assert_eq!(records[1].line_number, 2);
+        assert_eq!(records[1].sheet_name.as_deref(), Some("Calls"));
+    }
+
+    #[tokio::test]
+    async fn utf16_hits_keep_their_offset_in_the_file() {
+        let bytes: Vec<u8> = [0xFEFF_u16].into_iter().chain("Zoë\ncall 202-555-0143\n".encode_utf16()).flat_map(u16::to_le_bytes).collect();
+        // BOM, "Zoë\n" and "call " are 2 + 8 + 10 bytes in UTF-16
+        let expected = Some(20);
+
+        let db = Database::new_scratch().await.unwrap();
+        FileProcessor::new().process_file("calls.txt", &bytes, 1, &db).await.unwrap();
+        let path = std::env::temp_dir().join(format!("cast-utf16-{}.txt", std::process::id()));
+        std::fs::write(&path, &bytes).unwrap();
+        FileProcessor::new().process_path(&path, 1, ContentStorage::Inline, &db).await.unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let occurrences = db.get_occurrences("+12025550143", None).await.unwrap();
+        assert_eq!(occurrences.len(), 2);
+        assert!(occurrences.iter().all(|occurrence| occurrence.byte_offset == expected));
+    }
+}
@@ -0,0 +1,94 @@
+// Rendering of HTML returns and exports to plain text lines
+use std::borrow::Cow;
+use std::sync::LazyLock;
+use regex::{Captures, Regex};
+
+static TAG: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"<[^>]+>").unwrap());
+
+// Numeric references in decimal or hex, e.g. the "&#43;" some exports write for "+" in phone
+// numbers, and the named entities returns use
+static ENTITY: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"&(?:#(\d{1,7})|#[xX]([0-9A-Fa-f]{1,6})|([A-Za-z][A-Za-z0-9]*));").unwrap()
+});
+
+// Block ends become line breaks and other tags spaces; lines are trimmed and the blank ones
+// nested blocks leave dropped. Entities are decoded last so escaped markup such as
+// "&lt;b&gt;" stays text.
+pub fn to_text(html: &str) -> String {
+    let text = html.replace("<br>", "\n")
+        .replace("<br/>", "\n")
+        .replace("<br />", "\n")
+        .replace("</p>", "\n")
+        .replace("</div>", "\n");
+
+    let text = TAG.replace_all(&text, " ");
+
+    decode_entities(&text)
+        .split('\n')
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// Character references are replaced by what they stand for; an unknown name reads as a space
+pub fn decode_entities(text: &str) -> Cow<'_, str> {
+    ENTITY.replace_all(text, |caps: &Captures| {
+        let code = match (caps.get(1), caps.get(2), caps.get(3)) {
+            (Some(decimal), _, _) => decimal.as_str().parse().ok(),
+            (_, Some(hex), _) => u32::from_str_radix(hex.as_str(), 16).ok(),
+            (_, _, Some(name)) => return named_entity(name.as_str()).to_string(),
+            _ => None,
+        };
+        // NUL and surrogates are not characters; keep the reference as written
+        match code.and_then(|code| char::from_u32(code).filter(|c| *c != '\0')) {
+            Some(c) => c.to_string(),
+            None => caps[0].to_string(),
+        }
+    })
+}
+
+fn named_entity(name: &str) -> &'static str {
+    match name {
+        "amp" | "AMP" => "&",
+        "lt" | "LT" => "<",
+        "gt" | "GT" => ">",
+        "quot" | "QUOT" => "\"",
+        "apos" => "'",
+        "plus" => "+",
+        "commat" => "@",
+        "period" => ".",
+        "lpar" => "(",
+        "rpar" => ")",
+        "hyphen" | "dash" => "-",
+        "ndash" => "\u{2013}",
+        "mdash" => "\u{2014}",
+        "lsquo" => "\u{2018}",
+        "rsquo" => "\u{2019}",
+        "ldquo" => "\u{201C}",
+        "rdquo" => "\u{201D}",
+        "hellip" => "\u{2026}",
+        "copy" => "\u{A9}",
+        "reg" => "\u{AE}",
+        "trade" => "\u{2122}",
+        _ => " ",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_blocks_as_lines() {
+        let html = "<div><span>Sender</span><span>+1 202-555-0143</span></div><p>First</p>\n\n<div><p>Second<br>Third</p></div>";
+        assert_eq!(to_text(html), "Sender  +1 202-555-0143\nFirst\nSecond\nThird");
+    }
+
+    #[test]
+    fn decodes_references_after_tags_are_gone() {
+        assert_eq!(to_text("&lt;b&gt;bold&lt;/b&gt; &amp; &#43;1&#x20;202"), "<b>bold</b> & +1 202");
+        assert_eq!(decode_entities("a&nosuch;b"), "a b");
+        assert_eq!(decode_entities("&#0;&#xD800;"), "&#0;&#xD800;");
+    }
+}
@@ -0,0 +1,69 @@
+// Progress reporting and cancellation for an ingestion running in the background
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use crate::error::AppError;
+use crate::models::{IngestProgress, IngestStats};
+
+// Between the forced reports at the start and end of each file, progress is reported at most this often
+const REPORT_INTERVAL: Duration = Duration::from_millis(250);
+
+type ProgressFn = dyn Fn(&IngestProgress) + Send + Sync;
+
+// Clones share the job: cancelling any of them stops it
+#[derive(Clone)]
+pub struct IngestJob {
+    pub id: u64,
+    cancelled: Arc<AtomicBool>,
+    progress: Option<Arc<ProgressFn>>,
+    last_report: Arc<Mutex<Option<Instant>>>,
+}
+
+impl IngestJob {
+    pub fn new(id: u64) -> Self {
+        Self {
+            id,
+            cancelled: Arc::new(AtomicBool::new(false)),
+            progress: None,
+            last_report: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    pub fn on_progress(mut self, report: impl Fn(&IngestProgress) + Send + Sync + 'static) -> Self {
+        self.progress = Some(Arc::new(report));
+        self
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    // Called between records. Once the job is cancelled this fails, so the file being
+    // written is rolled back like any other failed file.
+    pub fn checkpoint(&self, file_name: &str, stats: &IngestStats, force: bool) -> Result<(), AppError> {
+        if self.is_cancelled() {
+            return Err(AppError::Cancelled);
+        }
+
+        let Some(report) = &self.progress else {
+            return Ok(());
+        };
+
+        let now = Instant::now();
+        let mut last_report = self.last_report.lock().unwrap_or_else(|e| e.into_inner());
+        if force || last_report.is_none_or(|last| now - last >= REPORT_INTERVAL) {
+            *last_report = Some(now);
+            report(&IngestProgress {
+                job_id: self.id,
+                file_name: file_name.to_string(),
+                stats: stats.clone(),
+            });
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,21 @@
+// Extraction and case-database backend shared by the desktop app and cast-cli
+pub mod archive;
+pub mod cast_json;
+pub mod cdr;
+pub mod chat;
+pub mod contacts;
+pub mod database;
+pub mod device_db;
+pub mod device_id;
+pub mod device_report;
+pub mod email;
+pub mod encoding;
+pub mod error;
+pub mod export;
+pub mod extractors;
+pub mod file_processor;
+pub mod html;
+pub mod job;
+pub mod models;
+pub mod phone;
+pub mod stream;
@@ -1,7 +1,25 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+mod commands;
+
+use cast_database_analyzer::{cast_json, database, encoding, error, export, extractors, file_processor, job, models, phone};
+use std::collections::HashMap;
+use std::sync::atomic::AtomicU64;
 use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use database::Database;
+use job::IngestJob;
+
+// Shared backend state; the case database is created on first use
+pub struct AppState {
+    pub db: Mutex<Option<Database>>,
+    // Region used to interpret phone numbers written without a country code
+    pub default_region: Mutex<String>,
+    // Background ingestions still running, by job id
+    pub jobs: Mutex<HashMap<u64, IngestJob>>,
+    pub next_job_id: AtomicU64,
+}
 
 #[derive(Serialize, Deserialize)]
 struct FileData {
@@ -42,11 +60,13 @@ async fn read_dropped_files(paths: Vec<String>) -> Result<Vec<FileData>, String>
     }
 }
 
-// Simple save file command for Tauri 2.x with plugins
+// Save a CAST_JSON case from the webview; it is validated before anything is written
 #[tauri::command]
 async fn save_database_file(app: tauri::AppHandle, data: String) -> Result<String, String> {
     use tauri_plugin_dialog::DialogExt;
     
+    let case = cast_json::parse(&data).map_err(|e| e.to_string())?;
+    
     match app.dialog()
         .file()
         .set_title("Save CAST Database")
@@ -54,7 +74,7 @@ async fn save_database_file(app: tauri::AppHandle, data: String) -> Result<Strin
         .blocking_save_file() 
     {
         Some(path) => {
-            match std::fs::write(path.as_path().unwrap(), data) {
+            match cast_json::write(&path.to_string(), &case) {
                 Ok(_) => Ok(path.to_string()),
                 Err(e) => Err(format!("Failed to write file: {}", e))
             }
@@ -103,9 +123,16 @@ fn main() {
     tauri::Builder::default()
         .plugin(tauri_plugin_fs::init())
         .plugin(tauri_plugin_dialog::init())
+        .manage(AppState {
+            db: Mutex::new(None),
+            default_region: Mutex::new(phone::DEFAULT_REGION.to_string()),
+            jobs: Mutex::new(HashMap::new()),
+            next_job_id: AtomicU64::new(0),
+        })
         .setup(|app| {
             #[cfg(debug_assertions)]
             {
+                use tauri::Manager;
                 let window = app.get_webview_window("main").unwrap();
                 window.open_devtools();
             }
@@ -115,7 +142,33 @@ fn main() {
             read_dropped_files,
             save_database_file,
             open_database_file,
-            show_message
+            show_message,
+            commands::process_file,
+            commands::process_path,
+            commands::start_ingest,
+            commands::cancel_ingest,
+            commands::get_analysis,
+            commands::analyze_cross_reference,
+            commands::get_occurrences,
+            commands::load_database,
+            commands::save_database,
+            commands::export_case_json,
+            commands::export_csv,
+            commands::export_cross_reference,
+            commands::clear_all,
+            commands::get_database_info,
+            commands::list_sources,
+            commands::create_source,
+            commands::rename_source,
+            commands::update_source,
+            commands::delete_source,
+            commands::get_events_by_party,
+            commands::get_events_in_range,
+            commands::get_events_by_source,
+            commands::analyze_tower_intersection,
+            commands::export_tower_intersection,
+            commands::get_default_region,
+            commands::set_default_region
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -1,4 +1,9 @@
+use std::collections::BTreeMap;
 use serde::{Deserialize, Serialize};
+use crate::cdr;
+
+// Per-item details beyond the value itself, e.g. {"country": "GB"} for phones
+pub type ItemAttributes = BTreeMap<String, String>;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ExtractedItem {
@@ -6,13 +11,51 @@ pub struct ExtractedItem {
     pub source: i32,
     pub count: i32,
     pub item_type: ItemType,
+    #[serde(default)]
+    pub attributes: ItemAttributes,
+    // Names the case's address books give the value
+    #[serde(default)]
+    pub aliases: Vec<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum ItemType {
     Phone,
     Email,
     Ip,
+    Imsi,
+    Imei,
+    Iccid,
+}
+
+impl ItemType {
+    // Name stored in the extracted_items.item_type column
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ItemType::Phone => "phone",
+            ItemType::Email => "email",
+            ItemType::Ip => "ip",
+            ItemType::Imsi => "imsi",
+            ItemType::Imei => "imei",
+            ItemType::Iccid => "iccid",
+        }
+    }
+}
+
+impl std::str::FromStr for ItemType {
+    type Err = String;
+    
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "phone" => Ok(ItemType::Phone),
+            "email" => Ok(ItemType::Email),
+            "ip" => Ok(ItemType::Ip),
+            "imsi" => Ok(ItemType::Imsi),
+            "imei" => Ok(ItemType::Imei),
+            "iccid" => Ok(ItemType::Iccid),
+            _ => Err(format!("Unknown item type: {}", s)),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -20,8 +63,224 @@ pub struct FileRecord {
     pub id: Option<i64>,
     pub file_name: String,
     pub source_id: i32,
+    // The archive this file was unpacked from
+    pub parent_id: Option<i64>,
     pub content: String,
+    // The original file, for text too large to keep in the case or not kept by choice
+    #[serde(default)]
+    pub content_path: Option<String>,
     pub processed_at: Option<String>,
+    // What the processor learned about the file as a whole, e.g. a PDF's export_type
+    #[serde(default)]
+    pub metadata: ItemAttributes,
+}
+
+// One row of an input file as read by FileProcessor, before extraction
+#[derive(Debug, Clone)]
+pub struct SourceRecord {
+    pub line_number: i64,
+    // Byte offset of the row within the file as stored, before decoding; None where it has
+    // no byte position (spreadsheets)
+    pub byte_offset: Option<i64>,
+    // Worksheet the row came from; line_number is then the row within that sheet
+    pub sheet_name: Option<String>,
+    // PDF page the line came from; line_number is then the line within that page
+    pub page_number: Option<i64>,
+    pub text: String,
+    // Set when the row belongs to a recognized carrier CDR layout or is a message of a chat export
+    pub event: Option<CdrEvent>,
+    // Set when the record is an address-book entry: a vCard or a row of a contact list
+    pub contact: Option<Contact>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Carrier {
+    Att,
+    TMobile,
+    Verizon,
+}
+
+impl Carrier {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Carrier::Att => "AT&T",
+            Carrier::TMobile => "T-Mobile",
+            Carrier::Verizon => "Verizon",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EventType {
+    Call,
+    Sms,
+    Data,
+    // A message read from a messaging app's export or return
+    Message,
+    Email,
+}
+
+impl EventType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EventType::Call => "call",
+            EventType::Sms => "sms",
+            EventType::Data => "data",
+            EventType::Message => "message",
+            EventType::Email => "email",
+        }
+    }
+}
+
+impl std::str::FromStr for EventType {
+    type Err = String;
+    
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "call" => Ok(EventType::Call),
+            "sms" => Ok(EventType::Sms),
+            "data" => Ok(EventType::Data),
+            "message" => Ok(EventType::Message),
+            "email" => Ok(EventType::Email),
+            _ => Err(format!("Unknown event type: {}", s)),
+        }
+    }
+}
+
+// Direction relative to the subscriber whose records these are
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Incoming,
+    Outgoing,
+}
+
+impl Direction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Direction::Incoming => "incoming",
+            Direction::Outgoing => "outgoing",
+        }
+    }
+}
+
+impl std::str::FromStr for Direction {
+    type Err = String;
+    
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "incoming" => Ok(Direction::Incoming),
+            "outgoing" => Ok(Direction::Outgoing),
+            _ => Err(format!("Unknown direction: {}", s)),
+        }
+    }
+}
+
+// One call, message or data session as reported in a carrier CDR row or a messaging app's
+// records. Parties are E.164, except app users known only by name or account id.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CdrEvent {
+    // None for messaging app records
+    pub carrier: Option<Carrier>,
+    pub event_type: EventType,
+    pub direction: Option<Direction>,
+    pub originating: Option<String>,
+    pub terminating: Option<String>,
+    // Digits as dialled, where the carrier reports them apart from the terminating party
+    pub dialed: Option<String>,
+    // Target of the return, or the number seen on the tower in a tower dump
+    pub subscriber: Option<String>,
+    // RFC 3339 with the offset the carrier reported in, or the local time as written when
+    // it named no zone
+    pub start_time: Option<String>,
+    pub duration_seconds: Option<i64>,
+    pub cell_site: Option<String>,
+    pub sector: Option<String>,
+    pub imei: Option<String>,
+    pub imsi: Option<String>,
+    // Everyone a message went to; terminating is only set when that is one party
+    #[serde(default)]
+    pub recipients: Vec<String>,
+    // File names of media sent with a message
+    #[serde(default)]
+    pub attachments: Vec<String>,
+    // Parts played by values the fields above do not hold, e.g. an email's Cc addresses or
+    // the relay IPs of its Received headers, as (value, role)
+    #[serde(default)]
+    pub roles: Vec<(String, String)>,
+}
+
+impl CdrEvent {
+    // Part `value` plays in this event, recorded on its occurrence
+    pub fn role_of(&self, item_type: ItemType, value: &str) -> Option<&str> {
+        let is = |field: &Option<String>| field.as_deref() == Some(value);
+        
+        match item_type {
+            ItemType::Phone if is(&self.originating) => Some("originating"),
+            ItemType::Phone if is(&self.terminating) => Some("terminating"),
+            ItemType::Phone if is(&self.dialed) => Some("dialed"),
+            ItemType::Phone if is(&self.subscriber) => Some("subscriber"),
+            ItemType::Phone if self.recipients.iter().any(|r| r == value) => Some("recipient"),
+            ItemType::Imei if is(&self.imei) => Some("device"),
+            ItemType::Imsi if is(&self.imsi) => Some("subscriber"),
+            _ => self.roles.iter().find(|(v, _)| v == value).map(|(_, role)| role.as_str()),
+        }
+    }
+    
+    // Parties that are email addresses, e.g. iMessage handles, are only matched through roles
+    pub fn add_address_roles(&mut self) {
+        let parties: Vec<(String, &str)> = [("originating", &self.originating), ("terminating", &self.terminating)]
+            .into_iter()
+            .filter_map(|(role, party)| party.clone().map(|p| (p, role)))
+            .chain(self.recipients.iter().map(|p| (p.clone(), "recipient")))
+            .filter(|(party, _)| party.contains('@'))
+            .collect();
+        for (party, role) in parties {
+            if !self.roles.iter().any(|(v, _)| *v == party) {
+                self.roles.push((party, role.to_string()));
+            }
+        }
+    }
+}
+
+// A name an address book gives to phone numbers and email addresses
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Contact {
+    pub name: String,
+    pub values: Vec<ContactValue>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContactValue {
+    // E.164 for phone numbers, as extracted from text
+    pub value: String,
+    pub item_type: ItemType,
+    // The book's own label, e.g. "cell" or "work"
+    pub label: Option<String>,
+}
+
+// A single hit of an extracted value, traceable to the exact row it came from
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Occurrence {
+    pub value: String,
+    pub item_type: ItemType,
+    pub file_id: i64,
+    pub file_name: String,
+    pub source_id: i32,
+    pub sheet_name: Option<String>,
+    pub page_number: Option<i64>,
+    pub line_number: i64,
+    pub column_number: i64,
+    pub byte_offset: Option<i64>,
+    pub row_data: String,
+    // "originating", "terminating", "dialed", "subscriber" or "device" for CDR rows,
+    // "recipient" for the other members of a group message, and "from", "to", "cc", "bcc",
+    // "reply-to" or "received" (a relay IP) for the headers of an email, and "contact" for
+    // the numbers and addresses of an address-book entry
+    pub role: Option<String>,
+    // Start of the event on the same row, where the row was read as a carrier record
+    pub start_time: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -36,21 +295,204 @@ pub struct Record {
     pub content: String,
 }
 
+// Running totals of one ingestion; archive members count as files
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IngestStats {
+    pub files: u64,
+    pub bytes_read: u64,
+    pub lines: u64,
+    pub items: u64,
+}
+
+impl IngestStats {
+    pub fn add(&mut self, other: &IngestStats) {
+        self.files += other.files;
+        self.bytes_read += other.bytes_read;
+        self.lines += other.lines;
+        self.items += other.items;
+    }
+}
+
+// Payload of the "ingest-progress" event; stats are those of the file named
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IngestProgress {
+    pub job_id: u64,
+    pub file_name: String,
+    #[serde(flatten)]
+    pub stats: IngestStats,
+}
+
+// Payload of the "ingest-finished" event. Files that failed, or the one being written
+// when the job was cancelled, left no rows behind.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IngestFinished {
+    pub job_id: u64,
+    pub stats: IngestStats,
+    pub cancelled: bool,
+    // "file: error" for each file skipped
+    pub errors: Vec<String>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AnalysisResult {
     pub phones: Vec<ExtractedItem>,
     pub emails: Vec<ExtractedItem>,
     pub ips: Vec<ExtractedItem>,
+    pub imsis: Vec<ExtractedItem>,
+    pub imeis: Vec<ExtractedItem>,
+    pub iccids: Vec<ExtractedItem>,
+}
+
+// One carrier return, warrant production or other body of evidence in the case
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Source {
+    pub id: i32,
+    pub name: String,
+    pub provider: Option<String>,
+    pub warrant_number: Option<String>,
+    pub created_at: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SourceRef {
+    pub id: i32,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrossReferenceItem {
+    pub value: String,
+    pub item_type: ItemType,
+    pub count: i32,
+    #[serde(default)]
+    pub attributes: ItemAttributes,
+    pub sources: Vec<SourceRef>,
+    #[serde(default)]
+    pub aliases: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DatabaseInfo {
-    pub source1: SourceInfo,
-    pub source2: SourceInfo,
+    pub sources: Vec<SourceInfo>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SourceInfo {
+    pub source: Source,
     pub files: Vec<String>,
     pub records: Vec<Record>,
-}
\ No newline at end of file
+}
+
+// Who contacted whom, when and from where; one row of the events table
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommEvent {
+    pub id: Option<i64>,
+    pub source_id: i32,
+    pub file_id: i64,
+    pub file_name: Option<String>,
+    pub sheet_name: Option<String>,
+    pub line_number: Option<i64>,
+    pub event_type: EventType,
+    pub direction: Option<Direction>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    // RFC 3339 with the offset the record was reported in, or without one when the record
+    // named no zone
+    pub start_time: Option<String>,
+    // Set when start_time is such a local time; it is then ordered as if it were UTC
+    #[serde(default)]
+    pub local_time: bool,
+    pub duration_seconds: Option<i64>,
+    pub cell_site: Option<String>,
+    pub sector: Option<String>,
+    pub imei: Option<String>,
+    pub imsi: Option<String>,
+    #[serde(default)]
+    pub recipients: Vec<String>,
+    #[serde(default)]
+    pub attachments: Vec<String>,
+}
+
+impl CommEvent {
+    pub fn from_cdr(event: &CdrEvent, source_id: i32, file_id: i64, record: &SourceRecord) -> Self {
+        Self {
+            id: None,
+            source_id,
+            file_id,
+            file_name: None,
+            sheet_name: record.sheet_name.clone(),
+            line_number: Some(record.line_number),
+            event_type: event.event_type,
+            direction: event.direction,
+            from: event.originating.clone(),
+            to: event.terminating.clone(),
+            start_time: event.start_time.clone(),
+            local_time: event.start_time.as_deref().is_some_and(cdr::is_local_time),
+            duration_seconds: event.duration_seconds,
+            cell_site: event.cell_site.clone(),
+            sector: event.sector.clone(),
+            imei: event.imei.clone(),
+            imsi: event.imsi.clone(),
+            recipients: event.recipients.clone(),
+            attachments: event.attachments.clone(),
+        }
+    }
+}
+
+// Constraints for an events query; unset fields match everything
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EventFilter {
+    // Phone number, IMEI, IMSI or app user on either end of the event, or among its recipients
+    pub party: Option<String>,
+    // Inclusive bounds, RFC 3339 or a bare UTC date
+    pub start: Option<String>,
+    pub end: Option<String>,
+    pub source_id: Option<i32>,
+}
+
+// One tower dump in an intersection: its source and the window of interest
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TowerSite {
+    pub source_id: i32,
+    // Inclusive bounds, RFC 3339 or a bare UTC date; unset means the whole dump
+    pub start: Option<String>,
+    pub end: Option<String>,
+    pub label: Option<String>,
+}
+
+// The records at one site where an identifier was seen
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TowerSiteMatch {
+    // Position of the site in the request
+    pub site: usize,
+    pub label: String,
+    pub source: SourceRef,
+    pub records: Vec<Occurrence>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TowerIntersectionItem {
+    pub value: String,
+    pub item_type: ItemType,
+    pub site_count: usize,
+    pub sites: Vec<TowerSiteMatch>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SaveStage {
+    Copying,
+    Verifying,
+    Done,
+}
+
+// Reported while a case is written to disk; page counts are only set while copying
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SaveProgress {
+    pub stage: SaveStage,
+    pub pages_copied: i64,
+    pub pages_total: i64,
+}
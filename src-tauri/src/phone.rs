@@ -0,0 +1,305 @@
+// Country calling-code metadata and E.164 normalization for extracted phone numbers
+use serde::Serialize;
+
+pub const DEFAULT_REGION: &str = "US";
+
+#[derive(Debug, Serialize)]
+pub struct Country {
+    pub region: &'static str,
+    pub calling_code: &'static str,
+    // Valid lengths of the national significant number (no trunk prefix)
+    pub national_lengths: &'static [usize],
+    pub trunk_prefix: Option<&'static str>,
+    pub idd_prefix: &'static str,
+}
+
+macro_rules! country {
+    ($region:expr, $cc:expr, $lengths:expr, $trunk:expr, $idd:expr) => {
+        Country {
+            region: $region,
+            calling_code: $cc,
+            national_lengths: $lengths,
+            trunk_prefix: $trunk,
+            idd_prefix: $idd,
+        }
+    };
+}
+
+// The first entry for a shared calling code is the one reported when the region
+// cannot be told apart from the number alone (e.g. +1 is reported as US).
+pub static COUNTRIES: &[Country] = &[
+    country!("US", "1", &[10], Some("1"), "011"),
+    country!("CA", "1", &[10], Some("1"), "011"),
+    country!("PR", "1", &[10], Some("1"), "011"),
+    country!("DO", "1", &[10], Some("1"), "011"),
+    country!("JM", "1", &[10], Some("1"), "011"),
+    country!("RU", "7", &[10], Some("8"), "810"),
+    country!("KZ", "7", &[10], Some("8"), "810"),
+    country!("EG", "20", &[8, 9, 10], Some("0"), "00"),
+    country!("ZA", "27", &[9], Some("0"), "00"),
+    country!("GR", "30", &[10], None, "00"),
+    country!("NL", "31", &[9], Some("0"), "00"),
+    country!("BE", "32", &[8, 9], Some("0"), "00"),
+    country!("FR", "33", &[9], Some("0"), "00"),
+    country!("ES", "34", &[9], None, "00"),
+    country!("HU", "36", &[8, 9], Some("06"), "00"),
+    country!("IT", "39", &[6, 7, 8, 9, 10, 11], None, "00"),
+    country!("RO", "40", &[9], Some("0"), "00"),
+    country!("CH", "41", &[9], Some("0"), "00"),
+    country!("AT", "43", &[7, 8, 9, 10, 11, 12, 13], Some("0"), "00"),
+    country!("GB", "44", &[9, 10], Some("0"), "00"),
+    country!("DK", "45", &[8], None, "00"),
+    country!("SE", "46", &[7, 8, 9, 10], Some("0"), "00"),
+    country!("NO", "47", &[8], None, "00"),
+    country!("PL", "48", &[9], None, "00"),
+    country!("DE", "49", &[7, 8, 9, 10, 11, 12, 13], Some("0"), "00"),
+    country!("PE", "51", &[8, 9], Some("0"), "00"),
+    country!("MX", "52", &[10], None, "00"),
+    country!("CU", "53", &[8], Some("0"), "119"),
+    country!("AR", "54", &[10, 11], Some("0"), "00"),
+    country!("BR", "55", &[10, 11], Some("0"), "00"),
+    country!("CL", "56", &[9], None, "00"),
+    country!("CO", "57", &[8, 10], None, "00"),
+    country!("VE", "58", &[10], Some("0"), "00"),
+    country!("MY", "60", &[8, 9, 10], Some("0"), "00"),
+    country!("AU", "61", &[9], Some("0"), "0011"),
+    country!("ID", "62", &[8, 9, 10, 11, 12], Some("0"), "001"),
+    country!("PH", "63", &[8, 10], Some("0"), "00"),
+    country!("NZ", "64", &[8, 9, 10], Some("0"), "00"),
+    country!("SG", "65", &[8], None, "000"),
+    country!("TH", "66", &[8, 9], Some("0"), "001"),
+    country!("JP", "81", &[9, 10], Some("0"), "010"),
+    country!("KR", "82", &[8, 9, 10], Some("0"), "001"),
+    country!("VN", "84", &[9, 10], Some("0"), "00"),
+    country!("CN", "86", &[10, 11], Some("0"), "00"),
+    country!("TR", "90", &[10], Some("0"), "00"),
+    country!("IN", "91", &[10], Some("0"), "00"),
+    country!("PK", "92", &[9, 10], Some("0"), "00"),
+    country!("AF", "93", &[9], Some("0"), "00"),
+    country!("LK", "94", &[9], Some("0"), "00"),
+    country!("MM", "95", &[8, 9, 10], Some("0"), "00"),
+    country!("IR", "98", &[10], Some("0"), "00"),
+    country!("MA", "212", &[9], Some("0"), "00"),
+    country!("DZ", "213", &[8, 9], Some("0"), "00"),
+    country!("TN", "216", &[8], None, "00"),
+    country!("LY", "218", &[9], Some("0"), "00"),
+    country!("GH", "233", &[9], Some("0"), "00"),
+    country!("NG", "234", &[8, 10], Some("0"), "009"),
+    country!("KE", "254", &[9], Some("0"), "000"),
+    country!("TZ", "255", &[9], Some("0"), "000"),
+    country!("UG", "256", &[9], Some("0"), "000"),
+    country!("PT", "351", &[9], None, "00"),
+    country!("IE", "353", &[7, 8, 9], Some("0"), "00"),
+    country!("IS", "354", &[7], None, "00"),
+    country!("FI", "358", &[5, 6, 7, 8, 9, 10, 11, 12], Some("0"), "00"),
+    country!("LV", "371", &[8], None, "00"),
+    country!("EE", "372", &[7, 8], None, "00"),
+    country!("UA", "380", &[9], Some("0"), "00"),
+    country!("CZ", "420", &[9], None, "00"),
+    country!("SK", "421", &[9], Some("0"), "00"),
+    country!("GT", "502", &[8], None, "00"),
+    country!("SV", "503", &[8], None, "00"),
+    country!("HN", "504", &[8], None, "00"),
+    country!("NI", "505", &[8], None, "00"),
+    country!("CR", "506", &[8], None, "00"),
+    country!("PA", "507", &[7, 8], None, "00"),
+    country!("HT", "509", &[8], None, "00"),
+    country!("BO", "591", &[8], Some("0"), "00"),
+    country!("EC", "593", &[8, 9], Some("0"), "00"),
+    country!("PY", "595", &[9], Some("0"), "00"),
+    country!("UY", "598", &[8], Some("0"), "00"),
+    country!("HK", "852", &[8], None, "001"),
+    country!("MO", "853", &[8], None, "00"),
+    country!("KH", "855", &[8, 9], Some("0"), "001"),
+    country!("BD", "880", &[10], Some("0"), "00"),
+    country!("TW", "886", &[8, 9], Some("0"), "002"),
+    country!("LB", "961", &[7, 8], Some("0"), "00"),
+    country!("JO", "962", &[8, 9], Some("0"), "00"),
+    country!("SY", "963", &[8, 9], Some("0"), "00"),
+    country!("IQ", "964", &[8, 9, 10], Some("0"), "00"),
+    country!("KW", "965", &[8], None, "00"),
+    country!("SA", "966", &[9], Some("0"), "00"),
+    country!("OM", "968", &[8], None, "00"),
+    country!("PS", "970", &[8, 9], Some("0"), "00"),
+    country!("AE", "971", &[8, 9], Some("0"), "00"),
+    country!("IL", "972", &[8, 9], Some("0"), "00"),
+    country!("BH", "973", &[8], None, "00"),
+    country!("QA", "974", &[8], None, "00"),
+    country!("NP", "977", &[8, 10], Some("0"), "00"),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PhoneNumber {
+    pub e164: String,
+    pub region: &'static str,
+}
+
+pub fn country_by_region(region: &str) -> Option<&'static Country> {
+    COUNTRIES.iter().find(|c| c.region.eq_ignore_ascii_case(region))
+}
+
+// Longest calling code that prefixes `digits`, preferring `home` when it shares that code
+fn country_by_calling_code(digits: &str, home: &'static Country) -> Option<&'static Country> {
+    (1..=3).rev()
+        .filter(|&len| len <= digits.len())
+        .find_map(|len| {
+            let code = &digits[..len];
+            if home.calling_code == code {
+                Some(home)
+            } else {
+                COUNTRIES.iter().find(|c| c.calling_code == code)
+            }
+        })
+}
+
+// Picks the longest run of whole digit groups (starting at `skip` digits in) whose
+// national part has a valid length for `country`; returns the number and groups used.
+fn longest_valid(groups: &[&str], skip: usize, country: &'static Country) -> Option<(PhoneNumber, usize)> {
+    let digits: String = groups.concat();
+    let mut end = 0;
+    let mut best = None;
+
+    for (i, group) in groups.iter().enumerate() {
+        end += group.len();
+        if end <= skip {
+            continue;
+        }
+        if country.national_lengths.contains(&(end - skip)) {
+            best = Some((end, i + 1));
+        }
+    }
+
+    best.map(|(end, used)| {
+        let number = PhoneNumber {
+            e164: format!("+{}{}", country.calling_code, &digits[skip..end]),
+            region: country.region,
+        };
+        (number, used)
+    })
+}
+
+// Digits following a "+", split on separators
+pub fn parse_international(groups: &[&str], home: &'static Country) -> Option<(PhoneNumber, usize)> {
+    let digits: String = groups.concat();
+    let country = country_by_calling_code(&digits, home)?;
+    let cc_len = country.calling_code.len();
+
+    // "+44 (0)20 ..." - a trunk prefix written as its own group after the calling code
+    if let Some(trunk) = country.trunk_prefix {
+        if groups[0].len() == cc_len && groups.get(1) == Some(&trunk) {
+            let mut rest = vec![groups[0]];
+            rest.extend_from_slice(&groups[2..]);
+            if let Some((number, used)) = longest_valid(&rest, cc_len, country) {
+                return Some((number, used + 1));
+            }
+        }
+    }
+
+    longest_valid(groups, cc_len, country)
+}
+
+// Digits dialled with an international access code ("00", or the home region's, e.g. "011")
+pub fn parse_with_idd(groups: &[&str], home: &'static Country) -> Option<(PhoneNumber, usize)> {
+    let idd = [home.idd_prefix, "00"]
+        .into_iter()
+        .find(|idd| groups[0].starts_with(idd))?;
+
+    let remainder = &groups[0][idd.len()..];
+    if remainder.is_empty() {
+        let (number, used) = parse_international(groups.get(1..).filter(|g| !g.is_empty())?, home)?;
+        Some((number, used + 1))
+    } else {
+        let mut rest = vec![remainder];
+        rest.extend_from_slice(&groups[1..]);
+        parse_international(&rest, home)
+    }
+}
+
+// A number written in the national format of `country`, starting with its trunk prefix
+pub fn parse_national(groups: &[&str], country: &'static Country) -> Option<(PhoneNumber, usize)> {
+    let trunk = country.trunk_prefix?;
+    if !groups[0].starts_with(trunk) {
+        return None;
+    }
+
+    // Short trailing groups are usually neighbouring columns (durations, dates)
+    let groups = match groups.iter().skip(1).position(|g| g.len() < 2) {
+        Some(pos) => &groups[..pos + 1],
+        None => groups,
+    };
+
+    longest_valid(groups, trunk.len(), country)
+}
+
+// A separator-free digit run that may carry a calling code but no "+"
+pub fn parse_bare_international(digits: &str, home: &'static Country) -> Option<PhoneNumber> {
+    if !(11..=15).contains(&digits.len()) || looks_like_timestamp(digits) {
+        return None;
+    }
+
+    let country = country_by_calling_code(digits, home)?;
+    let national_len = digits.len() - country.calling_code.len();
+    if country.national_lengths.contains(&national_len) {
+        Some(PhoneNumber {
+            e164: format!("+{}", digits),
+            region: country.region,
+        })
+    } else {
+        None
+    }
+}
+
+// Compact YYYYMMDD[hhmm...] stamps are common in CDR exports and would otherwise
+// pass as numbers with a short calling code such as +20.
+fn looks_like_timestamp(digits: &str) -> bool {
+    let field = |range: std::ops::Range<usize>| digits.get(range).and_then(|s| s.parse::<u32>().ok());
+
+    matches!(
+        (field(0..4), field(4..6), field(6..8)),
+        (Some(1970..=2099), Some(1..=12), Some(1..=31))
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn home(region: &str) -> &'static Country {
+        country_by_region(region).unwrap()
+    }
+
+    #[test]
+    fn international_numbers_take_the_longest_valid_run() {
+        let (number, used) = parse_international(&["44", "20", "7946", "0958", "12"], home("US")).unwrap();
+        assert_eq!(number, PhoneNumber { e164: "+442079460958".to_string(), region: "GB" });
+        assert_eq!(used, 4);
+        assert_eq!(parse_international(&["44", "0", "20", "7946", "0958"], home("US")).unwrap().0.e164, "+442079460958");
+        assert_eq!(parse_international(&["1", "202", "555", "0143"], home("CA")).unwrap().0.region, "CA");
+        assert_eq!(parse_international(&["44", "20"], home("US")), None);
+    }
+
+    #[test]
+    fn access_codes_lead_into_international_numbers() {
+        assert_eq!(parse_with_idd(&["011", "33", "1", "23", "45", "67", "89"], home("US")).unwrap().0.e164, "+33123456789");
+        assert_eq!(parse_with_idd(&["0033123456789"], home("US")).unwrap().0.e164, "+33123456789");
+        assert_eq!(parse_with_idd(&["020", "7946", "0958"], home("GB")), None);
+    }
+
+    #[test]
+    fn national_numbers_need_the_trunk_prefix() {
+        let (number, used) = parse_national(&["020", "7946", "0958", "5"], home("GB")).unwrap();
+        assert_eq!(number.e164, "+442079460958");
+        assert_eq!(used, 3);
+        assert_eq!(parse_national(&["20", "7946", "0958"], home("GB")), None);
+        assert_eq!(parse_national(&["2025550143"], home("HK")), None);
+    }
+
+    #[test]
+    fn bare_runs_must_fit_a_country_and_not_be_a_date() {
+        assert_eq!(parse_bare_international("442079460958", home("US")).unwrap().region, "GB");
+        assert_eq!(parse_bare_international("12025550143", home("US")).unwrap().e164, "+12025550143");
+        assert_eq!(parse_bare_international("20240115134500", home("US")), None);
+        assert_eq!(parse_bare_international("4420794", home("US")), None);
+        assert_eq!(parse_bare_international("44207946095812", home("US")), None);
+    }
+}
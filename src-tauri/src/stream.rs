@@ -0,0 +1,308 @@
+// Bounded-memory reading of text files: decoded lines and CSV records, a chunk at a time
+use std::io::{ErrorKind, Read};
+use crate::encoding::{StreamDecoder, TextEncoding};
+use crate::error::AppError;
+use crate::models::SourceRecord;
+
+// Bytes read from a file at a time; also the longest line or quoted CSV record kept whole
+pub const CHUNK_SIZE: usize = 1024 * 1024;
+
+// One physical line of decoded text, without its "\n"
+pub struct Line {
+    pub number: i64,
+    // Byte offset of the line's start in the file, BOM included
+    pub offset: i64,
+    pub text: String,
+}
+
+pub struct TextLines<R> {
+    reader: R,
+    encoding: TextEncoding,
+    decoder: StreamDecoder,
+    // The first bytes read, where a BOM would be
+    start: Vec<u8>,
+    chunk: Vec<u8>,
+    // Decoded text not yet returned starts at `position`
+    buffer: String,
+    position: usize,
+    eof: bool,
+    bytes_read: u64,
+    line_number: i64,
+    offset: i64,
+}
+
+impl<R: Read> TextLines<R> {
+    pub fn new(reader: R, encoding: TextEncoding) -> Self {
+        Self {
+            reader,
+            encoding,
+            decoder: encoding.stream_decoder(),
+            start: Vec::new(),
+            chunk: vec![0; CHUNK_SIZE],
+            buffer: String::new(),
+            position: 0,
+            eof: false,
+            bytes_read: 0,
+            line_number: 1,
+            offset: 0,
+        }
+    }
+
+    // Undecoded bytes consumed from the reader so far
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes_read
+    }
+
+    // A line longer than CHUNK_SIZE comes back in pieces that share its line number. Pieces
+    // end after the last whitespace that fits, so a value is only split when nothing
+    // separates it for a whole chunk
+    pub fn next_line(&mut self) -> Result<Option<Line>, AppError> {
+        loop {
+            if let Some(i) = self.buffer[self.position..].find('\n') {
+                return Ok(Some(self.take(self.position + i, true)));
+            }
+
+            let pending = self.buffer.len() - self.position;
+            if pending >= CHUNK_SIZE {
+                let mut end = self.position + CHUNK_SIZE;
+                while !self.buffer.is_char_boundary(end) {
+                    end -= 1;
+                }
+                if let Some((i, c)) = self.buffer[self.position..end].char_indices().rfind(|(_, c)| c.is_whitespace()) {
+                    end = self.position + i + c.len_utf8();
+                }
+                return Ok(Some(self.take(end, false)));
+            }
+
+            if self.eof {
+                return Ok((pending > 0).then(|| self.take(self.buffer.len(), false)));
+            }
+
+            self.buffer.drain(..self.position);
+            self.position = 0;
+
+            let read = match self.reader.read(&mut self.chunk) {
+                Ok(read) => read,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            };
+            self.bytes_read += read as u64;
+            self.eof = read == 0;
+            let wanted = 3_usize.saturating_sub(self.start.len()).min(read);
+            self.start.extend_from_slice(&self.chunk[..wanted]);
+            self.decoder.decode(&self.chunk[..read], self.eof, &mut self.buffer);
+        }
+    }
+
+    fn take(&mut self, end: usize, line_break: bool) -> Line {
+        // Decoded text only follows a BOM once all of it has been read
+        if self.offset == 0 {
+            self.offset = self.encoding.bom_len(&self.start) as i64;
+        }
+        let line = Line {
+            number: self.line_number,
+            offset: self.offset,
+            text: self.buffer[self.position..end].to_string(),
+        };
+
+        let consumed = end - self.position + line_break as usize;
+        self.offset += self.encoding.encoded_len(&self.buffer[self.position..self.position + consumed]) as i64;
+        self.position += consumed;
+        if line_break {
+            self.line_number += 1;
+        }
+
+        line
+    }
+
+    // The next non-blank line as a record
+    pub fn next_record(&mut self) -> Result<Option<SourceRecord>, AppError> {
+        while let Some(line) = self.next_line()? {
+            let row = line.text.trim_end_matches('\r');
+            if !row.trim().is_empty() {
+                return Ok(Some(SourceRecord {
+                    line_number: line.number,
+                    byte_offset: Some(line.offset),
+                    sheet_name: None,
+                    page_number: None,
+                    text: row.to_string(),
+                    event: None,
+                    contact: None,
+                }));
+            }
+        }
+        Ok(None)
+    }
+
+    // The next CSV record: its raw text, which spans lines while a quoted field is open,
+    // and its unquoted fields. Empty lines are skipped. A quote still open after CHUNK_SIZE
+    // bytes is taken as a stray one and the record ends at that line.
+    pub fn next_csv_row(&mut self) -> Result<Option<CsvRow>, AppError> {
+        let first = loop {
+            match self.next_line()? {
+                Some(line) if line.text.trim_end_matches('\r').is_empty() => continue,
+                Some(line) => break line,
+                None => return Ok(None),
+            }
+        };
+
+        let mut fields = CsvFields::default();
+        fields.scan(&first.text);
+        let mut raw = first.text;
+
+        while fields.in_quotes && raw.len() < CHUNK_SIZE {
+            let Some(line) = self.next_line()? else { break };
+            fields.field.push('\n');
+            fields.scan(&line.text);
+            raw.push('\n');
+            raw.push_str(&line.text);
+        }
+
+        Ok(Some(CsvRow {
+            record: SourceRecord {
+                line_number: first.number,
+                byte_offset: Some(first.offset),
+                sheet_name: None,
+                page_number: None,
+                text: raw.trim_end_matches(['\r', '\n']).to_string(),
+                event: None,
+                contact: None,
+            },
+            fields: fields.finish(),
+        }))
+    }
+}
+
+pub struct CsvRow {
+    pub record: SourceRecord,
+    pub fields: Vec<String>,
+}
+
+// Comma-separated fields as the csv crate reads them: a quote opens a quoted field only
+// at the field's start, and "" inside one is a literal quote
+#[derive(Default)]
+struct CsvFields {
+    fields: Vec<String>,
+    field: String,
+    in_quotes: bool,
+    // Nothing but an opening quote has been seen for the current field
+    started: bool,
+}
+
+impl CsvFields {
+    fn scan(&mut self, line: &str) {
+        let mut chars = line.chars().peekable();
+        while let Some(c) = chars.next() {
+            if self.in_quotes {
+                if c != '"' {
+                    self.field.push(c);
+                } else if chars.peek() == Some(&'"') {
+                    chars.next();
+                    self.field.push('"');
+                } else {
+                    self.in_quotes = false;
+                }
+            } else if c == ',' {
+                self.fields.push(std::mem::take(&mut self.field));
+                self.started = false;
+            } else if c == '"' && !self.started {
+                self.in_quotes = true;
+                self.started = true;
+            } else {
+                self.field.push(c);
+                self.started = true;
+            }
+        }
+
+        // The "\r" of a CRLF ends the record; inside quotes it is part of the field
+        if !self.in_quotes && self.field.ends_with('\r') {
+            self.field.pop();
+        }
+    }
+
+    fn finish(mut self) -> Vec<String> {
+        self.fields.push(self.field);
+        self.fields
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Hands out one byte per read, so every character and line straddles reads
+    struct Trickle<'a>(&'a [u8]);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let Some((first, rest)) = self.0.split_first() else { return Ok(0) };
+            buf[0] = *first;
+            self.0 = rest;
+            Ok(1)
+        }
+    }
+
+    #[test]
+    fn records_skip_blank_lines_and_keep_offsets() {
+        let text = "Zoë,202-555-0143\r\n\r\n  \nlast line";
+        let mut lines = TextLines::new(Trickle(text.as_bytes()), TextEncoding::Utf8);
+        let mut records = Vec::new();
+        while let Some(record) = lines.next_record().unwrap() {
+            records.push((record.line_number, record.byte_offset, record.text));
+        }
+        assert_eq!(records, [(1, Some(0), "Zoë,202-555-0143".to_string()), (4, Some(24), "last line".to_string())]);
+        assert_eq!(lines.bytes_read(), text.len() as u64);
+    }
+
+    #[test]
+    fn long_lines_come_back_in_pieces() {
+        let text = format!("{}\nnext", "é".repeat(CHUNK_SIZE / 2 + 1));
+        let mut lines = TextLines::new(text.as_bytes(), TextEncoding::Utf8);
+        let first = lines.next_line().unwrap().unwrap();
+        let second = lines.next_line().unwrap().unwrap();
+        assert_eq!((first.number, second.number), (1, 1));
+        assert_eq!(first.text.len() + second.text.len(), CHUNK_SIZE + 2);
+        assert_eq!(lines.next_line().unwrap().unwrap().text, "next");
+    }
+
+    #[test]
+    fn long_lines_break_at_whitespace_before_the_limit() {
+        let filler = "x".repeat(CHUNK_SIZE - 8);
+        let text = format!("{} 202-555-0143 called", filler);
+        let mut lines = TextLines::new(text.as_bytes(), TextEncoding::Utf8);
+        let first = lines.next_line().unwrap().unwrap();
+        let second = lines.next_line().unwrap().unwrap();
+        assert_eq!(first.text, format!("{} ", filler));
+        assert_eq!((second.number, second.offset, second.text.as_str()), (1, CHUNK_SIZE as i64 - 7, "202-555-0143 called"));
+        assert!(lines.next_line().unwrap().is_none());
+    }
+
+    #[test]
+    fn csv_rows_follow_quoted_fields_across_lines() {
+        let text = "Name,Note\r\n\"Doe, John\",\"said \"\"hi\"\"\r\non two lines\"\r\n\r\nplain,6\" tall\r\n";
+        let mut lines = TextLines::new(Trickle(text.as_bytes()), TextEncoding::Utf8);
+        let mut rows = Vec::new();
+        while let Some(row) = lines.next_csv_row().unwrap() {
+            rows.push((row.record.line_number, row.fields));
+        }
+        assert_eq!(rows, [
+            (1, vec!["Name".to_string(), "Note".to_string()]),
+            (2, vec!["Doe, John".to_string(), "said \"hi\"\r\non two lines".to_string()]),
+            (5, vec!["plain".to_string(), "6\" tall".to_string()]),
+        ]);
+    }
+
+    #[test]
+    fn decodes_utf16_as_it_streams() {
+        let bytes: Vec<u8> = [0xFEFF_u16].into_iter().chain("a,b\nc,d".encode_utf16()).flat_map(u16::to_le_bytes).collect();
+        let mut lines = TextLines::new(Trickle(&bytes), TextEncoding::Utf16Le);
+        let first = lines.next_csv_row().unwrap().unwrap();
+        assert_eq!(first.fields, ["a", "b"]);
+        let second = lines.next_csv_row().unwrap().unwrap();
+        assert_eq!(second.record.text, "c,d");
+        assert!(lines.next_csv_row().unwrap().is_none());
+
+        // Offsets count the file's bytes: the BOM, then two bytes a character
+        assert_eq!((first.record.byte_offset, second.record.byte_offset), (Some(2), Some(10)));
+    }
+}